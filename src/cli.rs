@@ -1,11 +1,16 @@
 use std::fmt;
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow::{Ok, Result, bail};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::config::Config;
+use crate::dict::DCustom;
+use crate::expr::Expr;
 use crate::lang::{Edition, EditionSpec, Lang};
 use crate::models::kaikki::WordEntry;
 use crate::path::{DictionaryType, PathManager};
@@ -40,14 +45,38 @@ pub enum Command {
     /// Phonetic transcription dictionary. Uses all editions
     IpaMerged(IpaMergedArgs),
 
+    /// Dictionary described by a spec file (TOML or JSON). Uses target for the edition
+    Custom(CustomArgs),
+
+    /// Hyphenation exceptions of a language, as a plain list or for TeX. Uses target for the edition
+    Hyphenation(HyphenationArgs),
+
     /// Download a Kaikki jsonlines
     Download(MainArgs),
 
+    /// Split a Kaikki jsonlines into smaller per-language jsonlines
+    Filter(FilterArgs),
+
     /// Show supported iso codes, with coloured editions
     Iso(IsoArgs),
 
     /// Build a release with all dictionaries
     Release(ReleaseArgs),
+
+    /// Build the dictionaries listed in a TOML manifest, or several types for one language pair
+    Build(BuildArgs),
+
+    /// Look up a word in a built main dictionary
+    Lookup(LookupArgs),
+
+    /// Serve built dictionaries over a local HTTP lookup API
+    Serve(ServeArgs),
+
+    /// Manage the testsuite fixtures
+    Fixture(FixtureArgs),
+
+    /// Inspect the configuration file
+    Config(ConfigArgs),
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -63,7 +92,120 @@ pub struct MainArgs {
     pub options: Options,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
+pub struct HyphenationArgs {
+    #[command(flatten)]
+    pub langs: MainLangs,
+
+    /// Dictionary name
+    #[arg(default_value_t)]
+    pub dict_name: DictName,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    pub format: HyphenationFormat,
+
+    #[command(flatten)]
+    pub options: HyphenationOptions,
+}
+
+/// The options of `wty hyphenation`: those that read and select the entries.
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+pub struct HyphenationOptions {
+    /// Redownload kaikki files
+    #[arg(long, short)]
+    pub redownload: bool,
+
+    /// Stream input directly from Kaikki instead of writing raw jsonl files to disk
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Read this jsonlines instead of kaikki's, plain or compressed (gz, zst, xz). `-` reads stdin
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stream", "redownload"])]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<PathBuf>,
+
+    /// Write the hyphenations to stdout
+    #[arg(long = "stdout", requires = "quiet")]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub output_stdout: bool,
+
+    /// Only keep the first n filtered lines. -1 keeps all
+    #[arg(long, default_value_t = -1)]
+    pub first: i32,
+
+    /// Skip up to n malformed lines instead of aborting. They are written to a quarantine file
+    #[arg(long, default_value_t = 0)]
+    pub max_errors: usize,
+
+    /// Only keep entries matching certain key–value filters
+    #[arg(long, value_parser = parse_tuple)]
+    #[serde(
+        serialize_with = "serialize_filters",
+        deserialize_with = "deserialize_filters"
+    )]
+    pub filter: Vec<(FilterKey, String)>,
+
+    /// Only keep entries not matching certain key–value filters
+    #[arg(long, value_parser = parse_tuple)]
+    #[serde(
+        serialize_with = "serialize_filters",
+        deserialize_with = "deserialize_filters"
+    )]
+    pub reject: Vec<(FilterKey, String)>,
+
+    /// Only keep entries matching an expression
+    #[arg(long = "where", value_name = "EXPR")]
+    #[serde(
+        serialize_with = "serialize_exprs",
+        deserialize_with = "deserialize_exprs"
+    )]
+    pub r#where: Vec<Expr>,
+
+    /// Do not print anything to the console
+    #[arg(long, short)]
+    pub quiet: bool,
+
+    /// Name the file after the experimental dictionary
+    #[arg(short, long)]
+    pub experimental: bool,
+
+    /// Change the root directory
+    #[arg(long, default_value = "data")]
+    pub root_dir: PathBuf,
+}
+
+impl From<HyphenationOptions> for Options {
+    fn from(opts: HyphenationOptions) -> Self {
+        Self {
+            redownload: opts.redownload,
+            stream: opts.stream,
+            input: opts.input,
+            output_stdout: opts.output_stdout,
+            first: opts.first,
+            max_errors: opts.max_errors,
+            filter: opts.filter,
+            reject: opts.reject,
+            r#where: opts.r#where,
+            quiet: opts.quiet,
+            experimental: opts.experimental,
+            root_dir: opts.root_dir,
+            ..Default::default()
+        }
+    }
+}
+
+/// Output format of `wty hyphenation`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum HyphenationFormat {
+    /// One hyphenated word per line: "ge-gen-über"
+    #[default]
+    Plain,
+    /// A `\hyphenation{...}` block of exceptions, to load in TeX
+    Tex,
+}
+
+#[derive(Parser, Debug, Clone)]
 pub struct GlossaryArgs {
     #[command(flatten)]
     pub langs: GlossaryLangs,
@@ -76,7 +218,7 @@ pub struct GlossaryArgs {
     pub options: Options,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 pub struct GlossaryExtendedArgs {
     #[command(flatten)]
     pub langs: GlossaryExtendedLangs,
@@ -89,7 +231,24 @@ pub struct GlossaryExtendedArgs {
     pub options: Options,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
+pub struct CustomArgs {
+    /// Spec of the dictionary (cf. `src/dict/custom.rs`)
+    #[arg(value_parser = parse_spec)]
+    pub spec: DCustom,
+
+    #[command(flatten)]
+    pub langs: MainLangs,
+
+    /// Dictionary name
+    #[arg(default_value_t)]
+    pub dict_name: DictName,
+
+    #[command(flatten)]
+    pub options: Options,
+}
+
+#[derive(Parser, Debug, Clone)]
 pub struct IpaArgs {
     #[command(flatten)]
     pub langs: MainLangs,
@@ -102,7 +261,7 @@ pub struct IpaArgs {
     pub options: Options,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 pub struct IpaMergedArgs {
     #[command(flatten)]
     pub langs: IpaMergedLangs,
@@ -115,11 +274,269 @@ pub struct IpaMergedArgs {
     pub options: Options,
 }
 
+#[derive(Parser, Debug)]
+pub struct LookupArgs {
+    #[command(flatten)]
+    pub langs: MainLangs,
+
+    /// Word to look up
+    pub word: String,
+
+    /// Dictionary name
+    #[arg(default_value_t)]
+    pub dict_name: DictName,
+
+    /// Do not colour the output
+    #[arg(long)]
+    pub no_color: bool,
+
+    #[command(flatten)]
+    pub options: LookupOptions,
+}
+
+/// The options of `wty lookup`: those that locate the dictionary.
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+pub struct LookupOptions {
+    /// Read the temporary files of `--save-temps` instead of the zip
+    #[arg(long, short)]
+    pub save_temps: bool,
+
+    /// Look up the experimental dictionary
+    #[arg(short, long)]
+    pub experimental: bool,
+
+    /// Change the root directory
+    #[arg(long, default_value = "data")]
+    pub root_dir: PathBuf,
+}
+
+impl From<LookupOptions> for Options {
+    fn from(opts: LookupOptions) -> Self {
+        Self {
+            save_temps: opts.save_temps,
+            experimental: opts.experimental,
+            root_dir: opts.root_dir,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct FilterArgs {
+    /// Edition to split
+    pub edition: Edition,
+
+    /// Source languages to keep. Each one is written to its own jsonlines
+    #[arg(required = true)]
+    pub sources: Vec<Lang>,
+
+    #[command(flatten)]
+    pub options: FilterOptions,
+}
+
+/// The options of `wty filter`: those that read and select the entries.
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+pub struct FilterOptions {
+    /// Redownload kaikki files
+    #[arg(long, short)]
+    pub redownload: bool,
+
+    /// Stream input directly from Kaikki instead of writing raw jsonl files to disk
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Read this jsonlines instead of kaikki's, plain or compressed (gz, zst, xz). `-` reads stdin
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stream", "redownload"])]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<PathBuf>,
+
+    /// Skip up to n malformed lines instead of aborting. They are written to a quarantine file
+    #[arg(long, default_value_t = 0)]
+    pub max_errors: usize,
+
+    /// Only keep entries matching certain key–value filters
+    #[arg(long, value_parser = parse_tuple)]
+    #[serde(
+        serialize_with = "serialize_filters",
+        deserialize_with = "deserialize_filters"
+    )]
+    pub filter: Vec<(FilterKey, String)>,
+
+    /// Only keep entries not matching certain key–value filters
+    #[arg(long, value_parser = parse_tuple)]
+    #[serde(
+        serialize_with = "serialize_filters",
+        deserialize_with = "deserialize_filters"
+    )]
+    pub reject: Vec<(FilterKey, String)>,
+
+    /// Only keep entries matching an expression
+    #[arg(long = "where", value_name = "EXPR")]
+    #[serde(
+        serialize_with = "serialize_exprs",
+        deserialize_with = "deserialize_exprs"
+    )]
+    pub r#where: Vec<Expr>,
+
+    /// Do not print anything to the console
+    #[arg(long, short)]
+    pub quiet: bool,
+
+    /// Change the root directory
+    #[arg(long, default_value = "data")]
+    pub root_dir: PathBuf,
+}
+
+impl From<FilterOptions> for Options {
+    fn from(opts: FilterOptions) -> Self {
+        Self {
+            redownload: opts.redownload,
+            stream: opts.stream,
+            input: opts.input,
+            max_errors: opts.max_errors,
+            filter: opts.filter,
+            reject: opts.reject,
+            r#where: opts.r#where,
+            quiet: opts.quiet,
+            root_dir: opts.root_dir,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ServeArgs {
+    /// Dictionaries to serve: zips, or directories of term banks (cf. `--save-temps`)
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Port to listen on
+    #[arg(long, default_value_t = 8787)]
+    pub port: u16,
+
+    /// Address to bind to
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct FixtureArgs {
+    #[command(subcommand)]
+    pub command: FixtureCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FixtureCommand {
+    /// Add a word from a Kaikki jsonlines to the testsuite
+    Add(FixtureAddArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct FixtureAddArgs {
+    #[command(flatten)]
+    pub langs: MainLangs,
+
+    /// Word to add
+    pub word: String,
+
+    /// Only add entries with this part of speech
+    #[arg(long)]
+    pub pos: Option<String>,
+
+    /// Only add entries with this language code. Defaults to the source language
+    #[arg(long)]
+    pub lang_code: Option<String>,
+
+    /// Regenerate the snapshot of this language pair afterwards
+    #[arg(long)]
+    pub snapshot: bool,
+
+    /// Testsuite directory
+    #[arg(long, default_value = "tests")]
+    pub tests_dir: PathBuf,
+
+    #[command(flatten)]
+    pub options: FixtureOptions,
+}
+
+/// The options of `wty fixture add`: those that locate the Kaikki jsonlines.
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureOptions {
+    /// Redownload kaikki files
+    #[arg(long, short)]
+    pub redownload: bool,
+
+    /// Do not print anything to the console
+    #[arg(long, short)]
+    pub quiet: bool,
+
+    /// Change the root directory, where the Kaikki jsonlines are looked for (and downloaded to)
+    #[arg(long, default_value = "data")]
+    pub root_dir: PathBuf,
+}
+
+impl From<FixtureOptions> for Options {
+    fn from(opts: FixtureOptions) -> Self {
+        Self {
+            redownload: opts.redownload,
+            quiet: opts.quiet,
+            root_dir: opts.root_dir,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct BuildArgs {
+    /// Path to the manifest. With `--types`, the source and target languages instead
+    #[arg(required = true, num_args = 1..=2, value_name = "MANIFEST | SOURCE TARGET")]
+    pub inputs: Vec<String>,
+
+    // Example:
+    //   `wty build de en --types main,ipa`
+    //
+    /// Build these dictionary types for a language pair, reading their input once
+    #[arg(long, value_delimiter = ',')]
+    pub types: Vec<BuildDictType>,
+
+    // With a manifest, these go underneath its `[defaults]`.
+    #[command(flatten)]
+    pub options: Options,
+}
+
+/// Dictionary types that `wty build` can build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuildDictType {
+    Main,
+    Glossary,
+    GlossaryExtended,
+    Ipa,
+    IpaMerged,
+    Custom,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective configuration of every dictionary type
+    Show,
+}
+
 #[derive(Parser, Debug)]
 pub struct ReleaseArgs {
     /// Change the root directory
     #[arg(long, default_value = "data")]
     pub root_dir: PathBuf,
+
+    /// Skip up to n malformed lines per edition instead of aborting
+    #[arg(long, default_value_t = 0)]
+    pub max_errors: usize,
 }
 
 #[derive(Parser, Debug, Default)]
@@ -169,8 +586,11 @@ pub struct IpaMergedLangs {
     pub target: Lang,
 }
 
+// The serde attributes are for the config file and the manifest (cf. `OptionsOverlay`), where
+// options are written as in the command line: `memory_limit = "4G"`, `filter = ["pos,noun"]`...
 #[expect(clippy::struct_excessive_bools)]
-#[derive(Parser, Debug, Default, Clone)]
+#[derive(Parser, Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Options {
     /// Write temporary files to disk and skip zipping
     #[arg(long, short)]
@@ -184,14 +604,37 @@ pub struct Options {
     #[arg(long)]
     pub stream: bool,
 
+    // Examples:
+    //   `--input de-extract.jsonl.zst`
+    //   `wiktwords ... | wty main de de --input -`
+    //
+    /// Read this jsonlines instead of kaikki's, plain or compressed (gz, zst, xz). `-` reads stdin
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stream", "redownload"])]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<PathBuf>,
+
     /// Write the generated Yomitan zip archive to stdout
     #[arg(long = "stdout", requires = "quiet", conflicts_with = "save_temps")]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub output_stdout: bool,
 
     /// Only keep the first n filtered lines. -1 keeps all
     #[arg(long, default_value_t = -1)]
     pub first: i32,
 
+    /// Skip up to n malformed lines instead of aborting. They are written to a quarantine file
+    #[arg(long, default_value_t = 0)]
+    pub max_errors: usize,
+
+    // Example:
+    //   `--memory-limit 4G`
+    //
+    /// Spill the intermediate representation to disk past this size (main dictionary)
+    #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_size")]
+    pub memory_limit: Option<usize>,
+
     // Example:
     //   `--filter pos,adv`
     //
@@ -200,6 +643,10 @@ pub struct Options {
     //
     /// Only keep entries matching certain key–value filters
     #[arg(long, value_parser = parse_tuple)]
+    #[serde(
+        serialize_with = "serialize_filters",
+        deserialize_with = "deserialize_filters"
+    )]
     pub filter: Vec<(FilterKey, String)>,
 
     // Example:
@@ -210,8 +657,65 @@ pub struct Options {
     //
     /// Only keep entries not matching certain key–value filters
     #[arg(long, value_parser = parse_tuple)]
+    #[serde(
+        serialize_with = "serialize_filters",
+        deserialize_with = "deserialize_filters"
+    )]
     pub reject: Vec<(FilterKey, String)>,
 
+    // Example:
+    //   `--where "pos in [noun, verb] and sense_topics == medicine"`
+    //
+    // You can specify this option multiple times: all expressions must hold.
+    // See `src/expr.rs` for the syntax.
+    //
+    /// Only keep entries matching an expression
+    #[arg(long = "where", value_name = "EXPR")]
+    #[serde(
+        serialize_with = "serialize_exprs",
+        deserialize_with = "deserialize_exprs"
+    )]
+    pub r#where: Vec<Expr>,
+
+    // Example:
+    //   `--drop-sense-tags obsolete,archaic,rare`
+    //
+    /// Drop senses with any of these tags (main dictionary)
+    #[arg(long, value_delimiter = ',', value_name = "TAGS")]
+    pub drop_sense_tags: Vec<String>,
+
+    /// Drop senses with any of these topics (main dictionary)
+    #[arg(long, value_delimiter = ',', value_name = "TOPICS")]
+    pub drop_sense_topics: Vec<String>,
+
+    /// Only keep senses with one of these topics (main dictionary)
+    #[arg(long, value_delimiter = ',', value_name = "TOPICS")]
+    pub keep_sense_topics: Vec<String>,
+
+    // Example:
+    //   `--keep-sense-categories Medicine,Pharmacology`
+    //
+    // A category matches in full ("German irregular verbs"), or after its language prefix
+    // ("Medicine" matches "en:Medicine").
+    //
+    /// Drop senses with any of these categories (main dictionary)
+    #[arg(long, value_delimiter = ',', value_name = "CATEGORIES")]
+    pub drop_sense_categories: Vec<String>,
+
+    /// Only keep senses with one of these categories (main dictionary)
+    #[arg(long, value_delimiter = ',', value_name = "CATEGORIES")]
+    pub keep_sense_categories: Vec<String>,
+
+    /// Show some families of categories, such as strong or irregular verbs, as tags (main dictionary)
+    ///
+    /// Only category names of the English edition are recognized: other editions add no tags.
+    #[arg(long)]
+    pub category_tags: bool,
+
+    /// Add descendants trees, mostly useful for historical languages like la or grc (main dictionary)
+    #[arg(long)]
+    pub descendants: bool,
+
     /// Do not print anything to the console
     #[arg(long, short)]
     pub quiet: bool,
@@ -220,6 +724,14 @@ pub struct Options {
     #[arg(short, long)]
     pub pretty: bool,
 
+    // Deflate's default (6) if not given.
+    //
+    /// Compression level of the zip, from 0 (store only, fastest) to 9 (smallest)
+    #[arg(long, value_name = "LEVEL", value_parser = parse_compression_level)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_compression_level")]
+    pub compression_level: Option<u8>,
+
     /// Skip converting to yomitan (to speed up testing)
     #[arg(long)]
     pub skip_yomitan: bool,
@@ -228,6 +740,12 @@ pub struct Options {
     #[arg(short, long)]
     pub experimental: bool,
 
+    // The date comes from SOURCE_DATE_EPOCH if set, else from the dataset (kaikki's Last-Modified).
+    //
+    /// Make byte-identical zips for the same input: the revision and timestamps are fixed
+    #[arg(long)]
+    pub reproducible: bool,
+
     /// Change the root directory
     #[arg(long, default_value = "data")]
     pub root_dir: PathBuf,
@@ -265,7 +783,105 @@ impl Deref for DictName {
     }
 }
 
-fn parse_tuple(s: &str) -> Result<(FilterKey, String), String> {
+/// Parse a size in bytes, with an optional binary suffix: `512M`, `4G`, `4GiB`...
+pub(crate) fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+    let number: usize = number
+        .parse()
+        .map_err(|_| format!("invalid size '{s}': expected a number, like 512M or 4G"))?;
+    let shift = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("invalid size unit in '{s}': expected K, M, G or T")),
+    };
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size '{s}' is too large"))
+}
+
+fn parse_spec(s: &str) -> Result<DCustom, String> {
+    DCustom::load(Path::new(s)).map_err(|err| format!("{err:#}"))
+}
+
+fn parse_compression_level(s: &str) -> Result<u8, String> {
+    let level = s
+        .parse()
+        .map_err(|_| format!("invalid compression level '{s}'"))?;
+    check_compression_level(level)
+}
+
+/// Deflate levels go from 1 to 9, and 0 stores files uncompressed.
+pub(crate) fn check_compression_level(level: u8) -> Result<u8, String> {
+    (level <= 9)
+        .then_some(level)
+        .ok_or_else(|| format!("compression level {level} is not between 0 and 9"))
+}
+
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(usize),
+        Human(String),
+    }
+
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Result::Ok(Some(bytes)),
+        Size::Human(s) => parse_size(&s).map(Some).map_err(D::Error::custom),
+    }
+}
+
+fn deserialize_compression_level<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u8>, D::Error> {
+    let level = u8::deserialize(deserializer)?;
+    check_compression_level(level)
+        .map(Some)
+        .map_err(D::Error::custom)
+}
+
+fn serialize_filters<S: Serializer>(
+    filters: &[(FilterKey, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+        filters
+            .iter()
+            .map(|(key, value)| format!("{},{value}", key.as_str())),
+    )
+}
+
+fn deserialize_filters<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(FilterKey, String)>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|filter| parse_tuple(filter).map_err(D::Error::custom))
+        .collect()
+}
+
+fn serialize_exprs<S: Serializer>(exprs: &[Expr], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(exprs.iter().map(ToString::to_string))
+}
+
+fn deserialize_exprs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Expr>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|expr| expr.parse().map_err(D::Error::custom))
+        .collect()
+}
+
+pub(crate) fn parse_tuple(s: &str) -> Result<(FilterKey, String), String> {
     let parts: Vec<_> = s.split(',').map(|x| x.trim().to_string()).collect();
     if parts.len() != 2 {
         return Err("expected two comma-separated values".into());
@@ -290,6 +906,14 @@ impl FilterKey {
         }
     }
 
+    const fn as_str(&self) -> &'static str {
+        match self {
+            Self::LangCode => "lang_code",
+            Self::Word => "word",
+            Self::Pos => "pos",
+        }
+    }
+
     fn try_from(s: &str) -> Result<Self> {
         match s {
             "lang_code" => Ok(Self::LangCode),
@@ -328,8 +952,25 @@ fn err_on_source_being_target(source: &Lang, target: &Lang) -> Result<()> {
 }
 
 impl Cli {
-    pub fn parse_cli() -> Self {
-        Self::parse()
+    /// Parse the command line, filling what was not given with the configuration file.
+    pub fn parse_cli() -> Result<Self> {
+        let matches = Self::command().get_matches();
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+        // A broken config file should not get in the way of commands that do not read it.
+        if cli.command.takes_options() {
+            Config::load()?.apply(&mut cli.command, &matches)?;
+        }
+        Ok(cli)
+    }
+}
+
+impl Command {
+    /// Whether the command takes options that the config file can set.
+    const fn takes_options(&self) -> bool {
+        !matches!(
+            self,
+            Self::Iso(_) | Self::Release(_) | Self::Serve(_) | Self::Config(_)
+        )
     }
 }
 
@@ -409,7 +1050,7 @@ macro_rules! impl_try_into_pathmanager {
                     dict_ty: $dict_ty,
                     dict_name: args.dict_name,
                     langs: args.langs.try_into()?,
-                    opts: args.options,
+                    opts: args.options.into(),
                 })
             }
         }
@@ -421,6 +1062,21 @@ impl_try_into_pathmanager!(GlossaryArgs, DictionaryType::Glossary);
 impl_try_into_pathmanager!(GlossaryExtendedArgs, DictionaryType::GlossaryExtended);
 impl_try_into_pathmanager!(IpaArgs, DictionaryType::Ipa);
 impl_try_into_pathmanager!(IpaMergedArgs, DictionaryType::IpaMerged);
+impl_try_into_pathmanager!(LookupArgs, DictionaryType::Main);
+impl_try_into_pathmanager!(HyphenationArgs, DictionaryType::Main);
+
+impl TryFrom<CustomArgs> for PathManager {
+    type Error = anyhow::Error;
+
+    fn try_from(args: CustomArgs) -> Result<Self> {
+        Ok(Self {
+            dict_ty: DictionaryType::Custom(args.spec.name()),
+            dict_name: args.dict_name,
+            langs: args.langs.try_into()?,
+            opts: args.options,
+        })
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -432,6 +1088,16 @@ mod tests {
         assert!(Cli::try_parse_from(["wty", "glossary", "el", "en"]).is_ok());
     }
 
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_size("4G").unwrap(), 4 << 30);
+        assert_eq!(parse_size("4gib").unwrap(), 4 << 30);
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("4X").is_err());
+    }
+
     #[test]
     fn main_needs_target_edition() {
         assert!(Cli::try_parse_from(["wty", "main", "grc", "el"]).is_ok());
@@ -470,6 +1136,85 @@ mod tests {
         assert!(MainArgs::try_parse_from(["_pname", "el", "el", "--reject", "pos,name"]).is_ok());
     }
 
+    #[test]
+    fn lookup_takes_a_word() {
+        assert!(Cli::try_parse_from(["wty", "lookup", "de", "en", "gepflogen"]).is_ok());
+        assert!(Cli::try_parse_from(["wty", "lookup", "de", "en"]).is_err());
+
+        // Only the options that locate the dictionary
+        let args = [
+            "wty",
+            "lookup",
+            "de",
+            "en",
+            "gepflogen",
+            "--save-temps",
+            "--root-dir",
+            "x",
+        ];
+        assert!(Cli::try_parse_from(args).is_ok());
+        let args = [
+            "wty",
+            "lookup",
+            "de",
+            "en",
+            "gepflogen",
+            "--memory-limit",
+            "2G",
+        ];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn hyphenation_takes_only_reading_options() {
+        let args = [
+            "wty",
+            "hyphenation",
+            "de",
+            "en",
+            "--first",
+            "10",
+            "--where",
+            "pos == noun",
+        ];
+        let Command::Hyphenation(args) = Cli::try_parse_from(args).unwrap().command else {
+            panic!("not a hyphenation command");
+        };
+        let opts = Options::from(args.options);
+        assert_eq!(opts.first, 10);
+        assert_eq!(opts.r#where.len(), 1);
+
+        let args = ["wty", "hyphenation", "de", "en", "--save-temps"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn serve_requires_a_dictionary() {
+        assert!(Cli::try_parse_from(["wty", "serve"]).is_err());
+        assert!(Cli::try_parse_from(["wty", "serve", "a.zip", "b.zip", "--port", "0"]).is_ok());
+    }
+
+    #[test]
+    fn filter_takes_several_languages() {
+        let cli = Cli::try_parse_from(["wty", "filter", "en", "de", "fr", "--reject", "pos,name"]);
+        assert!(cli.is_ok());
+        assert!(Cli::try_parse_from(["wty", "filter", "en"]).is_err());
+        // Options of the builders are not taken
+        for option in ["--memory-limit=1", "--pretty", "--compression-level=1"] {
+            assert!(Cli::try_parse_from(["wty", "filter", "en", "de", option]).is_err());
+        }
+    }
+
+    #[test]
+    fn fixture_add_takes_a_word() {
+        let cli =
+            Cli::try_parse_from(["wty", "fixture", "add", "de", "en", "faul", "--pos", "adj"]);
+        assert!(cli.is_ok());
+        assert!(Cli::try_parse_from(["wty", "fixture", "add", "de", "en"]).is_err());
+        let args = ["wty", "fixture", "add", "de", "en", "faul", "--pretty"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
     #[test]
     fn stdout_requires_quiet() {
         assert!(MainArgs::try_parse_from(["_pname", "el", "el", "--stdout"]).is_err());
@@ -0,0 +1,502 @@
+//! User configuration file with default options.
+//!
+//! Read from `$XDG_CONFIG_HOME/wty/config.toml` (or `~/.config/wty/config.toml`), then from
+//! `wty.toml` in the working directory, which takes precedence. Flags given in the command line
+//! always win.
+//!
+//! ```toml
+//! root_dir = "/mnt/wty"
+//! experimental = true
+//!
+//! # Only for the main dictionary (and `wty lookup`)
+//! [main]
+//! dict_name = "mydict"
+//! reject = ["pos,name"]
+//! drop_sense_tags = ["obsolete", "archaic"]
+//! ```
+//!
+//! Sections are named after the dictionary subcommands: `main`, `glossary`, `glossary-extended`,
+//! `ipa`, `ipa-merged` and `custom`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Ok, Result, bail};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, Parser};
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::Map;
+use crate::cli::{Cli, Command, ConfigArgs, ConfigCommand, DictName, FixtureCommand, Options};
+
+/// Dictionary types that can have their own section.
+pub const SECTIONS: [&str; 6] = [
+    "main",
+    "glossary",
+    "glossary-extended",
+    "ipa",
+    "ipa-merged",
+    "custom",
+];
+
+/// Options that only make sense in the command line.
+const CLI_ONLY: [&str; 2] = ["input", "output_stdout"];
+
+/// Some fields of `Options`, written as in the command line: `memory_limit = "4G"`.
+///
+/// Used by the config file and by the manifest of `wty build`. Names are checked when parsing,
+/// but values only when applied, so that a bad value only breaks the commands that take it.
+#[derive(Debug, Default, Clone)]
+pub struct OptionsOverlay(toml::Table);
+
+impl<'de> Deserialize<'de> for OptionsOverlay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = toml::Table::deserialize(deserializer)?;
+        if let Some(key) = table.keys().find(|key| !is_option(key)) {
+            return Err(D::Error::custom(format!("unknown option '{key}'")));
+        }
+        Result::Ok(Self(table))
+    }
+}
+
+fn is_option(key: &str) -> bool {
+    !CLI_ONLY.contains(&key)
+        && Options::command()
+            .get_arguments()
+            .any(|arg| arg.get_id() == key)
+}
+
+impl OptionsOverlay {
+    /// Fill the missing options of `self` with those of `other`.
+    pub fn or(mut self, other: Self) -> Self {
+        for (key, value) in other.0 {
+            self.0.entry(key).or_insert(value);
+        }
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&toml::Value> {
+        self.0.get(key)
+    }
+
+    /// Overwrite the fields of `opts` with the options for which `keep` holds.
+    ///
+    /// `opts` can be any struct whose fields are named and (de)serialized as those of `Options`.
+    pub fn apply<T: Serialize + DeserializeOwned>(
+        &self,
+        opts: &mut T,
+        keep: impl Fn(&str) -> bool,
+    ) -> Result<()> {
+        let mut table = toml::Table::try_from(&*opts)?;
+        for (key, value) in &self.0 {
+            if keep(key) {
+                table.insert(key.clone(), value.clone());
+                // One at a time, to tell which option is wrong
+                T::deserialize(toml::Value::Table(table.clone()))
+                    .with_context(|| format!("invalid option '{key}'"))?;
+            }
+        }
+        *opts = T::deserialize(toml::Value::Table(table))?;
+        Ok(())
+    }
+}
+
+/// Defaults for `Options` and `DictName`. Unset fields fall back to the CLI defaults.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ConfigOptions {
+    pub dict_name: Option<String>,
+    #[serde(flatten)]
+    pub options: OptionsOverlay,
+}
+
+impl ConfigOptions {
+    /// Fill the missing fields of `self` with those of `other`.
+    fn or(self, other: Self) -> Self {
+        Self {
+            dict_name: self.dict_name.or(other.dict_name),
+            options: self.options.or(other.options),
+        }
+    }
+
+    /// Overwrite every value of `opts` (and `dict_name`) that was not given in the command line.
+    ///
+    /// Options that `command` does not take are left alone.
+    fn apply<T: Serialize + DeserializeOwned>(
+        &self,
+        opts: &mut T,
+        dict_name: Option<&mut DictName>,
+        command: &clap::Command,
+        matches: &ArgMatches,
+    ) -> Result<()> {
+        let takes = |id: &str| command.get_arguments().any(|arg| arg.get_id() == id);
+        let from_cli = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+
+        self.options
+            .apply(opts, |key| takes(key) && !from_cli(key))
+            .context("invalid config")?;
+
+        if let Some(dict_name) = dict_name
+            && let Some(value) = &self.dict_name
+            && !from_cli("dict_name")
+        {
+            *dict_name = value.parse()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Files that were read, from lowest to highest precedence.
+    pub paths: Vec<PathBuf>,
+    pub global: ConfigOptions,
+    pub sections: Map<String, ConfigOptions>,
+}
+
+impl Config {
+    /// Load and merge every config file found. Returns an empty config if there is none.
+    pub fn load() -> Result<Self> {
+        let mut config = Self::default();
+        for path in config_paths() {
+            if path.exists() {
+                config.merge(Self::read(&path)?);
+            }
+        }
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut config = Self::parse(&content)
+            .with_context(|| format!("Failed to parse config @ {}", path.display()))?;
+        config.paths.push(path.to_path_buf());
+        Ok(config)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(content)?;
+
+        let mut sections = Map::default();
+        for section in SECTIONS {
+            if let Some(value) = table.remove(section) {
+                let options = value
+                    .try_into()
+                    .with_context(|| format!("invalid section [{section}]"))?;
+                sections.insert(section.to_string(), options);
+            }
+        }
+        if let Some(key) = table.keys().find(|key| table[*key].is_table()) {
+            bail!(
+                "unknown section [{key}]. Choose between: {}",
+                SECTIONS.join(" | ")
+            );
+        }
+
+        Ok(Self {
+            paths: Vec::new(),
+            global: table.try_into()?,
+            sections,
+        })
+    }
+
+    /// Merge `other` on top of `self`.
+    fn merge(&mut self, other: Self) {
+        self.paths.extend(other.paths);
+        self.global = other.global.or(std::mem::take(&mut self.global));
+        for (section, options) in other.sections {
+            let previous = self.sections.shift_remove(&section).unwrap_or_default();
+            self.sections.insert(section, options.or(previous));
+        }
+    }
+
+    /// Options for a section, falling back to the global ones.
+    pub fn effective(&self, section: Option<&str>) -> ConfigOptions {
+        let section = section
+            .and_then(|section| self.sections.get(section))
+            .cloned()
+            .unwrap_or_default();
+        section.or(self.global.clone())
+    }
+
+    /// Apply the config to the parsed command, leaving alone the values given in the CLI.
+    pub fn apply(&self, command: &mut Command, matches: &ArgMatches) -> Result<()> {
+        let Some((name, matches)) = matches.subcommand() else {
+            return Ok(());
+        };
+        let section = match name {
+            "main" | "download" | "lookup" | "hyphenation" => Some("main"),
+            name if SECTIONS.contains(&name) => Some(name),
+            _ => None,
+        };
+        let config = self.effective(section);
+        let cli = Cli::command();
+        let Some(subcommand) = cli.find_subcommand(name) else {
+            return Ok(());
+        };
+
+        match command {
+            Command::Main(args) | Command::Download(args) => config.apply(
+                &mut args.options,
+                Some(&mut args.dict_name),
+                subcommand,
+                matches,
+            ),
+            Command::Glossary(args) => config.apply(
+                &mut args.options,
+                Some(&mut args.dict_name),
+                subcommand,
+                matches,
+            ),
+            Command::GlossaryExtended(args) => config.apply(
+                &mut args.options,
+                Some(&mut args.dict_name),
+                subcommand,
+                matches,
+            ),
+            Command::Ipa(args) => config.apply(
+                &mut args.options,
+                Some(&mut args.dict_name),
+                subcommand,
+                matches,
+            ),
+            Command::IpaMerged(args) => config.apply(
+                &mut args.options,
+                Some(&mut args.dict_name),
+                subcommand,
+                matches,
+            ),
+            Command::Custom(args) => config.apply(
+                &mut args.options,
+                Some(&mut args.dict_name),
+                subcommand,
+                matches,
+            ),
+            Command::Lookup(args) => config.apply(
+                &mut args.options,
+                Some(&mut args.dict_name),
+                subcommand,
+                matches,
+            ),
+            Command::Hyphenation(args) => config.apply(
+                &mut args.options,
+                Some(&mut args.dict_name),
+                subcommand,
+                matches,
+            ),
+            Command::Filter(args) => config.apply(&mut args.options, None, subcommand, matches),
+            Command::Build(args) => config.apply(&mut args.options, None, subcommand, matches),
+            Command::Fixture(args) => match (&mut args.command, matches.subcommand()) {
+                (FixtureCommand::Add(args), Some((name, matches))) => {
+                    let Some(subcommand) = subcommand.find_subcommand(name) else {
+                        return Ok(());
+                    };
+                    config.apply(&mut args.options, None, subcommand, matches)
+                }
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Candidate config files, from lowest to highest precedence.
+fn config_paths() -> Vec<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    let mut paths = Vec::new();
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join("wty").join("config.toml"));
+    }
+    paths.push(PathBuf::from("wty.toml"));
+    paths
+}
+
+pub fn config(args: ConfigArgs) -> Result<()> {
+    match args.command {
+        ConfigCommand::Show => {
+            let config = Config::load()?;
+            print!("{}", show(&config)?);
+            Ok(())
+        }
+    }
+}
+
+/// The effective configuration of every section, as TOML.
+fn show(config: &Config) -> Result<String> {
+    let mut out = String::new();
+
+    if config.paths.is_empty() {
+        out.push_str("# No config file found. Showing defaults\n");
+    } else {
+        for path in &config.paths {
+            out.push_str(&format!("# Loaded {}\n", path.display()));
+        }
+    }
+
+    for section in SECTIONS {
+        let effective = config.effective(Some(section));
+        let mut opts = Options::parse_from(["wty"]);
+        effective
+            .options
+            .apply(&mut opts, |_| true)
+            .with_context(|| format!("invalid section [{section}]"))?;
+        let dict_name = effective
+            .dict_name
+            .unwrap_or_else(|| DictName::default().to_string());
+
+        out.push_str(&format!("\n[{section}]\n"));
+        out.push_str(&format!("dict_name = {}\n", toml::Value::from(dict_name)));
+        out.push_str(&toml::to_string(&opts)?);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::{CommandFactory, FromArgMatches};
+
+    use crate::cli::Cli;
+
+    fn parse_with_config(config: &str, args: &[&str]) -> Command {
+        let config = Config::parse(config).unwrap();
+        let matches = Cli::command().try_get_matches_from(args).unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        config.apply(&mut cli.command, &matches).unwrap();
+        cli.command
+    }
+
+    const CONFIG: &str = r#"
+        root_dir = "elsewhere"
+        experimental = true
+
+        [main]
+        dict_name = "mine"
+        reject = ["pos,name"]
+    "#;
+
+    #[test]
+    fn config_supplies_defaults() {
+        let Command::Main(args) = parse_with_config(CONFIG, &["wty", "main", "de", "en"]) else {
+            unreachable!()
+        };
+        assert_eq!(args.options.root_dir, PathBuf::from("elsewhere"));
+        assert!(args.options.experimental);
+        assert_eq!(args.options.reject.len(), 1);
+        assert_eq!(&*args.dict_name, "mine");
+
+        // Sections only apply to their dictionary type
+        let Command::Ipa(args) = parse_with_config(CONFIG, &["wty", "ipa", "de", "en"]) else {
+            unreachable!()
+        };
+        assert_eq!(args.options.root_dir, PathBuf::from("elsewhere"));
+        assert!(args.options.reject.is_empty());
+        assert_eq!(&*args.dict_name, "wty");
+    }
+
+    #[test]
+    fn cli_overrides_config() {
+        let args = ["wty", "main", "de", "en", "other", "--root-dir", "here"];
+        let Command::Main(args) = parse_with_config(CONFIG, &args) else {
+            unreachable!()
+        };
+        assert_eq!(args.options.root_dir, PathBuf::from("here"));
+        assert_eq!(&*args.dict_name, "other");
+        assert!(args.options.experimental);
+    }
+
+    #[test]
+    fn config_where_expressions() {
+        let config = "where = [\"pos == noun\"]";
+        let Command::Main(args) = parse_with_config(config, &["wty", "main", "de", "en"]) else {
+            unreachable!()
+        };
+        assert_eq!(args.options.r#where[0].to_string(), "pos == noun");
+
+        let args = ["wty", "main", "de", "en", "--where", "pos == verb"];
+        let Command::Main(args) = parse_with_config(config, &args) else {
+            unreachable!()
+        };
+        assert_eq!(args.options.r#where.len(), 1);
+        assert_eq!(args.options.r#where[0].to_string(), "pos == verb");
+
+        let config = Config::parse("where = [\"pos ==\"]").unwrap();
+        let matches = Cli::command().get_matches_from(["wty", "main", "de", "en"]);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        assert!(config.apply(&mut cli.command, &matches).is_err());
+    }
+
+    #[test]
+    fn later_files_take_precedence() {
+        let mut config = Config::parse(CONFIG).unwrap();
+        config.merge(Config::parse("root_dir = \"local\"\n[main]\npretty = true").unwrap());
+
+        let main = config.effective(Some("main"));
+        assert_eq!(main.options.get("root_dir"), Some(&"local".into()));
+        assert_eq!(main.dict_name.as_deref(), Some("mine"));
+        assert_eq!(main.options.get("pretty"), Some(&true.into()));
+        assert_eq!(main.options.get("experimental"), Some(&true.into()));
+    }
+
+    #[test]
+    fn invalid_configs() {
+        assert!(Config::parse("experimentl = true").is_err());
+        assert!(Config::parse("[nope]\npretty = true").is_err());
+        assert!(Config::parse("[main]\nword = \"a\"").is_err());
+        assert!(Config::parse("input = \"de.jsonl\"").is_err());
+        assert!(Config::parse("help = true").is_err());
+    }
+
+    #[test]
+    fn bad_values_only_break_commands_that_take_them() {
+        let config = Config::parse("memory_limit = \"lots\"\nroot_dir = \"elsewhere\"").unwrap();
+
+        let matches = Cli::command().get_matches_from(["wty", "lookup", "de", "en", "word"]);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        config.apply(&mut cli.command, &matches).unwrap();
+        let Command::Lookup(args) = cli.command else {
+            unreachable!()
+        };
+        assert_eq!(args.options.root_dir, PathBuf::from("elsewhere"));
+
+        let matches = Cli::command().get_matches_from(["wty", "main", "de", "en"]);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        let err = config.apply(&mut cli.command, &matches).unwrap_err();
+        assert!(format!("{err:#}").contains("invalid option 'memory_limit'"));
+    }
+
+    #[test]
+    fn show_merges_defaults() {
+        let shown = show(&Config::parse(CONFIG).unwrap()).unwrap();
+        assert!(shown.starts_with("# No config file found"));
+
+        let section = |name: &str| {
+            let start = shown.find(&format!("[{name}]\n")).unwrap();
+            let end = shown[start + 1..]
+                .find("\n[")
+                .map_or(shown.len(), |end| start + 1 + end);
+            &shown[start..end]
+        };
+        let main = section("main");
+        assert!(main.starts_with("[main]\ndict_name = \"mine\"\n"));
+        assert!(main.contains("\nroot_dir = \"elsewhere\"\n"));
+        assert!(main.contains("\nreject = [\"pos,name\"]\n"));
+        assert!(main.contains("\nfirst = -1\n"));
+        let ipa = section("ipa");
+        assert!(ipa.starts_with("[ipa]\ndict_name = \"wty\"\n"));
+        assert!(ipa.contains("\nroot_dir = \"elsewhere\"\n"));
+        assert!(ipa.contains("\nreject = []\n"));
+    }
+}
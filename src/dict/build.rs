@@ -0,0 +1,722 @@
+//! Build a batch of dictionaries from a TOML manifest.
+//!
+//! ```toml
+//! root_dir = "data"   # optional, defaults to --root-dir
+//! jobs = 4            # optional, defaults to the number of cores
+//!
+//! # Options shared by every dictionary, overridden per dictionary. Options given in the command
+//! # line (`wty build manifest.toml --max-errors 10`) go underneath these.
+//! [defaults]
+//! experimental = true
+//!
+//! [[dict]]
+//! type = "main"
+//! source = "de"
+//! target = "en"
+//! name = "wty-nouns"
+//! filter = ["pos,noun"]
+//! where = ["not sense_tags == obsolete"]
+//!
+//! [[dict]]
+//! type = "glossary-extended"
+//! edition = "all"
+//! source = "de"
+//! target = "fr"
+//!
+//! [[dict]]
+//! type = "custom"
+//! spec = "specs/nouns.toml"   # cf. `custom.rs`
+//! source = "de"
+//! target = "en"
+//! ```
+//!
+//! Jsonlines are downloaded once, before building, and shared among dictionaries.
+//!
+//! Alternatively, `wty build de en --types main,ipa,glossary` builds several types of dictionaries
+//! for one language pair. Those that read the same jsonlines (here main and ipa, from the English
+//! edition) are made from a single pass over it.
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use rayon::ThreadPoolBuilder;
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::Set;
+use crate::cli::{
+    BuildArgs, BuildDictType, CustomArgs, DictName, GlossaryArgs, GlossaryExtendedArgs,
+    GlossaryExtendedLangs, GlossaryLangs, IpaArgs, IpaMergedArgs, IpaMergedLangs, MainArgs,
+    MainLangs, Options,
+};
+use crate::config::OptionsOverlay;
+use crate::dict::{
+    Composite, DCustom, DGlossary, DGlossaryExtended, DIpa, DIpaMerged, DMain,
+    find_or_download_jsonl, make_dict,
+};
+use crate::lang::{Edition, Lang};
+use crate::path::{DictionaryType, PathManager};
+use crate::utils::{CHECK_C, human_size};
+
+const FAIL_C: &str = "✗";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    root_dir: Option<PathBuf>,
+    jobs: Option<usize>,
+    #[serde(default)]
+    defaults: ManifestEntry,
+    #[serde(rename = "dict", default)]
+    dicts: Vec<ManifestEntry>,
+}
+
+/// Either a `[[dict]]` or the `[defaults]` table. Every field can be given in both.
+#[derive(Debug, Deserialize, Default, Clone)]
+struct ManifestEntry {
+    #[serde(rename = "type")]
+    ty: Option<BuildDictType>,
+    edition: Option<String>,
+    source: Option<String>,
+    target: Option<String>,
+    name: Option<String>,
+    spec: Option<PathBuf>,
+
+    /// Any other field is an option, as in the config file.
+    #[serde(flatten)]
+    options: OptionsOverlay,
+}
+
+impl ManifestEntry {
+    /// Fill the missing fields of `self` with those of `defaults`.
+    fn or(self, defaults: &Self) -> Self {
+        let defaults = defaults.clone();
+        Self {
+            ty: self.ty.or(defaults.ty),
+            edition: self.edition.or(defaults.edition),
+            source: self.source.or(defaults.source),
+            target: self.target.or(defaults.target),
+            name: self.name.or(defaults.name),
+            spec: self.spec.or(defaults.spec),
+            options: self.options.or(defaults.options),
+        }
+    }
+
+    fn options(&self, base: &Options) -> Result<Options> {
+        let mut options = base.clone();
+        self.options.apply(&mut options, |_| true)?;
+        Ok(options)
+    }
+
+    /// `base` holds the options that the entry does not set.
+    fn to_job(&self, base: &Options) -> Result<BuildJob> {
+        let Some(ty) = self.ty else {
+            bail!("missing field 'type'");
+        };
+        let dict_name = match &self.name {
+            Some(name) => DictName::from_str(name)?,
+            None => DictName::default(),
+        };
+        let options = self.options(base)?;
+
+        let job = match ty {
+            BuildDictType::Main => BuildJob::Main(MainArgs {
+                langs: MainLangs {
+                    source: self.lang("source")?,
+                    target: self.lang("target")?,
+                },
+                dict_name,
+                options,
+            }),
+            BuildDictType::Glossary => BuildJob::Glossary(GlossaryArgs {
+                langs: GlossaryLangs {
+                    source: self.lang("source")?,
+                    target: self.lang("target")?,
+                },
+                dict_name,
+                options,
+            }),
+            BuildDictType::GlossaryExtended => BuildJob::GlossaryExtended(GlossaryExtendedArgs {
+                langs: GlossaryExtendedLangs {
+                    edition: self.lang("edition")?,
+                    source: self.lang("source")?,
+                    target: self.lang("target")?,
+                },
+                dict_name,
+                options,
+            }),
+            BuildDictType::Ipa => BuildJob::Ipa(IpaArgs {
+                langs: MainLangs {
+                    source: self.lang("source")?,
+                    target: self.lang("target")?,
+                },
+                dict_name,
+                options,
+            }),
+            BuildDictType::IpaMerged => BuildJob::IpaMerged(IpaMergedArgs {
+                langs: IpaMergedLangs {
+                    target: self.lang("target")?,
+                },
+                dict_name,
+                options,
+            }),
+            BuildDictType::Custom => {
+                let Some(spec) = &self.spec else {
+                    bail!("missing field 'spec'");
+                };
+                BuildJob::Custom(CustomArgs {
+                    spec: DCustom::load(spec)?,
+                    langs: MainLangs {
+                        source: self.lang("source")?,
+                        target: self.lang("target")?,
+                    },
+                    dict_name,
+                    options,
+                })
+            }
+        };
+
+        Ok(job)
+    }
+
+    /// Parse a language field into a `Lang`, `Edition` or `EditionSpec`.
+    fn lang<T: FromStr<Err = String>>(&self, field: &str) -> Result<T> {
+        let value = match field {
+            "edition" => &self.edition,
+            "source" => &self.source,
+            "target" => &self.target,
+            _ => unreachable!(),
+        };
+        let Some(value) = value else {
+            bail!("missing field '{field}'");
+        };
+        T::from_str(value).map_err(|err| anyhow::anyhow!("invalid field '{field}': {err}"))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum BuildJob {
+    Main(MainArgs),
+    Glossary(GlossaryArgs),
+    GlossaryExtended(GlossaryExtendedArgs),
+    Ipa(IpaArgs),
+    IpaMerged(IpaMergedArgs),
+    Custom(CustomArgs),
+}
+
+impl BuildJob {
+    fn path_manager(&self) -> Result<PathManager> {
+        match self.clone() {
+            Self::Main(args) => args.try_into(),
+            Self::Glossary(args) => args.try_into(),
+            Self::GlossaryExtended(args) => args.try_into(),
+            Self::Ipa(args) => args.try_into(),
+            Self::IpaMerged(args) => args.try_into(),
+            Self::Custom(args) => args.try_into(),
+        }
+    }
+
+    fn push_to(self, composite: &mut Composite) -> Result<()> {
+        match self {
+            Self::Main(args) => composite.push(DMain, args),
+            Self::Glossary(args) => composite.push(DGlossary, args),
+            Self::GlossaryExtended(args) => composite.push(DGlossaryExtended, args),
+            Self::Ipa(args) => composite.push(DIpa, args),
+            Self::IpaMerged(args) => composite.push(DIpaMerged, args),
+            Self::Custom(args) => composite.push(args.spec.clone(), args),
+        }
+    }
+
+    /// Returns the number of malformed lines that were skipped.
+    fn run(self) -> Result<usize> {
+        match self {
+            Self::Main(args) => make_dict(DMain, args),
+            Self::Glossary(args) => make_dict(DGlossary, args),
+            Self::GlossaryExtended(args) => make_dict(DGlossaryExtended, args),
+            Self::Ipa(args) => make_dict(DIpa, args),
+            Self::IpaMerged(args) => make_dict(DIpaMerged, args),
+            Self::Custom(args) => make_dict(args.spec.clone(), args),
+        }
+    }
+}
+
+struct BuildReport {
+    label: String,
+    output: PathBuf,
+    elapsed: Duration,
+    /// The number of malformed lines that were skipped, cf. `--max-errors`.
+    result: Result<usize>,
+}
+
+pub fn build(args: BuildArgs) -> Result<()> {
+    if !args.types.is_empty() {
+        return build_types(args);
+    }
+    let [manifest_path] = args.inputs.as_slice() else {
+        bail!("Expected a manifest, or a source and a target language with --types");
+    };
+    let manifest_path = PathBuf::from(manifest_path);
+
+    let content = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Failed to read manifest @ {}", manifest_path.display()))?;
+    let manifest: Manifest = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse manifest @ {}", manifest_path.display()))?;
+
+    let jobs = manifest_jobs(&manifest, &args.options)?;
+    if jobs.is_empty() {
+        bail!("No dictionaries found @ {}", manifest_path.display());
+    }
+
+    println!("Building {} dictionaries", jobs.len());
+
+    download_all(&jobs)?;
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(manifest.jobs.unwrap_or_default())
+        .build()?;
+
+    let start = Instant::now();
+    let reports: Vec<BuildReport> = pool.install(|| {
+        jobs.into_par_iter()
+            .map(|(label, job, pm)| {
+                let start = Instant::now();
+                let result = job.run();
+                let elapsed = start.elapsed();
+                match &result {
+                    Ok(_) => eprintln!("{label:<30} done in {elapsed:.2?}"),
+                    Err(err) => tracing::error!("[{label}] ERROR: {err:?}"),
+                }
+                BuildReport {
+                    label,
+                    output: output_path(&pm),
+                    elapsed,
+                    result,
+                }
+            })
+            .collect()
+    });
+
+    finish(&reports, start.elapsed())
+}
+
+/// Build several types of dictionaries for one language pair, grouped by input.
+fn build_types(args: BuildArgs) -> Result<()> {
+    let [source, target] = args.inputs.as_slice() else {
+        bail!("--types expects a source and a target language");
+    };
+
+    let mut jobs = Vec::new();
+    let mut seen = Vec::new();
+    for ty in args.types {
+        if seen.contains(&ty) {
+            continue;
+        }
+        seen.push(ty);
+        if ty == BuildDictType::GlossaryExtended {
+            bail!("glossary-extended needs an edition: use a manifest instead");
+        }
+        if ty == BuildDictType::Custom {
+            bail!("custom needs a spec: use a manifest instead");
+        }
+        let entry = ManifestEntry {
+            ty: Some(ty),
+            source: Some(source.clone()),
+            target: Some(target.clone()),
+            ..Default::default()
+        };
+        let job = entry.to_job(&args.options)?;
+        let pm = job.path_manager()?;
+        jobs.push((label(&pm), job, pm));
+    }
+
+    download_all(&jobs)?;
+
+    let mut groups: Vec<(Vec<(String, PathManager)>, Composite)> = Vec::new();
+    for (label, job, pm) in jobs {
+        let idx = match groups
+            .iter()
+            .position(|(_, composite)| composite.shares_input(&pm))
+        {
+            Some(idx) => idx,
+            None => {
+                groups.push((Vec::new(), Composite::new()));
+                groups.len() - 1
+            }
+        };
+        let (members, composite) = &mut groups[idx];
+        job.push_to(composite)?;
+        members.push((label, pm));
+    }
+
+    let start = Instant::now();
+    let mut reports = Vec::new();
+    for (members, composite) in groups {
+        let labels: Vec<_> = members.iter().map(|(label, _)| label.as_str()).collect();
+        let labels = labels.join(" ");
+
+        let start = Instant::now();
+        let result = composite.make();
+        let elapsed = start.elapsed();
+        match &result {
+            Ok(_) => eprintln!("{labels:<30} done in {elapsed:.2?}"),
+            Err(err) => tracing::error!("{labels} ERROR: {err:?}"),
+        }
+
+        for (label, pm) in members {
+            reports.push(BuildReport {
+                label,
+                output: output_path(&pm),
+                elapsed,
+                result: result
+                    .as_ref()
+                    .copied()
+                    .map_err(|err| anyhow::anyhow!("{err}")),
+            });
+        }
+    }
+
+    finish(&reports, start.elapsed())
+}
+
+fn output_path(pm: &PathManager) -> PathBuf {
+    if pm.opts.save_temps {
+        pm.dir_temp_dict()
+    } else {
+        pm.path_dict()
+    }
+}
+
+/// Print the summary, and fail if any dictionary did.
+fn finish(reports: &[BuildReport], elapsed: Duration) -> Result<()> {
+    print_summary(reports, elapsed);
+
+    let failed = reports.iter().filter(|r| r.result.is_err()).count();
+    if failed > 0 {
+        bail!("{failed} of {} dictionaries failed", reports.len());
+    }
+
+    Ok(())
+}
+
+/// `options` (from the command line) are the defaults of the `[defaults]` of the manifest.
+fn manifest_jobs(
+    manifest: &Manifest,
+    options: &Options,
+) -> Result<Vec<(String, BuildJob, PathManager)>> {
+    let base = Options {
+        // Builds run in parallel: their console output would be garbled.
+        quiet: true,
+        root_dir: manifest
+            .root_dir
+            .clone()
+            .unwrap_or_else(|| options.root_dir.clone()),
+        ..options.clone()
+    };
+
+    manifest
+        .dicts
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| {
+            let entry = entry.clone().or(&manifest.defaults);
+            let job = entry
+                .to_job(&base)
+                .with_context(|| format!("Invalid [[dict]] #{}", idx + 1))?;
+            let pm = job.path_manager()?;
+            Ok((label(&pm), job, pm))
+        })
+        .collect()
+}
+
+fn label(pm: &PathManager) -> String {
+    let (edition, source, target) = pm.langs();
+    match pm.dict_ty {
+        DictionaryType::GlossaryExtended => format!("[{}-{edition}-{source}-{target}]", pm.dict_ty),
+        _ => format!("[{}-{source}-{target}]", pm.dict_ty),
+    }
+}
+
+/// Download every jsonlines needed by the jobs, once, before building.
+///
+/// Done sequentially so that two jobs never download the same file at the same time.
+fn download_all(jobs: &[(String, BuildJob, PathManager)]) -> Result<()> {
+    let mut seen: Set<(Edition, Lang)> = Set::default();
+
+    for (_, _, pm) in jobs {
+        if pm.opts.stream || pm.opts.input.is_some() {
+            continue;
+        }
+        let (edition_pm, source_pm, _) = pm.langs();
+        for edition in edition_pm.variants() {
+            if !seen.insert((edition, source_pm)) {
+                continue;
+            }
+            fs::create_dir_all(pm.dir_kaik())?;
+            let mut pm = pm.clone();
+            pm.opts.quiet = false;
+            find_or_download_jsonl(edition, Some(source_pm), &pm)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_summary(reports: &[BuildReport], elapsed: Duration) {
+    println!();
+    println!("Summary");
+    for report in reports {
+        match &report.result {
+            Ok(skipped) => {
+                let size = fs::metadata(&report.output)
+                    .map(|md| human_size(md.len() as f64))
+                    .unwrap_or_default();
+                println!(
+                    "{CHECK_C} {:<30} {:>9.2?}  {} {size}{}",
+                    report.label,
+                    report.elapsed,
+                    report.output.display(),
+                    skipped_message(*skipped)
+                );
+            }
+            Err(err) => println!(
+                "{FAIL_C} {:<30} {:>9.2?}  {err}",
+                report.label, report.elapsed
+            ),
+        }
+    }
+    let ok = reports.iter().filter(|r| r.result.is_ok()).count();
+    println!(
+        "Built {ok} of {} dictionaries in {elapsed:.2?}",
+        reports.len()
+    );
+}
+
+/// `  (skipped 3 malformed lines)`, or nothing if there were none.
+pub(crate) fn skipped_message(skipped: usize) -> String {
+    if skipped == 0 {
+        String::new()
+    } else {
+        format!("  (skipped {skipped} malformed lines)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    use crate::utils::temp_dir;
+
+    fn parse(manifest: &str) -> Result<Vec<(String, BuildJob, PathManager)>> {
+        manifest_jobs(&toml::from_str(manifest)?, &Options::parse_from(["wty"]))
+    }
+
+    #[test]
+    fn manifest_defaults_are_overridden() {
+        let jobs = parse(
+            r#"
+            root_dir = "somewhere"
+
+            [defaults]
+            experimental = true
+            reject = ["pos,name"]
+            memory_limit = "1G"
+
+            [[dict]]
+            type = "main"
+            source = "de"
+            target = "en"
+            name = "nouns"
+            filter = ["pos,noun"]
+
+            [[dict]]
+            type = "ipa"
+            source = "de"
+            target = "en"
+            experimental = false
+
+            [[dict]]
+            type = "glossary-extended"
+            edition = "all"
+            source = "de"
+            target = "fr"
+            "#,
+        )
+        .unwrap();
+
+        let labels: Vec<_> = jobs.iter().map(|(label, _, _)| label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["[main-de-en]", "[ipa-de-en]", "[glossary-ext-all-de-fr]"]
+        );
+
+        let (_, _, main) = &jobs[0];
+        assert_eq!(&*main.dict_name, "nouns");
+        assert!(main.opts.experimental);
+        assert_eq!(main.opts.filter.len(), 1);
+        assert_eq!(main.opts.reject.len(), 1);
+        assert_eq!(main.opts.root_dir, PathBuf::from("somewhere"));
+        assert_eq!(main.opts.memory_limit, Some(1 << 30));
+
+        let (_, _, ipa) = &jobs[1];
+        assert_eq!(&*ipa.dict_name, "wty");
+        assert!(!ipa.opts.experimental);
+    }
+
+    #[test]
+    fn command_line_options_go_underneath_the_manifest() {
+        let manifest = r#"
+            [defaults]
+            max_errors = 5
+
+            [[dict]]
+            type = "main"
+            source = "de"
+            target = "en"
+
+            [[dict]]
+            type = "ipa"
+            source = "de"
+            target = "en"
+            max_errors = 1
+            "#;
+        let options = Options::parse_from([
+            "wty",
+            "--max-errors",
+            "9",
+            "--memory-limit",
+            "2G",
+            "--root-dir",
+            "here",
+        ]);
+        let jobs = manifest_jobs(&toml::from_str(manifest).unwrap(), &options).unwrap();
+
+        let (_, _, main) = &jobs[0];
+        assert_eq!(main.opts.max_errors, 5);
+        assert_eq!(main.opts.memory_limit, Some(2 << 30));
+        assert_eq!(main.opts.root_dir, PathBuf::from("here"));
+        let (_, _, ipa) = &jobs[1];
+        assert_eq!(ipa.opts.max_errors, 1);
+    }
+
+    #[test]
+    fn manifest_errors() {
+        let missing_target = "[[dict]]\ntype = \"main\"\nsource = \"de\"";
+        let err = parse(missing_target).unwrap_err();
+        assert!(format!("{err:#}").contains("missing field 'target'"));
+
+        let typo =
+            "[[dict]]\ntype = \"main\"\nsource = \"de\"\ntarget = \"en\"\nexperimentl = true";
+        assert!(parse(typo).is_err());
+
+        let bad_filter =
+            "[[dict]]\ntype = \"main\"\nsource = \"de\"\ntarget = \"en\"\nfilter = [\"nope\"]";
+        assert!(parse(bad_filter).is_err());
+
+        // Not an edition
+        let bad_target = "[[dict]]\ntype = \"main\"\nsource = \"de\"\ntarget = \"xx\"";
+        assert!(parse(bad_target).is_err());
+
+        let missing_spec = "[[dict]]\ntype = \"custom\"\nsource = \"de\"\ntarget = \"en\"";
+        let err = parse(missing_spec).unwrap_err();
+        assert!(format!("{err:#}").contains("missing field 'spec'"));
+    }
+
+    #[test]
+    fn build_from_manifest() {
+        let root = temp_dir("build");
+        fs::create_dir_all(root.join("kaikki")).unwrap();
+        fs::copy(
+            "tests/kaikki/de-en-extract.jsonl",
+            root.join("kaikki/de-en-extract.jsonl"),
+        )
+        .unwrap();
+
+        let spec_path = root.join("nouns.toml");
+        let spec =
+            "name = \"nouns\"\nselect = \"pos == noun\"\n[[definitions]]\nfrom = \"glosses\"";
+        fs::write(&spec_path, spec).unwrap();
+
+        let manifest_path = root.join("manifest.toml");
+        let manifest = format!(
+            r#"
+            root_dir = "{}"
+            jobs = 2
+
+            [[dict]]
+            type = "main"
+            source = "de"
+            target = "en"
+
+            [[dict]]
+            type = "ipa"
+            source = "de"
+            target = "en"
+
+            [[dict]]
+            type = "custom"
+            spec = "{}"
+            source = "de"
+            target = "en"
+            "#,
+            root.display(),
+            spec_path.display()
+        );
+        fs::write(&manifest_path, manifest).unwrap();
+
+        build(BuildArgs {
+            inputs: vec![manifest_path.display().to_string()],
+            types: Vec::new(),
+            options: Options::default(),
+        })
+        .unwrap();
+
+        assert!(root.join("dict/de/en/wty-de-en.zip").exists());
+        assert!(root.join("dict/de/en/wty-de-en-ipa.zip").exists());
+        assert!(root.join("dict/de/en/wty-de-en-nouns.zip").exists());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn build_types_in_one_pass() {
+        let root = temp_dir("build-types");
+        fs::create_dir_all(root.join("kaikki")).unwrap();
+        fs::copy(
+            "tests/kaikki/de-en-extract.jsonl",
+            root.join("kaikki/de-en-extract.jsonl"),
+        )
+        .unwrap();
+
+        build(BuildArgs {
+            inputs: vec!["de".to_string(), "en".to_string()],
+            types: vec![BuildDictType::Main, BuildDictType::Ipa, BuildDictType::Main],
+            options: Options {
+                quiet: true,
+                root_dir: root.clone(),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+
+        assert!(root.join("dict/de/en/wty-de-en.zip").exists());
+        assert!(root.join("dict/de/en/wty-de-en-ipa.zip").exists());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn build_types_needs_two_languages() {
+        let args = |inputs: &[&str], types| BuildArgs {
+            inputs: inputs.iter().map(ToString::to_string).collect(),
+            types,
+            options: Options::default(),
+        };
+        assert!(build(args(&["de"], vec![BuildDictType::Main])).is_err());
+        assert!(build(args(&["de", "en"], vec![BuildDictType::GlossaryExtended])).is_err());
+    }
+}
@@ -0,0 +1,485 @@
+//! Library entry point: make dictionaries from any reader, without touching the filesystem.
+//!
+//! ```no_run
+//! use std::{fs::File, io::{BufReader, Cursor}};
+//! use wty::{Converter, lang::{Edition, Lang}};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let converter = Converter::main(Lang::De, Edition::En)?.drop_sense_tags(["obsolete"]);
+//! let irs = converter.read_jsonl(BufReader::new(File::open("de-en-extract.jsonl")?))?;
+//! let zip = converter.write_zip(irs, Cursor::new(Vec::new()))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{BufRead, Seek, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use zip::ZipWriter;
+
+use crate::cli::{DictName, GlossaryLangs, LangSpecs, MainLangs, Options};
+use crate::dict::core::{Quarantine, Selection, for_each_valid_entry};
+use crate::dict::writer::{Stamp, write_yomitan_zip};
+use crate::dict::{DCustom, DGlossary, DIpa, DMain, Dictionary, Langs};
+use crate::expr::Expr;
+use crate::lang::{Edition, Lang};
+use crate::models::kaikki::WordEntry;
+use crate::models::yomitan::YomitanEntry;
+use crate::path::{DictionaryType, PathManager};
+
+/// Builder to make a dictionary of type `D` from jsonlines or `WordEntry`s.
+///
+/// Nothing is printed, downloaded or written to disk, except by `write_zip`, to the given writer.
+#[derive(Debug, Clone)]
+pub struct Converter<D> {
+    dict: D,
+    edition: Edition,
+    // Never used for paths: it carries the languages and options the dictionaries expect.
+    pm: PathManager,
+    /// Date of `write_zip`, in seconds since the Unix epoch, cf. `Self::reproducible`.
+    epoch: Option<i64>,
+}
+
+impl Converter<DMain> {
+    /// Main dictionary of `source` words, from the `edition` Wiktionary.
+    pub fn main(source: Lang, edition: Edition) -> Result<Self> {
+        let langs = MainLangs {
+            source,
+            target: edition,
+        };
+        Ok(Self::new(
+            DMain,
+            DictionaryType::Main,
+            edition,
+            langs.try_into()?,
+        ))
+    }
+}
+
+impl Converter<DGlossary> {
+    /// Glossary of `edition` words, translated to `target`.
+    pub fn glossary(edition: Edition, target: Lang) -> Result<Self> {
+        let langs = GlossaryLangs {
+            source: edition,
+            target,
+        };
+        Ok(Self::new(
+            DGlossary,
+            DictionaryType::Glossary,
+            edition,
+            langs.try_into()?,
+        ))
+    }
+}
+
+impl Converter<DIpa> {
+    /// IPA dictionary of `source` words, from the `edition` Wiktionary.
+    pub fn ipa(source: Lang, edition: Edition) -> Result<Self> {
+        let langs = MainLangs {
+            source,
+            target: edition,
+        };
+        Ok(Self::new(
+            DIpa,
+            DictionaryType::Ipa,
+            edition,
+            langs.try_into()?,
+        ))
+    }
+}
+
+impl Converter<DCustom> {
+    /// Dictionary described by `spec`, of `source` words, from the `edition` Wiktionary.
+    pub fn custom(spec: DCustom, source: Lang, edition: Edition) -> Result<Self> {
+        let langs = MainLangs {
+            source,
+            target: edition,
+        };
+        let dict_ty = DictionaryType::Custom(spec.name());
+        Ok(Self::new(spec, dict_ty, edition, langs.try_into()?))
+    }
+}
+
+impl<D: Dictionary> Converter<D> {
+    fn new(dict: D, dict_ty: DictionaryType, edition: Edition, langs: LangSpecs) -> Self {
+        Self {
+            dict,
+            edition,
+            pm: PathManager {
+                dict_ty,
+                dict_name: DictName::default(),
+                langs,
+                opts: Options {
+                    quiet: true,
+                    first: -1,
+                    ..Default::default()
+                },
+            },
+            epoch: None,
+        }
+    }
+
+    /// Name of the dictionary, in its index. Defaults to `wty`.
+    #[must_use]
+    pub fn dict_name(mut self, name: &str) -> Self {
+        let Ok(dict_name) = name.parse();
+        self.pm.dict_name = dict_name;
+        self
+    }
+
+    /// Only keep entries matching `expr` (cf. `--where`). Every expression must hold.
+    #[must_use]
+    pub fn filter(mut self, expr: Expr) -> Self {
+        self.pm.opts.r#where.push(expr);
+        self
+    }
+
+    /// Only keep the first `n` entries that pass the filters.
+    #[must_use]
+    pub fn first(mut self, n: usize) -> Self {
+        // `--first n` stops at the n-th entry, without keeping it
+        self.pm.opts.first = i32::try_from(n.saturating_add(1)).unwrap_or(i32::MAX);
+        self
+    }
+
+    /// Skip up to `n` malformed lines instead of failing.
+    #[must_use]
+    pub const fn max_errors(mut self, n: usize) -> Self {
+        self.pm.opts.max_errors = n;
+        self
+    }
+
+    /// Drop senses with any of these tags (main dictionary).
+    #[must_use]
+    pub fn drop_sense_tags<S: Into<String>>(mut self, tags: impl IntoIterator<Item = S>) -> Self {
+        self.pm.opts.drop_sense_tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Drop senses with any of these topics (main dictionary).
+    #[must_use]
+    pub fn drop_sense_topics<S: Into<String>>(
+        mut self,
+        topics: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.pm.opts.drop_sense_topics = topics.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only keep senses with one of these topics (main dictionary).
+    #[must_use]
+    pub fn keep_sense_topics<S: Into<String>>(
+        mut self,
+        topics: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.pm.opts.keep_sense_topics = topics.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Drop senses with any of these categories (main dictionary).
+    #[must_use]
+    pub fn drop_sense_categories<S: Into<String>>(
+        mut self,
+        categories: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.pm.opts.drop_sense_categories = categories.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only keep senses with one of these categories (main dictionary).
+    #[must_use]
+    pub fn keep_sense_categories<S: Into<String>>(
+        mut self,
+        categories: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.pm.opts.keep_sense_categories = categories.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Show some families of categories, such as irregular verbs, as tags (main dictionary).
+    ///
+    /// Only category names of the English edition are recognized.
+    #[must_use]
+    pub const fn category_tags(mut self, category_tags: bool) -> Self {
+        self.pm.opts.category_tags = category_tags;
+        self
+    }
+
+    /// Add descendants trees (main dictionary).
+    #[must_use]
+    pub const fn descendants(mut self, descendants: bool) -> Self {
+        self.pm.opts.descendants = descendants;
+        self
+    }
+
+    /// Include experimental features.
+    #[must_use]
+    pub const fn experimental(mut self, experimental: bool) -> Self {
+        self.pm.opts.experimental = experimental;
+        self
+    }
+
+    /// Write the term banks of `write_zip` with whitespace.
+    #[must_use]
+    pub const fn pretty(mut self, pretty: bool) -> Self {
+        self.pm.opts.pretty = pretty;
+        self
+    }
+
+    /// Compression level of `write_zip`, from 0 (store only) to 9. Defaults to Deflate's 6.
+    ///
+    /// Levels above 9 are clamped to 9.
+    #[must_use]
+    pub const fn compression_level(mut self, level: u8) -> Self {
+        let level = if level > 9 { 9 } else { level };
+        self.pm.opts.compression_level = Some(level);
+        self
+    }
+
+    /// Date the zip of `write_zip` with `epoch` (seconds since the Unix epoch) instead of now,
+    /// so that the same input always gives the same bytes.
+    #[must_use]
+    pub const fn reproducible(mut self, epoch: i64) -> Self {
+        self.epoch = Some(epoch);
+        self
+    }
+
+    fn stamp(&self) -> Result<Stamp> {
+        self.epoch.map_or_else(|| Ok(Stamp::now()), Stamp::at)
+    }
+
+    const fn langs(&self) -> Langs {
+        Langs {
+            edition: self.edition,
+            source: self.pm.langs.source,
+            target: self.pm.langs.target,
+        }
+    }
+
+    /// Make the intermediate representation from the jsonlines of `reader`.
+    pub fn read_jsonl<R: BufRead + Send>(&self, reader: R) -> Result<D::I> {
+        let opts = &self.pm.opts;
+        let langs = self.langs();
+        let probe = |lang_code: &str| lang_code == langs.source.as_ref();
+        let probe = self.dict.supports_probe().then_some(&probe as _);
+
+        let mut irs = D::I::default();
+        let mut quarantine = Quarantine::discarding(opts.max_errors);
+        let mut selection = Selection::default();
+
+        for_each_valid_entry(reader, probe, "<reader>", &mut quarantine, |_, entry| {
+            if selection.keeps(&entry, opts) {
+                self.add(langs, entry, &mut irs);
+            }
+            Ok(selection.flow())
+        })?;
+
+        quarantine.finish()?;
+        Ok(irs)
+    }
+
+    /// Make the intermediate representation from already deserialized entries.
+    pub fn read_entries(&self, entries: impl IntoIterator<Item = WordEntry>) -> D::I {
+        let opts = &self.pm.opts;
+        let langs = self.langs();
+        let mut irs = D::I::default();
+        let mut selection = Selection::default();
+
+        // Same selection as `read_jsonl`, where the probe skips entries before they are counted
+        for entry in entries {
+            if self.dict.supports_probe() && entry.lang_code != langs.source.as_ref() {
+                continue;
+            }
+            if selection.keeps(&entry, opts) {
+                self.add(langs, entry, &mut irs);
+            }
+            if selection.done() {
+                break;
+            }
+        }
+
+        irs
+    }
+
+    fn add(&self, langs: Langs, mut entry: WordEntry, irs: &mut D::I) {
+        if self.dict.keep_if(langs.source, &entry) {
+            self.dict.preprocess(langs, &mut entry, &self.pm.opts, irs);
+            self.dict.process(langs, &entry, irs);
+        }
+    }
+
+    /// Postprocess `irs` and convert it to Yomitan entries, term banks first.
+    pub fn to_yomitan(&self, mut irs: D::I) -> Result<impl Iterator<Item = Result<YomitanEntry>>> {
+        self.dict.postprocess(&mut irs);
+        let labelled = self.dict.to_yomitan(self.pm.langs, irs)?;
+        Ok(labelled.into_iter().flat_map(|labelled| labelled.entries))
+    }
+
+    /// Postprocess `irs` and write it as a Yomitan zip (index, styles, tags and banks) to
+    /// `writer`, which is returned.
+    pub fn write_zip<W: Write + Seek>(&self, mut irs: D::I, writer: W) -> Result<W> {
+        self.dict.postprocess(&mut irs);
+        let labelled = self.dict.to_yomitan(self.pm.langs, irs)?;
+
+        let mut zip = ZipWriter::new(writer);
+        write_yomitan_zip(
+            self.pm.langs.source,
+            self.pm.langs.target,
+            &self.pm.opts,
+            &self.pm,
+            &self.stamp()?,
+            labelled,
+            &mut 0,
+            Path::new("<writer>"),
+            &mut zip,
+        )?;
+        Ok(zip.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor, Read};
+
+    use super::*;
+
+    fn fixture() -> BufReader<std::fs::File> {
+        BufReader::new(std::fs::File::open("tests/kaikki/de-en-extract.jsonl").unwrap())
+    }
+
+    #[test]
+    fn jsonl_and_entries_agree() {
+        let converter = Converter::main(Lang::De, Edition::En).unwrap();
+        let from_jsonl: Vec<_> = converter
+            .to_yomitan(converter.read_jsonl(fixture()).unwrap())
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert!(!from_jsonl.is_empty());
+
+        let entries = fixture()
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap());
+        let from_entries: Vec<_> = converter
+            .to_yomitan(converter.read_entries(entries))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        let json = |entries: &[YomitanEntry]| serde_json::to_string(entries).unwrap();
+        assert_eq!(json(&from_jsonl), json(&from_entries));
+    }
+
+    #[test]
+    fn jsonl_and_entries_agree_on_first_with_several_languages() {
+        // Each German entry is preceded by a French one, that `first` must not count
+        let mut jsonl = String::new();
+        for line in fixture().lines() {
+            let line = line.unwrap();
+            let mut french: serde_json::Value = serde_json::from_str(&line).unwrap();
+            french["lang_code"] = "fr".into();
+            jsonl.push_str(&format!("{french}\n{line}\n"));
+        }
+
+        let converter = Converter::main(Lang::De, Edition::En).unwrap().first(3);
+        let yomitan = |irs| -> Vec<_> {
+            let entries = converter.to_yomitan(irs).unwrap();
+            let entries: Vec<_> = entries.collect::<Result<_>>().unwrap();
+            entries
+                .iter()
+                .map(|e| serde_json::to_string(e).unwrap())
+                .collect()
+        };
+
+        let from_jsonl = yomitan(converter.read_jsonl(jsonl.as_bytes()).unwrap());
+        let entries = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap());
+        let from_entries = yomitan(converter.read_entries(entries));
+
+        assert!(!from_jsonl.is_empty());
+        assert_eq!(from_jsonl, from_entries);
+
+        let all = Converter::main(Lang::De, Edition::En).unwrap();
+        let all = all.to_yomitan(all.read_jsonl(fixture()).unwrap()).unwrap();
+        assert!(from_jsonl.len() < all.count());
+    }
+
+    #[test]
+    fn compression_level_is_clamped() {
+        let converter = Converter::main(Lang::De, Edition::En).unwrap();
+        let converter = converter.compression_level(200);
+        assert_eq!(converter.pm.opts.compression_level, Some(9));
+    }
+
+    #[test]
+    fn writes_zip_to_any_writer() {
+        let converter = Converter::ipa(Lang::De, Edition::En)
+            .unwrap()
+            .dict_name("mine");
+        let irs = converter.read_jsonl(fixture()).unwrap();
+        let cursor = converter.write_zip(irs, Cursor::new(Vec::new())).unwrap();
+
+        let mut zip = zip::ZipArchive::new(cursor).unwrap();
+        let mut index = String::new();
+        zip.by_name("index.json")
+            .unwrap()
+            .read_to_string(&mut index)
+            .unwrap();
+        assert!(index.contains("mine-de-en-ipa"), "{index}");
+    }
+
+    #[test]
+    fn reproducible_zips_are_identical() {
+        let write = || {
+            let converter = Converter::main(Lang::De, Edition::En)
+                .unwrap()
+                .reproducible(1_700_000_000);
+            let irs = converter.read_jsonl(fixture()).unwrap();
+            converter
+                .write_zip(irs, Cursor::new(Vec::new()))
+                .unwrap()
+                .into_inner()
+        };
+        let bytes = write();
+        assert_eq!(bytes, write());
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut index = String::new();
+        zip.by_name("index.json")
+            .unwrap()
+            .read_to_string(&mut index)
+            .unwrap();
+        assert!(index.contains("2023.11.14"), "{index}");
+    }
+
+    #[test]
+    fn custom_ipa_matches_ipa() {
+        let json = |entries: Vec<YomitanEntry>| serde_json::to_string(&entries).unwrap();
+
+        let ipa = Converter::ipa(Lang::De, Edition::En).unwrap();
+        let expected: Vec<_> = ipa
+            .to_yomitan(ipa.read_jsonl(fixture()).unwrap())
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert!(!expected.is_empty());
+
+        let spec = DCustom::parse("name = \"ipa\"\nbank = \"ipa\"").unwrap();
+        let custom = Converter::custom(spec, Lang::De, Edition::En).unwrap();
+        let found = custom
+            .to_yomitan(custom.read_jsonl(fixture()).unwrap())
+            .unwrap();
+
+        assert_eq!(json(found.collect::<Result<_>>().unwrap()), json(expected));
+    }
+
+    #[test]
+    fn malformed_lines_fail_unless_allowed() {
+        let jsonl = "{\"word\": \"a\", \"lang_code\": \"de\"}\nnot json\n";
+        let converter = Converter::main(Lang::De, Edition::En).unwrap();
+        assert!(converter.read_jsonl(jsonl.as_bytes()).is_err());
+        assert!(converter.max_errors(1).read_jsonl(jsonl.as_bytes()).is_ok());
+    }
+}
@@ -1,44 +1,74 @@
-use anyhow::{Context, Ok, Result};
+use anyhow::{Context, Ok, Result, bail};
 use serde::{Deserialize, Serialize};
 
 use std::borrow::Cow;
 use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::path::PathBuf;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+use std::time::SystemTime;
 
+use rayon::prelude::*;
+
+use crate::Map;
 use crate::cli::{LangSpecs, Options};
 use crate::dict::writer::write_yomitan;
 use crate::lang::{Edition, Lang};
 use crate::models::kaikki::WordEntry;
 use crate::models::yomitan::YomitanEntry;
 use crate::path::{PathKind, PathManager};
-use crate::utils::pretty_print_at_path;
+use crate::progress::{self, Progress};
+use crate::utils::pretty_println_at_path;
 use crate::utils::skip_because_file_exists;
 
-const CONSOLE_PRINT_INTERVAL: i32 = 10000;
-
-// pub type E = Box<dyn Iterator<Item = YomitanEntry>>;
-pub type E = Vec<YomitanEntry>;
+/// Entries are rendered lazily, as the writer pulls them bank by bank.
+///
+/// Rendering only fails when the intermediate representation is read back from disk, cf.
+/// `--memory-limit`.
+pub type E = Box<dyn Iterator<Item = Result<YomitanEntry>>>;
 
 // Used in tests to write separate files for lemmas/forms.
 pub struct LabelledYomitanEntry {
     pub label: &'static str,
     pub entries: E,
+    /// Number of entries, if known in advance. Used to report progress as `bank 2/5`.
+    pub len: Option<usize>,
 }
 
 impl LabelledYomitanEntry {
     pub fn new(
         label: &'static str,
-        // entries: impl IntoIterator<Item = YomitanEntry> + 'static,
-        entries: Vec<YomitanEntry>,
+        entries: impl IntoIterator<Item = YomitanEntry> + 'static,
+    ) -> Self {
+        Self::fallible(label, entries.into_iter().map(Ok))
+    }
+
+    /// Entries whose rendering can fail.
+    pub fn fallible(
+        label: &'static str,
+        entries: impl IntoIterator<Item = Result<YomitanEntry>> + 'static,
     ) -> Self {
+        let entries = entries.into_iter();
+        let len = match entries.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(lower),
+            _ => None,
+        };
         Self {
             label,
-            // entries: Box::new(entries.into_iter()),
-            entries,
+            entries: Box::new(entries),
+            len,
         }
     }
+
+    /// Set the number of entries, for iterators that can not tell it themselves.
+    pub const fn with_len(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
 }
 
 /// Trait for Intermediate representation. Used for postprocessing (merge, etc.) and debugging via snapshots.
@@ -50,6 +80,20 @@ pub trait Intermediate: Default {
         self.len() == 0
     }
 
+    /// Approximate size in bytes. Used to bound memory when making several dictionaries at once.
+    fn memory_usage(&self) -> usize {
+        0
+    }
+
+    /// Move `Self::I` to disk, in `dir`, because it grew past `limit` bytes (cf. `--memory-limit`).
+    ///
+    /// It is merged back by `Dictionary::to_yomitan`. Only the main dictionary spills: the others
+    /// stay in memory.
+    #[allow(unused_variables)]
+    fn spill(&mut self, dir: &Path, limit: usize) -> Result<()> {
+        Ok(())
+    }
+
     /// How to write `Self::I` to disk.
     ///
     /// Only called if `opts.save_temps` is set and `Dictionary::write_ir` returns true.
@@ -67,6 +111,11 @@ where
         Self::len(self)
     }
 
+    // Shallow: does not account for the heap of the items themselves.
+    fn memory_usage(&self) -> usize {
+        self.capacity() * std::mem::size_of::<T>()
+    }
+
     fn write(&self, pm: &PathManager) -> Result<()> {
         let writer_path = pm.dir_tidy().join("tidy.jsonl");
         let writer_file = File::create(&writer_path)?;
@@ -77,7 +126,7 @@ where
             serde_json::to_writer(writer, self)?;
         }
         if !pm.opts.quiet {
-            pretty_print_at_path("Wrote tidy", &writer_path);
+            pretty_println_at_path("Wrote tidy", &writer_path);
         }
         Ok(())
     }
@@ -111,7 +160,7 @@ pub trait Dictionary {
 
     /// Console message for found irs. It is customized for the main dictionary.
     fn found_ir_message(&self, irs: &Self::I) {
-        println!("Found {} irs", irs.len());
+        progress::println(&format!("Found {} irs", irs.len()));
     }
 
     /// Whether to write or not `Self::I` to disk.
@@ -131,12 +180,13 @@ pub trait Dictionary {
     fn postprocess(&self, irs: &mut Self::I) {}
 
     /// How to convert `Self::I` into one or more yomitan entries.
-    fn to_yomitan(&self, langs: LangSpecs, irs: Self::I) -> Vec<LabelledYomitanEntry>;
+    fn to_yomitan(&self, langs: LangSpecs, irs: Self::I) -> Result<Vec<LabelledYomitanEntry>>;
 }
 
-fn rejected(entry: &WordEntry, opts: &Options) -> bool {
+pub(crate) fn rejected(entry: &WordEntry, opts: &Options) -> bool {
     opts.reject.iter().any(|(k, v)| k.field_value(entry) == v)
         || !opts.filter.iter().all(|(k, v)| k.field_value(entry) == v)
+        || !opts.r#where.iter().all(|expr| expr.eval(entry))
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -150,15 +200,82 @@ pub struct Langs {
 pub enum DatasetInput {
     Cached(PathBuf),
     Streamed(Edition),
+    /// Given with `--input`: a jsonlines file, plain or compressed.
+    Local(PathBuf),
+    /// Given with `--input -`.
+    Stdin,
 }
 
 impl DatasetInput {
-    fn open_reader(&self, capacity: usize, quiet: bool) -> Result<Box<dyn BufRead>> {
+    /// Where to read `edition` from: `--input`, kaikki with `--stream`, or a file on disk, which
+    /// is downloaded if missing.
+    pub(crate) fn for_edition(
+        edition: Edition,
+        lang: Option<Lang>,
+        pm: &PathManager,
+    ) -> Result<Self> {
+        if let Some(input) = &pm.opts.input {
+            if pm.langs.edition.variants().len() > 1 {
+                bail!("--input holds a single edition, but this dictionary reads all of them");
+            }
+            if input.as_os_str() == "-" {
+                return Ok(Self::Stdin);
+            }
+            return Ok(Self::Local(input.clone()));
+        }
+
+        if pm.opts.stream {
+            return Ok(Self::Streamed(edition));
+        }
+
+        Ok(Self::Cached(find_or_download_jsonl(edition, lang, pm)?))
+    }
+
+    pub(crate) fn open_reader(
+        &self,
+        capacity: usize,
+        quiet: bool,
+    ) -> Result<Box<dyn BufRead + Send>> {
+        Ok(self.open(capacity, quiet)?.0)
+    }
+
+    /// Open the dataset. Also returns kaikki's Last-Modified if it is streamed (cf. `Stamp`).
+    pub(crate) fn open(
+        &self,
+        capacity: usize,
+        quiet: bool,
+    ) -> Result<(Box<dyn BufRead + Send>, Option<SystemTime>)> {
         match self {
-            Self::Cached(path) => Ok(Box::new(BufReader::with_capacity(
-                capacity,
-                File::open(path)?,
-            ))),
+            Self::Cached(path) => {
+                let file = File::open(path)?;
+                let total = file.metadata()?.len();
+                let label = path
+                    .file_name()
+                    .map_or_else(|| self.display_path(), |name| name.to_string_lossy().into());
+                let reader = BufReader::with_capacity(
+                    capacity,
+                    Progress::reader(label, Some(total), !quiet, file),
+                );
+                Ok((Box::new(reader), None))
+            }
+            Self::Local(path) => {
+                let file = File::open(path)
+                    .with_context(|| format!("could not open input {}", path.display()))?;
+                let total = file.metadata()?.len();
+                // Counts compressed bytes, like downloads
+                let reader = Progress::reader(self.display_path(), Some(total), !quiet, file);
+                Ok((
+                    decompressed(BufReader::with_capacity(capacity, reader), capacity)?,
+                    None,
+                ))
+            }
+            Self::Stdin => {
+                let reader = Progress::reader(self.display_path(), None, !quiet, std::io::stdin());
+                Ok((
+                    decompressed(BufReader::with_capacity(capacity, reader), capacity)?,
+                    None,
+                ))
+            }
             Self::Streamed(edition) => {
                 #[cfg(feature = "html")]
                 {
@@ -173,14 +290,41 @@ impl DatasetInput {
         }
     }
 
-    fn display_path(&self) -> String {
+    pub(crate) fn display_path(&self) -> String {
         match self {
             Self::Cached(path) => path.display().to_string(),
             Self::Streamed(edition) => format!("stream://{edition}"),
+            Self::Local(path) => path.display().to_string(),
+            Self::Stdin => "<stdin>".to_string(),
         }
     }
 }
 
+/// Decompress `reader` if it starts with the magic bytes of gzip, zstd or xz.
+fn decompressed<R: BufRead + Send + 'static>(
+    mut reader: R,
+    capacity: usize,
+) -> Result<Box<dyn BufRead + Send>> {
+    const GZIP: &[u8] = &[0x1f, 0x8b];
+    const ZSTD: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+    const XZ: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+    let head = reader.fill_buf()?;
+    let reader: Box<dyn BufRead + Send> = if head.starts_with(GZIP) {
+        let decoder = flate2::bufread::MultiGzDecoder::new(reader);
+        Box::new(BufReader::with_capacity(capacity, decoder))
+    } else if head.starts_with(ZSTD) {
+        let decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+        Box::new(BufReader::with_capacity(capacity, decoder))
+    } else if head.starts_with(XZ) {
+        let decoder = liblzma::bufread::XzDecoder::new_multi_decoder(reader);
+        Box::new(BufReader::with_capacity(capacity, decoder))
+    } else {
+        Box::new(reader)
+    };
+    Ok(reader)
+}
+
 impl Langs {
     pub const fn new(edition: Edition, source: Lang, target: Lang) -> Self {
         Self {
@@ -246,25 +390,13 @@ pub fn find_or_download_jsonl(
     Ok(path.clone())
 }
 
-fn dataset_input(edition: Edition, source: Lang, pm: &PathManager) -> Result<DatasetInput> {
-    if pm.opts.stream {
-        return Ok(DatasetInput::Streamed(edition));
-    }
-
-    Ok(DatasetInput::Cached(find_or_download_jsonl(
-        edition,
-        Some(source),
-        pm,
-    )?))
-}
-
 pub fn iter_datasets(
     pm: &PathManager,
 ) -> impl Iterator<Item = Result<(Edition, DatasetInput)>> + '_ {
     let (edition_pm, source_pm, _) = pm.langs();
 
     edition_pm.variants().into_iter().map(move |edition| {
-        let dataset = dataset_input(edition, source_pm, pm)?;
+        let dataset = DatasetInput::for_edition(edition, Some(source_pm), pm)?;
         tracing::debug!("edition: {edition}, path: {}", dataset.display_path());
 
         Ok((edition, dataset))
@@ -273,9 +405,9 @@ pub fn iter_datasets(
 
 #[derive(Deserialize)]
 #[serde(default)]
-struct LangCodeProbe<'a> {
+pub(crate) struct LangCodeProbe<'a> {
     #[serde(borrow)]
-    lang_code: Cow<'a, str>,
+    pub(crate) lang_code: Cow<'a, str>,
 }
 
 impl Default for LangCodeProbe<'_> {
@@ -286,74 +418,367 @@ impl Default for LangCodeProbe<'_> {
     }
 }
 
-pub fn make_dict<D: Dictionary>(dict: D, raw_args: D::A) -> Result<()> {
-    let pm: &PathManager = &raw_args.try_into()?;
-    let (_, source_pm, target_pm) = pm.langs();
-    let opts = &pm.opts;
+/// Lines per batch. Batches are decoded in parallel, then processed in input order.
+const BATCH_SIZE: usize = 1 << 10;
+/// Batches read ahead of the one being processed.
+const BATCHES_IN_FLIGHT: usize = 2;
 
-    pm.setup_dirs()?;
+type Batch = Vec<Vec<u8>>;
 
-    let capacity = 256 * (1 << 10); // default is 8 * (1 << 10) := 8KB
-    let mut line = Vec::with_capacity(1 << 10);
-    let mut irs = D::I::default();
+/// Read `reader` in batches of lines and send them, until EOF or until the receiver hangs up.
+fn read_batches(mut reader: impl BufRead, tx: &SyncSender<Result<Batch>>) {
+    loop {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        while batch.len() < BATCH_SIZE {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line) {
+                Result::Ok(0) => break, // EOF
+                Result::Ok(_) => batch.push(line),
+                Err(err) => {
+                    let _ = tx.send(Err(err.into()));
+                    return;
+                }
+            }
+        }
 
-    for pair in iter_datasets(pm) {
-        let (edition, dataset) = pair?;
-        let mut reader = dataset.open_reader(capacity, opts.quiet)?;
+        let eof = batch.len() < BATCH_SIZE;
+        if !batch.is_empty() && tx.send(Ok(batch)).is_err() {
+            return;
+        }
+        if eof {
+            return;
+        }
+    }
+}
+
+/// A predicate on the `lang_code` of a jsonline.
+pub(crate) type Probe<'a> = &'a (dyn Fn(&str) -> bool + Sync);
+
+/// A decoded line: `None` if it was skipped by the probe.
+pub(crate) type Decoded = Option<Result<WordEntry, BadLine>>;
+
+/// A line that could not be deserialized.
+#[derive(Debug)]
+pub(crate) struct BadLine {
+    line: Vec<u8>,
+    error: serde_json::Error,
+}
+
+impl BadLine {
+    pub(crate) const fn new(line: Vec<u8>, error: serde_json::Error) -> Self {
+        Self { line, error }
+    }
+}
 
-        let mut line_count = 0;
-        let mut accepted_count = 0;
+/// Where malformed lines go, instead of aborting, until there are more than `--max-errors`.
+///
+/// Each one is written as `{"dataset": "...", "line": 12, "error": "...", "content": "..."}`.
+pub(crate) struct Quarantine {
+    /// `None` to only count them.
+    path: Option<PathBuf>,
+    writer: Option<BufWriter<File>>,
+    max_errors: usize,
+    count: usize,
+}
+
+impl Quarantine {
+    pub(crate) const fn new(path: PathBuf, max_errors: usize) -> Self {
+        Self {
+            path: Some(path),
+            writer: None,
+            max_errors,
+            count: 0,
+        }
+    }
+
+    /// Skip up to `max_errors` bad lines without writing them anywhere.
+    pub(crate) const fn discarding(max_errors: usize) -> Self {
+        Self {
+            path: None,
+            writer: None,
+            max_errors,
+            count: 0,
+        }
+    }
 
-        loop {
-            line.clear();
-            if reader.read_until(b'\n', &mut line)? == 0 {
-                break; // EOF
+    pub(crate) fn for_pm(pm: &PathManager) -> Self {
+        Self::new(pm.path_quarantine(), pm.opts.max_errors)
+    }
+
+    /// Record the bad line `line_number` (starting at 1) of `dataset`, or fail if there are too
+    /// many.
+    pub(crate) fn record(&mut self, dataset: &str, line_number: usize, bad: BadLine) -> Result<()> {
+        self.count += 1;
+        if self.count > self.max_errors {
+            let error = anyhow::Error::new(bad.error)
+                .context(format!("Error decoding JSON @ {dataset}:{line_number}"));
+            return Err(if self.max_errors == 0 {
+                error
+            } else {
+                error.context(format!("More than {} malformed lines", self.max_errors))
+            });
+        }
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                self.writer.insert(BufWriter::new(File::create(path)?))
             }
+        };
+        let record = serde_json::json!({
+            "dataset": dataset,
+            "line": line_number,
+            "error": bad.error.to_string(),
+            "content": String::from_utf8_lossy(&bad.line).trim_end_matches(['\r', '\n']),
+        });
+        serde_json::to_writer(&mut *writer, &record)?;
+        writer.write_all(b"\n")?;
 
-            line_count += 1;
+        Ok(())
+    }
 
-            if !opts.quiet && line_count % CONSOLE_PRINT_INTERVAL == 0 {
-                print!("Processed {line_count} lines...\r");
-                std::io::stdout().flush()?;
+    /// Flush the file and report the skipped lines, if any. Returns how many there were.
+    pub(crate) fn finish(self) -> Result<usize> {
+        match (self.writer, &self.path) {
+            (Some(mut writer), Some(path)) => {
+                writer.flush()?;
+                tracing::warn!(
+                    "Skipped {} malformed lines, written to {}",
+                    self.count,
+                    path.display()
+                );
             }
+            _ if self.count > 0 => tracing::warn!("Skipped {} malformed lines", self.count),
+            _ => (),
+        }
+        Ok(self.count)
+    }
+}
 
-            if dict.supports_probe() {
-                let probe: LangCodeProbe = serde_json::from_slice(&line)
-                    .with_context(|| "Error decoding JSON @ make_dict")?;
-                if source_pm.as_ref() != probe.lang_code.as_ref() {
-                    continue;
+/// Deserialize a batch of lines in parallel.
+///
+/// If `probe` is given, lines whose `lang_code` does not satisfy it are skipped without being
+/// fully deserialized.
+fn decode_batch(batch: &[Vec<u8>], probe: Option<Probe>) -> Vec<Decoded> {
+    batch
+        .par_iter()
+        .map(|line| {
+            if let Some(probe) = probe {
+                let lang_code: LangCodeProbe = match serde_json::from_slice(line) {
+                    Result::Ok(lang_code) => lang_code,
+                    Err(err) => return Some(Err(BadLine::new(line.clone(), err))),
+                };
+                if !probe(&lang_code.lang_code) {
+                    return None;
                 }
             }
 
-            let mut entry: WordEntry =
-                serde_json::from_slice(&line).with_context(|| "Error decoding JSON @ make_dict")?;
+            Some(serde_json::from_slice(line).map_err(|err| BadLine::new(line.clone(), err)))
+        })
+        .collect()
+}
 
-            if rejected(&entry, opts) {
-                continue;
-            }
+/// Feed every line of `reader`, with its decoding, to `f` in input order, until it breaks.
+///
+/// One thread reads batches of lines, that are decoded in parallel while the next ones are being
+/// read. Entries are then handed sequentially, so that the output does not depend on scheduling.
+pub(crate) fn for_each_entry(
+    reader: impl BufRead + Send,
+    probe: Option<Probe>,
+    mut f: impl FnMut(&[u8], Decoded) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::sync_channel(BATCHES_IN_FLIGHT);
+        scope.spawn(move || read_batches(reader, &tx));
 
-            accepted_count += 1;
-            if accepted_count == opts.first {
-                break;
+        for batch in rx {
+            let batch = batch?;
+            for (line, decoded) in batch.iter().zip(decode_batch(&batch, probe)) {
+                if f(line, decoded)?.is_break() {
+                    // Dropping the receiver stops the reader.
+                    return Ok(());
+                }
             }
+        }
 
-            let langs = Langs {
-                edition,
-                source: source_pm,
-                target: target_pm,
-            };
+        Ok(())
+    })
+}
+
+/// Feed the well-formed entries of `reader` to `f`, with their line number, until it breaks.
+///
+/// Lines skipped by `probe` are not fed, and malformed ones go to `quarantine` as lines of
+/// `dataset`. Returns the number of lines read.
+pub(crate) fn for_each_valid_entry(
+    reader: impl BufRead + Send,
+    probe: Option<Probe>,
+    dataset: &str,
+    quarantine: &mut Quarantine,
+    mut f: impl FnMut(usize, WordEntry) -> Result<ControlFlow<()>>,
+) -> Result<usize> {
+    for_each_valid_line(
+        reader,
+        probe,
+        dataset,
+        quarantine,
+        |line_number, _, entry| f(line_number, entry),
+    )
+}
+
+/// Same as `for_each_valid_entry`, also feeding the line the entry was read from, as it is in
+/// `reader`.
+pub(crate) fn for_each_valid_line(
+    reader: impl BufRead + Send,
+    probe: Option<Probe>,
+    dataset: &str,
+    quarantine: &mut Quarantine,
+    mut f: impl FnMut(usize, &[u8], WordEntry) -> Result<ControlFlow<()>>,
+) -> Result<usize> {
+    let mut line_count = 0;
 
-            if dict.keep_if(langs.source, &entry) {
-                dict.preprocess(langs, &mut entry, opts, &mut irs);
-                dict.process(langs, &entry, &mut irs);
+    for_each_entry(reader, probe, |line, decoded| {
+        line_count += 1;
+
+        match decoded {
+            None => Ok(ControlFlow::Continue(())),
+            Some(Err(bad)) => {
+                quarantine.record(dataset, line_count, bad)?;
+                Ok(ControlFlow::Continue(()))
             }
+            Some(Result::Ok(entry)) => f(line_count, line, entry),
+        }
+    })?;
+
+    Ok(line_count)
+}
+
+/// The entries of a dataset that pass the filters of the options, until `--first` of them.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Selection {
+    accepted_count: i32,
+    done: bool,
+}
+
+impl Selection {
+    /// Whether to keep `entry`. Once `--first` entries are accepted, nothing more is kept.
+    pub(crate) fn keeps(&mut self, entry: &WordEntry, opts: &Options) -> bool {
+        if self.done || rejected(entry, opts) {
+            return false;
+        }
+
+        self.accepted_count += 1;
+        if self.accepted_count == opts.first {
+            self.done = true;
+        }
+        !self.done
+    }
+
+    /// Whether it wants no more entries from the current dataset.
+    pub(crate) const fn done(&self) -> bool {
+        self.done
+    }
+
+    pub(crate) const fn accepted_count(&self) -> i32 {
+        self.accepted_count
+    }
+
+    /// Break once done, so that the rest of the dataset is not read.
+    pub(crate) const fn flow(&self) -> ControlFlow<()> {
+        if self.done {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
         }
+    }
+}
+
+/// Make the dictionary. Returns the number of malformed lines that were skipped (cf.
+/// `--max-errors`).
+pub fn make_dict<D: Dictionary>(dict: D, raw_args: D::A) -> Result<usize> {
+    let pm: &PathManager = &raw_args.try_into()?;
+    let (_, source_pm, target_pm) = pm.langs();
+    let opts = &pm.opts;
+
+    pm.setup_dirs()?;
+
+    let capacity = 256 * (1 << 10); // default is 8 * (1 << 10) := 8KB
+    let probe = |lang_code: &str| lang_code == source_pm.as_ref();
+    let probe: Option<Probe> = dict.supports_probe().then_some(&probe);
+    let mut irs = D::I::default();
+    let mut quarantine = Quarantine::for_pm(pm);
+    let mut streamed = None;
+
+    for pair in iter_datasets(pm) {
+        let (edition, dataset) = pair?;
+        let (reader, modified) = dataset.open(capacity, opts.quiet)?;
+        streamed = streamed.max(modified);
+        let dataset_path = dataset.display_path();
+
+        let mut selection = Selection::default();
+        let mut last_check = 0;
+
+        let langs = Langs {
+            edition,
+            source: source_pm,
+            target: target_pm,
+        };
+
+        let line_count = for_each_valid_entry(
+            reader,
+            probe,
+            &dataset_path,
+            &mut quarantine,
+            |line, mut entry| {
+                if let Some(limit) = opts.memory_limit
+                    && line - last_check >= MEMORY_CHECK_INTERVAL
+                {
+                    last_check = line;
+                    if irs.memory_usage() > limit {
+                        irs.spill(&pm.dir_spill(), limit)?;
+                    }
+                }
+
+                if selection.keeps(&entry, opts) && dict.keep_if(langs.source, &entry) {
+                    dict.preprocess(langs, &mut entry, opts, &mut irs);
+                    dict.process(langs, &entry, &mut irs);
+                }
+
+                Ok(selection.flow())
+            },
+        )?;
 
         if !opts.quiet {
-            println!("Processed {line_count} lines. Accepted {accepted_count} lines.");
+            progress::println(&format!(
+                "Processed {line_count} lines. Accepted {} lines.",
+                selection.accepted_count()
+            ));
         }
     }
 
+    let skipped = quarantine.finish()?;
+
+    write_dict(&dict, pm, irs, streamed)?;
+    Ok(skipped)
+}
+
+/// Postprocess the intermediate representation and write the dictionary.
+///
+/// `streamed` is the newest Last-Modified of the datasets streamed from kaikki, if any.
+fn write_dict<D: Dictionary>(
+    dict: &D,
+    pm: &PathManager,
+    mut irs: D::I,
+    streamed: Option<SystemTime>,
+) -> Result<()> {
+    let (_, source_pm, target_pm) = pm.langs();
+    let opts = &pm.opts;
+
     if !opts.quiet {
         dict.found_ir_message(&irs);
     }
@@ -369,9 +794,707 @@ pub fn make_dict<D: Dictionary>(dict: D, raw_args: D::A) -> Result<()> {
     }
 
     if !opts.skip_yomitan {
-        let labelled_entries = dict.to_yomitan(pm.langs, irs);
-        write_yomitan(source_pm, target_pm, opts, &pm, labelled_entries)?;
+        let labelled_entries = dict.to_yomitan(pm.langs, irs)?;
+        write_yomitan(source_pm, target_pm, opts, pm, labelled_entries, streamed)?;
+    }
+
+    Ok(())
+}
+
+/// A dictionary of a `Composite`, with the type of its intermediate representation erased.
+trait CompositePart {
+    fn pm(&self) -> &PathManager;
+
+    fn supports_probe(&self) -> bool;
+
+    /// Reset the per-dataset counters (cf. `--first`).
+    fn reset(&mut self);
+
+    /// Whether it wants no more entries from the current dataset.
+    fn done(&self) -> bool;
+
+    fn feed(&mut self, edition: Edition, entry: Cow<'_, WordEntry>);
+
+    /// Spill the intermediate representation if it grew past its `--memory-limit`.
+    fn spill_over_limit(&mut self) -> Result<()>;
+
+    fn finish(self: Box<Self>, streamed: Option<SystemTime>) -> Result<()>;
+}
+
+struct Part<D: Dictionary> {
+    dict: D,
+    pm: PathManager,
+    irs: D::I,
+    selection: Selection,
+}
+
+impl<D: Dictionary> CompositePart for Part<D> {
+    fn pm(&self) -> &PathManager {
+        &self.pm
+    }
+
+    fn supports_probe(&self) -> bool {
+        self.dict.supports_probe()
+    }
+
+    fn reset(&mut self) {
+        self.selection = Selection::default();
+    }
+
+    fn done(&self) -> bool {
+        self.selection.done()
+    }
+
+    fn feed(&mut self, edition: Edition, entry: Cow<'_, WordEntry>) {
+        let opts = &self.pm.opts;
+        if !self.selection.keeps(&entry, opts) {
+            return;
+        }
+
+        let (_, source, target) = self.pm.langs();
+        let langs = Langs {
+            edition,
+            source,
+            target,
+        };
+        if self.dict.keep_if(langs.source, &entry) {
+            // Only clones if another dictionary still needs the entry.
+            let mut entry = entry.into_owned();
+            self.dict.preprocess(langs, &mut entry, opts, &mut self.irs);
+            self.dict.process(langs, &entry, &mut self.irs);
+        }
+    }
+
+    fn spill_over_limit(&mut self) -> Result<()> {
+        if let Some(limit) = self.pm.opts.memory_limit
+            && self.irs.memory_usage() > limit
+        {
+            self.irs.spill(&self.pm.dir_spill(), limit)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>, streamed: Option<SystemTime>) -> Result<()> {
+        write_dict(&self.dict, &self.pm, self.irs, streamed)
+    }
+}
+
+/// Several dictionaries made from a single pass over their input.
+///
+/// Every dictionary keeps its own intermediate representation, options and output path. They must
+/// read the same input: same edition and source language (cf. `Composite::shares_input`).
+#[derive(Default)]
+pub struct Composite {
+    parts: Vec<Box<dyn CompositePart>>,
+}
+
+impl Composite {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// Whether a dictionary of `pm` reads the same input as those already added.
+    pub fn shares_input(&self, pm: &PathManager) -> bool {
+        self.parts.first().is_none_or(|part| {
+            let first = part.pm();
+            first.langs.edition == pm.langs.edition
+                && first.langs.source == pm.langs.source
+                && first.opts.stream == pm.opts.stream
+                && first.opts.input == pm.opts.input
+        })
+    }
+
+    pub fn push<D: Dictionary + 'static>(&mut self, dict: D, raw_args: D::A) -> Result<()> {
+        let pm: PathManager = raw_args.try_into()?;
+        if !self.shares_input(&pm) {
+            bail!(
+                "{} {:?} does not read the same input as the other dictionaries",
+                pm.dict_ty,
+                pm.langs
+            );
+        }
+        self.parts.push(Box::new(Part {
+            dict,
+            pm,
+            irs: D::I::default(),
+            selection: Selection::default(),
+        }));
+        Ok(())
+    }
+
+    /// Make every dictionary, reading their input once. Returns the number of malformed lines
+    /// that were skipped.
+    pub fn make(self) -> Result<usize> {
+        let mut parts = self.parts;
+        let Some(pm) = parts.first().map(|part| part.pm().clone()) else {
+            return Ok(0);
+        };
+        let (_, source_pm, _) = pm.langs();
+        let opts = &pm.opts;
+
+        for part in &parts {
+            part.pm().setup_dirs()?;
+        }
+
+        let capacity = 256 * (1 << 10);
+        let probe = |lang_code: &str| lang_code == source_pm.as_ref();
+        let probe: Option<Probe> = parts
+            .iter()
+            .all(|part| part.supports_probe())
+            .then_some(&probe);
+
+        let mut quarantine = Quarantine::for_pm(&pm);
+        let mut streamed = None;
+
+        for pair in iter_datasets(&pm) {
+            let (edition, dataset) = pair?;
+            let (reader, modified) = dataset.open(capacity, opts.quiet)?;
+            streamed = streamed.max(modified);
+            let dataset_path = dataset.display_path();
+
+            for part in &mut parts {
+                part.reset();
+            }
+            let mut last_check = 0;
+
+            let line_count = for_each_valid_entry(
+                reader,
+                probe,
+                &dataset_path,
+                &mut quarantine,
+                |line, entry| {
+                    if line - last_check >= MEMORY_CHECK_INTERVAL {
+                        last_check = line;
+                        for part in &mut parts {
+                            part.spill_over_limit()?;
+                        }
+                    }
+
+                    if let Some((last, others)) = parts.split_last_mut() {
+                        for part in others {
+                            part.feed(edition, Cow::Borrowed(&entry));
+                        }
+                        last.feed(edition, Cow::Owned(entry));
+                    }
+
+                    Ok(if parts.iter().all(|part| part.done()) {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    })
+                },
+            )?;
+
+            if !opts.quiet {
+                progress::println(&format!("Processed {line_count} lines."));
+            }
+        }
+
+        let skipped = quarantine.finish()?;
+
+        for part in parts {
+            part.finish(streamed)?;
+        }
+
+        Ok(skipped)
+    }
+}
+
+/// Lines between two checks of the memory used by `make_dicts`.
+const MEMORY_CHECK_INTERVAL: usize = 50_000;
+
+/// A dictionary being made by `make_dicts`.
+struct SourceState<I> {
+    pm: PathManager,
+    irs: I,
+    selection: Selection,
+}
+
+/// Make one dictionary per `PathManager`, reading their edition once instead of once each.
+///
+/// Only for dictionaries that probe on the source language (cf. `Dictionary::supports_probe`):
+/// entries are routed to the dictionary of their `lang_code`. The `PathManager`s must only differ
+/// in their source language. The options used for reading are those of the first one.
+///
+/// If the intermediate representations grow past `memory_limit` bytes, the language with the
+/// largest one is dropped from this pass and retried in a later one. A language alone in its pass
+/// is never dropped.
+///
+/// Returns the number of malformed lines that were skipped: they can not be told apart by
+/// language, so they go to a quarantine file of the edition (cf. `path_quarantine_edition`).
+pub fn make_dicts<D: Dictionary>(
+    dict: D,
+    pms: Vec<PathManager>,
+    memory_limit: Option<usize>,
+) -> Result<usize> {
+    let Some(first) = pms.first().cloned() else {
+        return Ok(0);
+    };
+    if !dict.supports_probe() {
+        bail!("this dictionary can not be made for several source languages at once");
+    }
+    let (edition_pm, _, target_pm) = first.langs();
+    if pms
+        .iter()
+        .any(|pm| pm.langs.edition != edition_pm || pm.langs.target != target_pm)
+    {
+        bail!("dictionaries made at once must only differ in their source language");
+    }
+
+    let capacity = 256 * (1 << 10);
+    let quiet = first.opts.quiet;
+    let mut pending = pms;
+    let mut failed = Vec::new();
+    let mut quarantine = Quarantine::new(first.path_quarantine_edition(), first.opts.max_errors);
+    let mut first_pass = true;
+    let mut streamed = None;
+
+    while !pending.is_empty() {
+        let mut pass = Vec::with_capacity(pending.len());
+        for pm in pending.drain(..) {
+            pm.setup_dirs()?;
+            pass.push(Some(SourceState {
+                pm,
+                irs: D::I::default(),
+                selection: Selection::default(),
+            }));
+        }
+        let route: Map<String, usize> = pass
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(idx, state)| (state.pm.langs.source.to_string(), idx))
+            .collect();
+        // Whether `pass[idx]` is still in this pass. Read by the probe, on the decoding threads.
+        let active: Vec<AtomicBool> = pass.iter().map(|_| AtomicBool::new(true)).collect();
+        let mut retry = Vec::new();
+
+        for edition in edition_pm.variants() {
+            let dataset = DatasetInput::for_edition(edition, None, &first)?;
+            if !first_pass && matches!(dataset, DatasetInput::Stdin) {
+                bail!(
+                    "stdin can only be read once: raise --memory-limit, or give a file to --input"
+                );
+            }
+            tracing::debug!("edition: {edition}, path: {}", dataset.display_path());
+            let (reader, modified) = dataset.open(capacity, quiet)?;
+            streamed = streamed.max(modified);
+            let dataset_path = dataset.display_path();
+
+            for state in pass.iter_mut().flatten() {
+                state.selection = Selection::default();
+            }
+
+            // Evicted languages are not even deserialized.
+            let probe = |lang_code: &str| {
+                route
+                    .get(lang_code)
+                    .is_some_and(|idx| active[*idx].load(Ordering::Relaxed))
+            };
+            // Later passes read the same lines: bad ones were already recorded.
+            let mut recorded = Quarantine::discarding(usize::MAX);
+            let quarantine = if first_pass {
+                &mut quarantine
+            } else {
+                &mut recorded
+            };
+            let mut last_check = 0;
+
+            let line_count = for_each_valid_entry(
+                reader,
+                Some(&probe),
+                &dataset_path,
+                quarantine,
+                |line, mut entry| {
+                    if line - last_check >= MEMORY_CHECK_INTERVAL {
+                        last_check = line;
+                        if let Some(limit) = memory_limit {
+                            evict_largest(&mut pass, &mut retry, limit);
+                            for (state, active) in pass.iter().zip(&active) {
+                                if state.is_none() {
+                                    active.store(false, Ordering::Relaxed);
+                                }
+                            }
+                        }
+                        spill_over_limit(&mut pass)?;
+                    }
+
+                    let Some(Some(state)) = route
+                        .get(entry.lang_code.as_str())
+                        .map(|idx| pass[*idx].as_mut())
+                    else {
+                        return Ok(ControlFlow::Continue(()));
+                    };
+                    if !state.selection.keeps(&entry, &state.pm.opts) {
+                        let all_done = state.selection.done()
+                            && pass.iter().flatten().all(|state| state.selection.done());
+                        return Ok(if all_done {
+                            ControlFlow::Break(())
+                        } else {
+                            ControlFlow::Continue(())
+                        });
+                    }
+
+                    let langs = Langs {
+                        edition,
+                        source: state.pm.langs.source,
+                        target: target_pm,
+                    };
+                    if dict.keep_if(langs.source, &entry) {
+                        dict.preprocess(langs, &mut entry, &state.pm.opts, &mut state.irs);
+                        dict.process(langs, &entry, &mut state.irs);
+                    }
+
+                    Ok(ControlFlow::Continue(()))
+                },
+            )?;
+
+            if !quiet {
+                progress::println(&format!("Processed {line_count} lines."));
+            }
+        }
+
+        for state in pass.into_iter().flatten() {
+            let source = state.pm.langs.source;
+            if let Err(err) = write_dict(&dict, &state.pm, state.irs, streamed) {
+                tracing::error!("[{source}] ERROR: {err:?}");
+                failed.push(source);
+            }
+        }
+
+        if !retry.is_empty() {
+            tracing::debug!("Memory limit reached: {} languages left", retry.len());
+        }
+        pending = retry;
+        first_pass = false;
     }
 
+    let skipped = quarantine.finish()?;
+
+    if !failed.is_empty() {
+        let failed: Vec<_> = failed.iter().map(ToString::to_string).collect();
+        bail!("Failed to make dictionaries for: {}", failed.join(", "));
+    }
+
+    Ok(skipped)
+}
+
+/// Spill the intermediate representations that grew past their own `--memory-limit`.
+fn spill_over_limit<I: Intermediate>(pass: &mut [Option<SourceState<I>>]) -> Result<()> {
+    for state in pass.iter_mut().flatten() {
+        if let Some(limit) = state.pm.opts.memory_limit
+            && state.irs.memory_usage() > limit
+        {
+            state.irs.spill(&state.pm.dir_spill(), limit)?;
+        }
+    }
     Ok(())
 }
+
+/// Drop the largest intermediate representations until they all fit in `limit` bytes.
+fn evict_largest<I: Intermediate>(
+    pass: &mut [Option<SourceState<I>>],
+    retry: &mut Vec<PathManager>,
+    limit: usize,
+) {
+    let mut sizes: Vec<(usize, usize)> = pass
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, state)| state.as_ref().map(|state| (idx, state.irs.memory_usage())))
+        .collect();
+    let mut total: usize = sizes.iter().map(|(_, size)| size).sum();
+    sizes.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+
+    for (idx, size) in sizes {
+        if total <= limit || pass.iter().flatten().count() <= 1 {
+            break;
+        }
+        let state = pass[idx].take().expect("only active states are sized");
+        tracing::debug!(
+            "Dropping {} ({size} bytes) to stay under {limit} bytes",
+            state.pm.langs.source
+        );
+        retry.push(state.pm);
+        total -= size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir;
+
+    fn lines(n: usize) -> String {
+        (0..n)
+            .map(|i| {
+                let lang_code = if i % 2 == 0 { "de" } else { "fr" };
+                format!(r#"{{"word": "w{i}", "lang_code": "{lang_code}"}}"#) + "\n"
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batches_keep_input_order() {
+        let n = BATCH_SIZE * 2 + 3;
+        let reader: Box<dyn BufRead + Send> = Box::new(std::io::Cursor::new(lines(n)));
+        let (tx, rx) = mpsc::sync_channel(BATCHES_IN_FLIGHT);
+        thread::spawn(move || read_batches(reader, &tx));
+
+        let batches: Vec<Batch> = rx.into_iter().map(|batch| batch.unwrap()).collect();
+        assert_eq!(batches.len(), 3);
+
+        let words: Vec<String> = batches
+            .iter()
+            .flat_map(|batch| decode_batch(batch, None))
+            .map(|entry| entry.unwrap().unwrap().word)
+            .collect();
+        let expected: Vec<String> = (0..n).map(|i| format!("w{i}")).collect();
+        assert_eq!(words, expected);
+    }
+
+    #[test]
+    fn decode_batch_probes_lang_code() {
+        let batch: Batch = lines(4).lines().map(|l| l.as_bytes().to_vec()).collect();
+        let decoded = decode_batch(&batch, Some(&|lang_code: &str| lang_code == "fr"));
+        let words: Vec<_> = decoded
+            .into_iter()
+            .map(|entry| entry.map(|entry| entry.unwrap().word))
+            .collect();
+        assert_eq!(words, [None, Some("w1".into()), None, Some("w3".into())]);
+    }
+
+    #[test]
+    fn reader_stops_when_receiver_hangs_up() {
+        let reader: Box<dyn BufRead + Send> =
+            Box::new(std::io::Cursor::new(lines(BATCH_SIZE * 10)));
+        let (tx, rx) = mpsc::sync_channel(BATCHES_IN_FLIGHT);
+        let handle = thread::spawn(move || read_batches(reader, &tx));
+
+        let _first = rx.recv().unwrap().unwrap();
+        drop(rx);
+        handle.join().unwrap();
+    }
+
+    fn main_args(source: Lang, root_dir: &std::path::Path) -> crate::cli::MainArgs {
+        crate::cli::MainArgs {
+            langs: crate::cli::MainLangs {
+                source,
+                target: Edition::En,
+            },
+            dict_name: crate::cli::DictName::default(),
+            options: Options {
+                save_temps: true,
+                pretty: true,
+                quiet: true,
+                root_dir: root_dir.to_path_buf(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn main_pm(source: Lang, root_dir: &std::path::Path) -> PathManager {
+        main_args(source, root_dir).try_into().unwrap()
+    }
+
+    fn term_banks(pm: &PathManager) -> Vec<(String, String)> {
+        let mut banks: Vec<_> = std::fs::read_dir(pm.dir_temp_dict())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().unwrap() != "index.json")
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().to_string();
+                (name, std::fs::read_to_string(path).unwrap())
+            })
+            .collect();
+        banks.sort();
+        banks
+    }
+
+    #[test]
+    fn make_dicts_matches_make_dict() {
+        let sources = [Lang::De, Lang::Fr, Lang::La];
+
+        // One dictionary at a time, from the per-language fixtures
+        let single_root = temp_dir("core-single");
+        std::fs::create_dir_all(single_root.join("kaikki")).unwrap();
+        // A whole edition, with the fixtures interleaved
+        let multi_root = temp_dir("core-multi");
+        std::fs::create_dir_all(multi_root.join("kaikki")).unwrap();
+
+        let mut fixtures: Vec<Vec<String>> = Vec::new();
+        for source in sources {
+            let name = format!("{source}-en-extract.jsonl");
+            let path = PathBuf::from("tests/kaikki").join(&name);
+            std::fs::copy(&path, single_root.join("kaikki").join(&name)).unwrap();
+            let content = std::fs::read_to_string(path).unwrap();
+            fixtures.push(content.lines().map(str::to_string).collect());
+        }
+        let longest = fixtures.iter().map(Vec::len).max().unwrap();
+        let mut edition = String::new();
+        for idx in 0..longest {
+            for fixture in &fixtures {
+                if let Some(line) = fixture.get(idx) {
+                    edition.push_str(line);
+                    edition.push('\n');
+                }
+            }
+        }
+        std::fs::write(multi_root.join("kaikki/en-extract.jsonl"), edition).unwrap();
+
+        let multi_pms: Vec<_> = sources.iter().map(|s| main_pm(*s, &multi_root)).collect();
+        make_dicts(crate::dict::DMain, multi_pms.clone(), None).unwrap();
+
+        for (source, multi_pm) in sources.iter().zip(&multi_pms) {
+            let single_pm = main_pm(*source, &single_root);
+            make_dict(crate::dict::DMain, main_args(*source, &single_root)).unwrap();
+            let banks = term_banks(&single_pm);
+            assert!(!banks.is_empty());
+            assert_eq!(banks, term_banks(multi_pm), "{source}");
+        }
+
+        let _ = std::fs::remove_dir_all(single_root);
+        let _ = std::fs::remove_dir_all(multi_root);
+    }
+
+    #[test]
+    fn make_dicts_rejects_different_targets() {
+        let root_dir = temp_dir("core-targets");
+        let mut other = main_pm(Lang::De, &root_dir);
+        other.langs.target = Lang::Fr;
+        let pms = vec![main_pm(Lang::Fr, &root_dir), other];
+        assert!(make_dicts(crate::dict::DMain, pms, None).is_err());
+    }
+
+    #[test]
+    fn composite_matches_make_dict() {
+        let single_root = temp_dir("core-composite-single");
+        let composite_root = temp_dir("core-composite");
+        for root_dir in [&single_root, &composite_root] {
+            std::fs::create_dir_all(root_dir.join("kaikki")).unwrap();
+            std::fs::copy(
+                "tests/kaikki/de-en-extract.jsonl",
+                root_dir.join("kaikki/de-en-extract.jsonl"),
+            )
+            .unwrap();
+        }
+        let ipa_args = |root_dir: &std::path::Path| {
+            let args = main_args(Lang::De, root_dir);
+            crate::cli::IpaArgs {
+                langs: args.langs,
+                dict_name: args.dict_name,
+                options: args.options,
+            }
+        };
+
+        let mut composite = Composite::new();
+        composite
+            .push(crate::dict::DMain, main_args(Lang::De, &composite_root))
+            .unwrap();
+        composite
+            .push(crate::dict::DIpa, ipa_args(&composite_root))
+            .unwrap();
+        composite.make().unwrap();
+
+        make_dict(crate::dict::DMain, main_args(Lang::De, &single_root)).unwrap();
+        make_dict(crate::dict::DIpa, ipa_args(&single_root)).unwrap();
+
+        let pms = |root_dir: &std::path::Path| -> [PathManager; 2] {
+            [
+                main_pm(Lang::De, root_dir),
+                ipa_args(root_dir).try_into().unwrap(),
+            ]
+        };
+        for (single_pm, composite_pm) in pms(&single_root).iter().zip(&pms(&composite_root)) {
+            let banks = term_banks(single_pm);
+            assert!(!banks.is_empty());
+            assert_eq!(banks, term_banks(composite_pm));
+        }
+
+        let _ = std::fs::remove_dir_all(single_root);
+        let _ = std::fs::remove_dir_all(composite_root);
+    }
+
+    #[test]
+    fn composite_rejects_other_inputs() {
+        let root_dir = temp_dir("core-composite-inputs");
+        let mut composite = Composite::new();
+        composite
+            .push(crate::dict::DMain, main_args(Lang::De, &root_dir))
+            .unwrap();
+        assert!(
+            composite
+                .push(crate::dict::DMain, main_args(Lang::Fr, &root_dir))
+                .is_err()
+        );
+        assert_eq!(composite.len(), 1);
+    }
+
+    #[test]
+    fn malformed_lines_are_quarantined() {
+        let root_dir = temp_dir("core-quarantine");
+        std::fs::create_dir_all(root_dir.join("kaikki")).unwrap();
+        let lines = [
+            r#"{"word": "Katze", "lang_code": "de", "pos": "noun", "senses": [{"glosses": ["cat"]}]}"#,
+            r#"{"word": "Hund", "lang_code": "de", "pos": "#,
+            r#"{"word": "Maus", "lang_code": "de", "pos": "noun", "senses": [{"glosses": ["mouse"]}]}"#,
+        ];
+        std::fs::write(
+            root_dir.join("kaikki/de-en-extract.jsonl"),
+            lines.join("\n"),
+        )
+        .unwrap();
+
+        let mut args = main_args(Lang::De, &root_dir);
+        let err = make_dict(crate::dict::DMain, args.clone()).unwrap_err();
+        assert!(format!("{err:#}").contains("de-en-extract.jsonl:2"));
+
+        args.options.max_errors = 1;
+        assert_eq!(make_dict(crate::dict::DMain, args.clone()).unwrap(), 1);
+
+        let pm: PathManager = args.try_into().unwrap();
+        let quarantine = std::fs::read_to_string(pm.path_quarantine()).unwrap();
+        let records: Vec<serde_json::Value> = quarantine
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["line"], 2);
+        assert_eq!(records[0]["content"], lines[1]);
+
+        let _ = std::fs::remove_dir_all(root_dir);
+    }
+
+    #[test]
+    fn evict_largest_keeps_one_language() {
+        let root_dir = temp_dir("core-evict");
+        let state = |source, size| {
+            Some(SourceState {
+                pm: main_pm(source, &root_dir),
+                irs: vec![0_u8; size],
+                selection: Selection::default(),
+            })
+        };
+        let mut pass = vec![
+            state(Lang::De, 10),
+            state(Lang::Fr, 30),
+            state(Lang::La, 20),
+        ];
+        let mut retry = Vec::new();
+
+        evict_largest(&mut pass, &mut retry, 35);
+        let evicted: Vec<_> = retry.iter().map(|pm| pm.langs.source).collect();
+        assert_eq!(evicted, [Lang::Fr]);
+
+        evict_largest(&mut pass, &mut retry, 0);
+        assert_eq!(retry.len(), 2);
+        assert_eq!(pass.iter().flatten().count(), 1);
+    }
+}
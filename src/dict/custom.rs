@@ -0,0 +1,405 @@
+//! Dictionaries described by a spec file instead of code (`wty custom`).
+//!
+//! ```toml
+//! # Appended to the dictionary name: wty-de-en-nouns.zip
+//! name = "nouns"
+//! # Entries to keep, besides those of other languages (cf. `--where`)
+//! select = "pos == noun and not sense_tags == obsolete"
+//! # The term: "word" (default), or "canonical" for the canonical form if there is one
+//! term = "word"
+//! # The reading: "auto" (default, like the other dictionaries), "word" or "none"
+//! reading = "auto"
+//! # "term" (default) for definitions, or "ipa" for phonetic transcriptions
+//! bank = "term"
+//!
+//! # Definitions, in order. Entries without any are skipped.
+//! [[definitions]]
+//! from = "glosses"
+//! tags = ["colloquial"]   # only senses with one of these tags (same for `topics`)
+//!
+//! [[definitions]]
+//! from = "translations"   # to the target language
+//! ```
+//!
+//! Definitions can also come `from` the `examples` of the senses, the `forms` of the entry (with
+//! all of the given `tags`), its `ipa` and its `etymology`. Specs ending in `.json` are read as
+//! JSON, with the same shape.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Deserializer, de::Error as _};
+
+use crate::cli::LangSpecs;
+use crate::dict::{Dictionary, LabelledYomitanEntry, Langs, get_reading, get_short_ipas};
+use crate::expr::Expr;
+use crate::lang::Lang;
+use crate::models::kaikki::{Tag, WordEntry};
+use crate::models::yomitan::{
+    DetailedDefinition, PhoneticTranscription, TermBank, TermBankMeta, TermPhoneticTranscription,
+    YomitanEntry,
+};
+use crate::tags::find_short_pos_or_default;
+
+/// A dictionary made from a spec, cf. the module documentation.
+#[derive(Debug, Clone)]
+pub struct DCustom {
+    name: &'static str,
+    select: Option<Expr>,
+    term: TermSource,
+    reading: ReadingSource,
+    bank: Bank,
+    definitions: Vec<DefinitionSource>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    name: String,
+    #[serde(default, deserialize_with = "deserialize_expr")]
+    select: Option<Expr>,
+    #[serde(default)]
+    term: TermSource,
+    #[serde(default)]
+    reading: ReadingSource,
+    #[serde(default)]
+    bank: Bank,
+    #[serde(default)]
+    definitions: Vec<DefinitionSource>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TermSource {
+    #[default]
+    Word,
+    Canonical,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ReadingSource {
+    #[default]
+    Auto,
+    Word,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Bank {
+    #[default]
+    Term,
+    Ipa,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "from", rename_all = "snake_case", deny_unknown_fields)]
+enum DefinitionSource {
+    Glosses {
+        #[serde(default)]
+        tags: Vec<Tag>,
+        #[serde(default)]
+        topics: Vec<Tag>,
+    },
+    // Empty structs rather than units, for which unknown fields would be ignored
+    Examples {},
+    Translations {},
+    Forms {
+        #[serde(default)]
+        tags: Vec<Tag>,
+    },
+    Ipa {},
+    Etymology {},
+}
+
+fn deserialize_expr<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Expr>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|expr| expr.parse().map_err(D::Error::custom))
+        .transpose()
+}
+
+impl DCustom {
+    /// Read a spec, in TOML or in JSON if it ends with `.json`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read spec @ {}", path.display()))?;
+        let is_json = path.extension().is_some_and(|ext| ext == "json");
+        let spec = if is_json {
+            serde_json::from_str(&content).map_err(anyhow::Error::from)
+        } else {
+            toml::from_str(&content).map_err(anyhow::Error::from)
+        };
+        let spec = spec.with_context(|| format!("Failed to parse spec @ {}", path.display()))?;
+        Self::new(spec).with_context(|| format!("Invalid spec @ {}", path.display()))
+    }
+
+    /// Parse a spec in TOML.
+    pub fn parse(content: &str) -> Result<Self> {
+        Self::new(toml::from_str(content)?)
+    }
+
+    fn new(spec: Spec) -> Result<Self> {
+        let is_valid_name = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-';
+        if spec.name.is_empty() || !spec.name.chars().all(is_valid_name) {
+            bail!(
+                "invalid name '{}': expected lowercase letters, digits and dashes",
+                spec.name
+            );
+        }
+        match spec.bank {
+            Bank::Term if spec.definitions.is_empty() => {
+                bail!("a term bank needs at least one [[definitions]]")
+            }
+            Bank::Ipa if !spec.definitions.is_empty() => {
+                bail!("an ipa bank has no [[definitions]]: it is made of transcriptions")
+            }
+            _ => (),
+        }
+
+        Ok(Self {
+            // Part of `DictionaryType`, which is `Copy`. There are only a few specs per run.
+            name: Box::leak(spec.name.into_boxed_str()),
+            select: spec.select,
+            term: spec.term,
+            reading: spec.reading,
+            bank: spec.bank,
+            definitions: spec.definitions,
+        })
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn term(&self, entry: &WordEntry) -> String {
+        match self.term {
+            TermSource::Word => entry.word.clone(),
+            TermSource::Canonical => entry
+                .canonical_form()
+                .map_or_else(|| entry.word.clone(), |form| form.form.clone()),
+        }
+    }
+
+    fn reading(&self, langs: Langs, entry: &WordEntry) -> String {
+        match self.reading {
+            ReadingSource::Auto => get_reading(langs.edition, langs.source, entry)
+                .unwrap_or_else(|| entry.word.clone()),
+            ReadingSource::Word => entry.word.clone(),
+            ReadingSource::None => String::new(),
+        }
+    }
+
+    fn definitions(&self, target: Lang, entry: &WordEntry) -> Vec<DetailedDefinition> {
+        let mut texts = Vec::new();
+
+        for source in &self.definitions {
+            match source {
+                DefinitionSource::Glosses { tags, topics } => {
+                    let senses = entry.senses.iter().filter(|sense| {
+                        (tags.is_empty() || sense.tags.iter().any(|tag| tags.contains(tag)))
+                            && (topics.is_empty()
+                                || sense.topics.iter().any(|topic| topics.contains(topic)))
+                    });
+                    texts.extend(
+                        senses
+                            .filter(|sense| !sense.glosses.is_empty())
+                            .map(|sense| sense.glosses.join(": ")),
+                    );
+                }
+                DefinitionSource::Examples {} => {
+                    let examples = entry.senses.iter().flat_map(|sense| &sense.examples);
+                    texts.extend(examples.filter(|ex| !ex.text.is_empty()).map(|ex| {
+                        if ex.translation.is_empty() {
+                            ex.text.clone()
+                        } else {
+                            format!("{} — {}", ex.text, ex.translation)
+                        }
+                    }));
+                }
+                DefinitionSource::Translations {} => texts.extend(
+                    entry
+                        .non_trivial_translations()
+                        .filter(|translation| translation.lang_code == target.as_ref())
+                        .map(|translation| translation.word.clone()),
+                ),
+                DefinitionSource::Forms { tags } => texts.extend(
+                    entry
+                        .non_trivial_forms()
+                        .filter(|form| tags.iter().all(|tag| form.tags.contains(tag)))
+                        .map(|form| form.form.clone()),
+                ),
+                DefinitionSource::Ipa {} => {
+                    texts.extend(get_short_ipas(entry).into_iter().map(|ipa| {
+                        if ipa.tags.is_empty() {
+                            ipa.ipa
+                        } else {
+                            format!("{} ({})", ipa.ipa, ipa.tags.join(", "))
+                        }
+                    }));
+                }
+                DefinitionSource::Etymology {} => {
+                    texts.extend(
+                        entry
+                            .etymology_texts()
+                            .into_iter()
+                            .flatten()
+                            .map(String::from),
+                    );
+                }
+            }
+        }
+
+        texts.into_iter().map(DetailedDefinition::Text).collect()
+    }
+}
+
+impl Dictionary for DCustom {
+    type I = Vec<YomitanEntry>;
+    type A = crate::cli::CustomArgs;
+
+    fn keep_if(&self, source: Lang, entry: &WordEntry) -> bool {
+        entry.lang_code == source.as_ref()
+            && self.select.as_ref().is_none_or(|expr| expr.eval(entry))
+    }
+
+    fn supports_probe(&self) -> bool {
+        true
+    }
+
+    fn process(&self, langs: Langs, entry: &WordEntry, irs: &mut Self::I) {
+        match self.bank {
+            Bank::Term => {
+                let definitions = self.definitions(langs.target, entry);
+                if definitions.is_empty() {
+                    return;
+                }
+                let short_pos = find_short_pos_or_default(&entry.pos);
+                irs.push(YomitanEntry::TermBank(TermBank(
+                    self.term(entry),
+                    self.reading(langs, entry),
+                    short_pos.to_string(),
+                    short_pos.to_string(),
+                    definitions,
+                )));
+            }
+            Bank::Ipa => {
+                let transcriptions = get_short_ipas(entry);
+                if transcriptions.is_empty() {
+                    return;
+                }
+                irs.push(YomitanEntry::TermBankMeta(
+                    TermBankMeta::TermPhoneticTranscription(TermPhoneticTranscription(
+                        self.term(entry),
+                        "ipa".to_string(),
+                        PhoneticTranscription {
+                            reading: self.reading(langs, entry),
+                            transcriptions,
+                        },
+                    )),
+                ));
+            }
+        }
+    }
+
+    fn to_yomitan(&self, _: LangSpecs, irs: Self::I) -> Result<Vec<LabelledYomitanEntry>> {
+        Ok(vec![LabelledYomitanEntry::new("term", irs)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::lang::Edition;
+
+    fn entry() -> WordEntry {
+        serde_json::from_str(
+            r#"{
+                "word": "Hund",
+                "pos": "noun",
+                "lang_code": "de",
+                "senses": [
+                    {"glosses": ["dog"]},
+                    {"glosses": ["person", "scoundrel"], "tags": ["derogatory"]}
+                ],
+                "translations": [{"lang_code": "fr", "word": "chien"}]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn texts(spec: &str) -> Vec<String> {
+        let dict = DCustom::parse(spec).unwrap();
+        let langs = Langs::new(Edition::En, Lang::De, Lang::Fr);
+        let mut irs = Vec::new();
+        dict.process(langs, &entry(), &mut irs);
+
+        irs.iter()
+            .flat_map(|entry| match entry {
+                YomitanEntry::TermBank(TermBank(_, _, _, _, definitions)) => definitions.clone(),
+                _ => panic!("expected a term bank"),
+            })
+            .map(|definition| match definition {
+                DetailedDefinition::Text(text) => text,
+                _ => panic!("expected a text definition"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn definitions_follow_the_spec() {
+        let spec = r#"
+            name = "test"
+            [[definitions]]
+            from = "glosses"
+            tags = ["derogatory"]
+            [[definitions]]
+            from = "translations"
+        "#;
+        assert_eq!(texts(spec), ["person: scoundrel", "chien"]);
+
+        // Entries without definitions are skipped
+        let spec = r#"
+            name = "test"
+            [[definitions]]
+            from = "etymology"
+        "#;
+        assert!(texts(spec).is_empty());
+    }
+
+    #[test]
+    fn select_filters_entries() {
+        let dict = DCustom::parse(
+            "name = \"nouns\"\nselect = \"pos == noun\"\n[[definitions]]\nfrom = \"glosses\"",
+        )
+        .unwrap();
+        let mut verb = entry();
+        verb.pos = "verb".into();
+        assert!(dict.keep_if(Lang::De, &entry()));
+        assert!(!dict.keep_if(Lang::De, &verb));
+        assert!(!dict.keep_if(Lang::Fr, &entry()));
+    }
+
+    #[test]
+    fn invalid_specs() {
+        // No definitions for a term bank
+        assert!(DCustom::parse("name = \"test\"").is_err());
+        // Definitions for an ipa bank
+        assert!(
+            DCustom::parse("name = \"test\"\nbank = \"ipa\"\n[[definitions]]\nfrom = \"ipa\"")
+                .is_err()
+        );
+        // Not usable in a file name
+        assert!(DCustom::parse("name = \"a/b\"\nbank = \"ipa\"").is_err());
+        // Unknown fields and sources
+        assert!(DCustom::parse("name = \"test\"\nbank = \"ipa\"\nnope = 1").is_err());
+        assert!(DCustom::parse("name = \"test\"\n[[definitions]]\nfrom = \"nope\"").is_err());
+        assert!(
+            DCustom::parse("name = \"test\"\n[[definitions]]\nfrom = \"ipa\"\ntags = []").is_err()
+        );
+        // Bad expression
+        assert!(DCustom::parse("name = \"test\"\nbank = \"ipa\"\nselect = \"pos ==\"").is_err());
+    }
+}
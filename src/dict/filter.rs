@@ -0,0 +1,221 @@
+//! Split an edition into per-language jsonlines.
+//!
+//! Writes `{lang}-{edition}-extract.jsonl` (`PathKind::Filtered`) next to the unfiltered
+//! jsonlines, where later builds of that language pick it up instead of the whole edition. With
+//! `--filter`, `--reject` or `--where`, it writes `{lang}-{edition}-extract-selected.jsonl`
+//! instead, that builds leave alone.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::Map;
+use crate::cli::{DictName, FilterArgs, LangSpecs};
+use crate::dict::{DatasetInput, Probe, Quarantine, for_each_valid_line, rejected};
+use crate::lang::{EditionSpec, Lang};
+use crate::path::{DictionaryType, PathKind, PathManager};
+use crate::progress;
+use crate::utils::{CHECK_C, pretty_println_at_path};
+
+struct FilteredOutput {
+    path: PathBuf,
+    path_partial: PathBuf,
+    writer: BufWriter<File>,
+    count: usize,
+}
+
+pub fn filter(args: FilterArgs) -> Result<()> {
+    let edition = args.edition;
+    let pm = PathManager {
+        dict_ty: DictionaryType::Main,
+        dict_name: DictName::default(),
+        langs: LangSpecs {
+            edition: EditionSpec::One(edition),
+            source: args.sources[0],
+            target: edition.into(),
+        },
+        opts: args.options.into(),
+    };
+    let opts = &pm.opts;
+
+    fs::create_dir_all(pm.dir_kaik())?;
+
+    let dataset = DatasetInput::for_edition(edition, None, &pm)?;
+    tracing::debug!("edition: {edition}, path: {}", dataset.display_path());
+
+    // Written to a partial file first, so that an interrupted run never leaves behind a
+    // truncated jsonl that later builds would mistake for a complete one.
+    // Entries left out by predicates must not pass for the whole language in later builds.
+    let needs_entry =
+        !opts.filter.is_empty() || !opts.reject.is_empty() || !opts.r#where.is_empty();
+
+    let mut outputs: Map<Lang, FilteredOutput> = Map::default();
+    for source in &args.sources {
+        let path = if needs_entry {
+            pm.path_selected(edition, *source)
+        } else {
+            pm.dataset_paths(edition, Some(*source))
+                .inner
+                .into_iter()
+                .find(|p| p.kind == PathKind::Filtered)
+                .expect("a language always has a filtered path")
+                .path
+        };
+        let path_partial = path.with_extension("jsonl.part");
+        let writer = BufWriter::new(File::create(&path_partial)?);
+        outputs.insert(
+            *source,
+            FilteredOutput {
+                path,
+                path_partial,
+                writer,
+                count: 0,
+            },
+        );
+    }
+
+    let capacity = 256 * (1 << 10);
+    let reader = dataset.open_reader(capacity, opts.quiet)?;
+    let dataset_path = dataset.display_path();
+    let mut quarantine = Quarantine::for_pm(&pm);
+    let lang_codes: Vec<String> = args.sources.iter().map(ToString::to_string).collect();
+    let probe = |lang_code: &str| lang_codes.iter().any(|code| code == lang_code);
+    let probe: Probe = &probe;
+
+    let line_count = for_each_valid_line(
+        reader,
+        Some(probe),
+        &dataset_path,
+        &mut quarantine,
+        |_, line, entry| {
+            if needs_entry && rejected(&entry, opts) {
+                return Ok(ControlFlow::Continue(()));
+            }
+            let Some(output) = entry
+                .lang_code
+                .parse::<Lang>()
+                .ok()
+                .and_then(|lang| outputs.get_mut(&lang))
+            else {
+                return Ok(ControlFlow::Continue(()));
+            };
+
+            output.writer.write_all(line)?;
+            if !line.ends_with(b"\n") {
+                output.writer.write_all(b"\n")?;
+            }
+            output.count += 1;
+            Ok(ControlFlow::Continue(()))
+        },
+    )?;
+
+    if !opts.quiet {
+        progress::println(&format!("Processed {line_count} lines."));
+    }
+    quarantine.finish()?;
+
+    // Filtered files keep the date of their edition, cf. `--reproducible`
+    let modified = match &dataset {
+        DatasetInput::Cached(path) | DatasetInput::Local(path) => {
+            Some(fs::metadata(path)?.modified()?)
+        }
+        DatasetInput::Streamed(_) | DatasetInput::Stdin => None,
+    };
+
+    for (_, output) in outputs {
+        let file = output.writer.into_inner()?;
+        if let Some(modified) = modified {
+            file.set_modified(modified)?;
+        }
+        drop(file);
+        fs::rename(&output.path_partial, &output.path)?;
+
+        if !opts.quiet {
+            let msg = format!("{CHECK_C} Wrote {} lines", output.count);
+            pretty_println_at_path(&msg, &output.path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cli::{FilterKey, FilterOptions};
+    use crate::lang::Edition;
+    use crate::utils::temp_dir;
+
+    fn options(root_dir: &std::path::Path) -> FilterOptions {
+        FilterOptions {
+            redownload: false,
+            stream: false,
+            input: None,
+            max_errors: 0,
+            filter: Vec::new(),
+            reject: Vec::new(),
+            r#where: Vec::new(),
+            quiet: true,
+            root_dir: root_dir.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn filter_several_languages() {
+        let root_dir = temp_dir("filter");
+        fs::create_dir_all(root_dir.join("kaikki")).unwrap();
+
+        let lines = [
+            r#"{"lang_code": "de", "word": "faul", "pos": "adj"}"#,
+            r#"{"lang_code": "fr", "word": "chat", "pos": "noun"}"#,
+            r#"{"lang_code": "nl", "word": "kat", "pos": "noun"}"#,
+            r#"{"lang_code": "de", "word": "Katze", "pos": "noun"}"#,
+            r#"{"lang_code": "fr", "word": "manger", "pos": "verb"}"#,
+        ];
+        // The last line has no trailing newline on purpose
+        fs::write(root_dir.join("kaikki/en-extract.jsonl"), lines.join("\n")).unwrap();
+
+        filter(FilterArgs {
+            edition: Edition::En,
+            sources: vec![Lang::De, Lang::Fr],
+            options: FilterOptions {
+                reject: vec![(FilterKey::Word, "faul".to_string())],
+                ..options(&root_dir)
+            },
+        })
+        .unwrap();
+
+        let de = fs::read_to_string(root_dir.join("kaikki/de-en-extract-selected.jsonl")).unwrap();
+        let fr = fs::read_to_string(root_dir.join("kaikki/fr-en-extract-selected.jsonl")).unwrap();
+        assert_eq!(de, format!("{}\n", lines[3]));
+        assert_eq!(fr, format!("{}\n{}\n", lines[1], lines[4]));
+        assert!(
+            !root_dir
+                .join("kaikki/nl-en-extract-selected.jsonl")
+                .exists()
+        );
+        assert!(
+            !root_dir
+                .join("kaikki/de-en-extract-selected.jsonl.part")
+                .exists()
+        );
+        // Builds of these languages still read the whole edition
+        assert!(!root_dir.join("kaikki/de-en-extract.jsonl").exists());
+
+        // Without predicates, they are what builds read
+        filter(FilterArgs {
+            edition: Edition::En,
+            sources: vec![Lang::De],
+            options: options(&root_dir),
+        })
+        .unwrap();
+        let de = fs::read_to_string(root_dir.join("kaikki/de-en-extract.jsonl")).unwrap();
+        assert_eq!(de, format!("{}\n{}\n", lines[0], lines[3]));
+
+        let _ = fs::remove_dir_all(root_dir);
+    }
+}
@@ -0,0 +1,221 @@
+//! Hyphenation exceptions, for hyphenators of languages that lack TeX patterns.
+//!
+//! Writes the syllabified words of a language, from the `hyphenations` of its entries, next to its
+//! dictionaries: either one `ge-gen-über` per line, or the same words in a TeX `\hyphenation`
+//! block. Only words whose syllables spell them are kept, ignoring the stress marks that some
+//! editions add ("про‧дол‧жа́ть" for "продолжать").
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+
+use anyhow::Result;
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+use crate::Set;
+use crate::cli::{HyphenationArgs, HyphenationFormat};
+use crate::dict::{Probe, Quarantine, Selection, for_each_valid_entry, iter_datasets};
+use crate::models::kaikki::WordEntry;
+use crate::path::PathManager;
+use crate::progress;
+use crate::utils::{CHECK_C, pretty_println_at_path};
+
+pub fn hyphenation(args: HyphenationArgs) -> Result<()> {
+    let format = args.format;
+    let pm = PathManager::try_from(args)?;
+    let (_, source, _) = pm.langs();
+    let opts = &pm.opts;
+
+    pm.setup_dirs()?;
+
+    let capacity = 256 * (1 << 10);
+    let probe = |lang_code: &str| lang_code == source.as_ref();
+    let probe: Option<Probe> = Some(&probe);
+    let mut quarantine = Quarantine::for_pm(&pm);
+
+    let mut words = Set::default();
+    let mut hyphenated = Vec::new();
+
+    for pair in iter_datasets(&pm) {
+        let (_, dataset) = pair?;
+        let reader = dataset.open_reader(capacity, opts.quiet)?;
+        let dataset_path = dataset.display_path();
+
+        let mut selection = Selection::default();
+
+        let line_count =
+            for_each_valid_entry(reader, probe, &dataset_path, &mut quarantine, |_, entry| {
+                if entry.lang_code != source.as_ref() || !selection.keeps(&entry, opts) {
+                    return Ok(selection.flow());
+                }
+
+                // TeX takes a single hyphenation per word: the first one wins.
+                if !words.contains(&entry.word)
+                    && let Some(syllables) = hyphenate(&entry)
+                {
+                    words.insert(entry.word.clone());
+                    hyphenated.push(syllables.join("-"));
+                }
+
+                Ok(ControlFlow::Continue(()))
+            })?;
+
+        if !opts.quiet {
+            progress::println(&format!(
+                "Processed {line_count} lines. Accepted {} lines.",
+                selection.accepted_count()
+            ));
+        }
+    }
+
+    quarantine.finish()?;
+
+    if opts.output_stdout {
+        let stdout = std::io::stdout();
+        write_hyphenation(&mut stdout.lock(), format, &hyphenated)?;
+    } else {
+        let path = pm.path_hyphenation(match format {
+            HyphenationFormat::Plain => "txt",
+            HyphenationFormat::Tex => "tex",
+        });
+        let mut writer = BufWriter::new(File::create(&path)?);
+        write_hyphenation(&mut writer, format, &hyphenated)?;
+        writer.flush()?;
+
+        if !opts.quiet {
+            let msg = format!("{CHECK_C} Wrote {} hyphenations", hyphenated.len());
+            pretty_println_at_path(&msg, &path);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_hyphenation(
+    writer: &mut impl Write,
+    format: HyphenationFormat,
+    hyphenated: &[String],
+) -> Result<()> {
+    match format {
+        HyphenationFormat::Plain => {
+            for word in hyphenated {
+                writeln!(writer, "{word}")?;
+            }
+        }
+        HyphenationFormat::Tex => {
+            writeln!(writer, "\\hyphenation{{")?;
+            for word in hyphenated {
+                writeln!(writer, "{word}")?;
+            }
+            writeln!(writer, "}}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Split the word of `entry` at the syllables of its first usable hyphenation.
+///
+/// The syllables may carry stress marks that are not in the word: they are matched on their base
+/// letters, but the returned syllables are slices of the word itself.
+fn hyphenate(entry: &WordEntry) -> Option<Vec<String>> {
+    let word = &entry.word;
+    // A hyphen in the word would be read as a break point.
+    if word.contains(['-', ' ']) {
+        return None;
+    }
+    let base_word = base_letters(word);
+
+    entry.hyphenations().into_iter().find_map(|syllables| {
+        if syllables.len() < 2 {
+            return None;
+        }
+        let base_syllables: Vec<String> = syllables.iter().map(|s| base_letters(s)).collect();
+        if base_syllables.concat() != base_word {
+            return None;
+        }
+        Some(split_at_base_lengths(
+            word,
+            base_syllables.iter().map(|s| s.chars().count()),
+        ))
+    })
+}
+
+fn base_letters(s: &str) -> String {
+    s.nfd().filter(|&c| !is_combining_mark(c)).collect()
+}
+
+/// Split `word` in pieces of the given numbers of base letters, keeping its combining marks.
+fn split_at_base_lengths(word: &str, lengths: impl IntoIterator<Item = usize>) -> Vec<String> {
+    let mut lengths = lengths.into_iter();
+    let mut remaining = lengths.next().unwrap_or(usize::MAX);
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+
+    for c in word.nfd() {
+        if !is_combining_mark(c) {
+            if remaining == 0 {
+                pieces.push(piece.nfc().collect());
+                piece.clear();
+                remaining = lengths.next().unwrap_or(usize::MAX);
+            }
+            remaining -= 1;
+        }
+        piece.push(c);
+    }
+    pieces.push(piece.nfc().collect());
+
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(json: &str) -> WordEntry {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn hyphenate_words() {
+        let gegenueber =
+            entry(r#"{"word": "gegenüber", "hyphenations": [{"parts": ["ge", "gen", "über"]}]}"#);
+        assert_eq!(hyphenate(&gegenueber).unwrap(), ["ge", "gen", "über"]);
+
+        // Stress marks of the syllables are not in the word
+        let prodolzhat =
+            entry(r#"{"word": "продолжать", "hyphenations": [{"parts": ["про", "дол", "жа́ть"]}]}"#);
+        assert_eq!(hyphenate(&prodolzhat).unwrap(), ["про", "дол", "жать"]);
+
+        // Break points kept inside a part, and the legacy field
+        let vivir = entry(r#"{"word": "vivir", "hyphenation": ["vi‧vir"]}"#);
+        assert_eq!(hyphenate(&vivir).unwrap(), ["vi", "vir"]);
+        let korfos = entry(r#"{"word": "κόρφος", "hyphenation": "κόρ‐φος"}"#);
+        assert_eq!(hyphenate(&korfos).unwrap(), ["κόρ", "φος"]);
+
+        // Nothing to hyphenate, or syllables that do not spell the word
+        let wain = entry(r#"{"word": "wain", "hyphenations": [{"parts": ["wain"]}]}"#);
+        assert!(hyphenate(&wain).is_none());
+        let wrong = entry(r#"{"word": "fissa", "hyphenations": [{"parts": ["fis", "se"]}]}"#);
+        assert!(hyphenate(&wrong).is_none());
+    }
+
+    #[test]
+    fn unknown_legacy_hyphenations_are_empty() {
+        for hyphenation in ["null", "{}", r#"{"parts": ["vi", "vir"]}"#, "[1, 2]", "3"] {
+            let json = format!(r#"{{"word": "vivir", "hyphenation": {hyphenation}}}"#);
+            let vivir: WordEntry = serde_json::from_str(&json).unwrap();
+            assert!(vivir.hyphenations().is_empty(), "{hyphenation}");
+        }
+    }
+
+    #[test]
+    fn write_tex() {
+        let hyphenated = ["ge-gen-über".to_string(), "vi-vir".to_string()];
+        let mut out = Vec::new();
+        write_hyphenation(&mut out, HyphenationFormat::Tex, &hyphenated).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\\hyphenation{\nge-gen-über\nvi-vir\n}\n"
+        );
+    }
+}
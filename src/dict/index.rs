@@ -4,9 +4,12 @@ const BASE_URL: &str = "https://huggingface.co/datasets/daxida/wty-release/resol
 
 // Helper function to sync index with the file tree.
 // It is sort of a kludge due to the fact that write_yomitan expects a source: Lang
-fn source_str<'a>(dict_ty: DictionaryType, source: &'a Lang) -> &'a str {
+fn source_str(dict_ty: DictionaryType, source: &Lang) -> &str {
     match dict_ty {
-        DictionaryType::Main | DictionaryType::Ipa | DictionaryType::Glossary => source.as_ref(),
+        DictionaryType::Main
+        | DictionaryType::Ipa
+        | DictionaryType::Glossary
+        | DictionaryType::Custom(_) => source.as_ref(),
         DictionaryType::IpaMerged => "all",
         _ => unimplemented!(),
     }
@@ -50,33 +53,42 @@ fn index_url(dict_name_expanded: &str) -> String {
 /// indexUrl points to a separate copy of the index in the download repository.
 /// downloadUrl points to the download link in the download repository.
 ///
+/// Custom dictionaries are never released, so they are not updatable and have neither url.
+///
 /// <https://github.com/yomidevs/yomitan/blob/master/ext/data/schemas/dictionary-index-schema.json>
 pub fn get_index(
     dict_ty: DictionaryType,
     dict_name_expanded: &str,
     source: Lang,
     target: Lang,
+    revision: &str,
 ) -> String {
-    let current_date = chrono::Utc::now().format("%Y.%m.%d"); // needs to be dot separated
-    let index_url = index_url(dict_name_expanded);
-    let download_url = download_url(dict_ty, dict_name_expanded, source, target);
+    let update_fields = if matches!(dict_ty, DictionaryType::Custom(_)) {
+        String::new()
+    } else {
+        let index_url = index_url(dict_name_expanded);
+        let download_url = download_url(dict_ty, dict_name_expanded, source, target);
+        format!(
+            r#",
+  "isUpdatable": true,
+  "indexUrl": "{index_url}",
+  "downloadUrl": "{download_url}""#
+        )
+    };
     let source_str = source_str(dict_ty, &source);
 
     format!(
         r#"{{
   "title": "{dict_name_expanded}",
   "format": 3,
-  "revision": "{current_date}",
+  "revision": "{revision}",
   "sequenced": true,
   "author": "wty contributors",
   "url": "https://github.com/daxida/wty",
   "description": "Dictionaries for various language pairs generated from Wiktionary data, via Kaikki and wty.",
   "attribution": "https://kaikki.org/",
   "sourceLanguage": "{source_str}",
-  "targetLanguage": "{target}",
-  "isUpdatable": true,
-  "indexUrl": "{index_url}",
-  "downloadUrl": "{download_url}"
+  "targetLanguage": "{target}"{update_fields}
 }}"#
     )
 }
@@ -108,4 +120,22 @@ mod tests {
             "https://huggingface.co/datasets/daxida/wty-release/resolve/main/latest/index/wty-afb-en-ipa-index.json?download=true"
         );
     }
+
+    #[test]
+    fn custom_index_is_not_updatable() {
+        let parse = |dict_ty| -> serde_json::Value {
+            let index = get_index(dict_ty, "wty-de-en", Lang::De, Lang::En, "rev");
+            serde_json::from_str(&index).unwrap()
+        };
+
+        let main = parse(DictionaryType::Main);
+        assert_eq!(main["isUpdatable"], true);
+        assert!(main["indexUrl"].is_string());
+
+        let custom = parse(DictionaryType::Custom("mini"));
+        assert_eq!(custom["sourceLanguage"], "de");
+        for key in ["isUpdatable", "indexUrl", "downloadUrl"] {
+            assert!(custom.get(key).is_none(), "{key}");
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use std::{fs::File, io::BufWriter, sync::LazyLock};
+use std::{fs::File, io::BufWriter, path::Path, sync::LazyLock};
 
 use anyhow::Result;
 use indexmap::map::Entry;
@@ -11,19 +11,23 @@ use crate::{
     cli::{LangSpecs, MainArgs, Options},
     dict::{
         Dictionary, Intermediate, LabelledYomitanEntry, Langs, locale::localize_examples_string,
+        spill::Runs,
     },
     lang::{Edition, Lang},
     models::{
-        kaikki::{Example, Form, HeadTemplate, Pos, Sense, Tag, WordEntry},
+        kaikki::{
+            Descendant, EtymologyStep, Example, Form, HeadTemplate, Linkage, Pos, Sense, Tag,
+            WordEntry,
+        },
         yomitan::{
             BacklinkContent, BacklinkContentKind, DetailedDefinition, GenericNode, NTag, Node,
-            NodeData, TermBank, TermBankSimplified, YomitanEntry, wrap,
+            NodeData, QueryLink, TermBank, TermBankSimplified, YomitanEntry, wrap,
         },
     },
     path::PathManager,
     tags::{
-        REDUNDANT_FORM_TAGS, find_short_pos_or_default, find_tag_in_bank, merge_person_tags,
-        remove_redundant_tags, sort_tags, sort_tags_by_similar,
+        REDUNDANT_FORM_TAGS, find_category_tag, find_short_pos_or_default, find_tag_in_bank,
+        merge_person_tags, remove_redundant_tags, sort_tags, sort_tags_by_similar,
     },
     utils::{link_kaikki, link_wiktionary, pretty_println_at_path},
 };
@@ -104,8 +108,31 @@ pub mod heap {
             self.gloss_tree.heap_size()
                 + self.etymology_text.as_ref().map_or(0, HeapSize::heap_size)
                 + self.head_info_text.as_ref().map_or(0, HeapSize::heap_size)
+                + self.hyphenation.as_ref().map_or(0, HeapSize::heap_size)
                 + self.link_wiktionary.heap_size()
                 + self.link_kaikki.heap_size()
+                + self.relations.heap_size()
+                + self.derived_terms.heap_size()
+                + self.etymology_chain.heap_size()
+                + self.descendants.heap_size()
+        }
+    }
+
+    impl HeapSize for EtymologyStep {
+        fn heap_size(&self) -> usize {
+            self.lang.heap_size() + self.lang_code.heap_size() + self.word.heap_size()
+        }
+    }
+
+    impl HeapSize for Descendant {
+        fn heap_size(&self) -> usize {
+            self.lang.heap_size()
+                + self.lang_code.heap_size()
+                + self.word.heap_size()
+                + self.roman.heap_size()
+                + self.tags.heap_size()
+                + self.raw_tags.heap_size()
+                + self.descendants.heap_size()
         }
     }
 
@@ -114,10 +141,21 @@ pub mod heap {
             self.tags.heap_size()
                 + self.topics.heap_size()
                 + self.examples.heap_size()
+                + self.relations.heap_size()
                 + self.children.heap_size()
         }
     }
 
+    impl HeapSize for Relations {
+        fn heap_size(&self) -> usize {
+            self.synonyms.heap_size()
+                + self.antonyms.heap_size()
+                + self.hypernyms.heap_size()
+                + self.hyponyms.heap_size()
+                + self.related.heap_size()
+        }
+    }
+
     impl HeapSize for LemmaMap {
         fn heap_size(&self) -> usize {
             self.0.heap_size()
@@ -222,6 +260,7 @@ pub mod heap {
                 Self::Array(v) => v.heap_size(),
                 Self::Generic(boxed) => boxed.heap_size(),
                 Self::Backlink(bl) => bl.heap_size(),
+                Self::Query(ql) => ql.0.heap_size(),
             }
         }
     }
@@ -270,6 +309,15 @@ impl Intermediate for Tidy {
         self.len()
     }
 
+    // Kept up to date by the inserts: walking the maps at every check would be too slow.
+    fn memory_usage(&self) -> usize {
+        self.heap_estimate
+    }
+
+    fn spill(&mut self, dir: &Path, limit: usize) -> Result<()> {
+        self.spill(dir, limit)
+    }
+
     fn write(&self, pm: &PathManager) -> Result<()> {
         self.write(pm)
     }
@@ -341,18 +389,28 @@ impl Dictionary for DMain {
     }
 
     fn postprocess(&self, irs: &mut Self::I) {
-        postprocess_forms(&mut irs.form_map);
+        // Spilled forms are postprocessed once merged, cf. `to_yomitan_spilled`
+        if irs.spilled.is_none() {
+            postprocess_forms(&mut irs.form_map);
+        }
     }
 
-    fn to_yomitan(&self, langs: LangSpecs, irs: Self::I) -> Vec<LabelledYomitanEntry> {
-        vec![
-            LabelledYomitanEntry::new("lemma", to_yomitan_lemmas(langs.target, irs.lemma_map)),
-            LabelledYomitanEntry::new("form", to_yomitan_forms(langs.source, irs.form_map)),
-        ]
+    fn to_yomitan(&self, langs: LangSpecs, mut irs: Self::I) -> Result<Vec<LabelledYomitanEntry>> {
+        if let Some(spilled) = irs.spilled.take() {
+            return to_yomitan_spilled(langs, irs, spilled);
+        }
+
+        let (n_lemmas, n_forms) = (irs.lemma_map.len(), irs.form_map.len());
+        Ok(vec![
+            LabelledYomitanEntry::new("lemma", to_yomitan_lemmas(langs.target, irs.lemma_map))
+                .with_len(n_lemmas),
+            LabelledYomitanEntry::new("form", to_yomitan_forms(langs.source, irs.form_map))
+                .with_len(n_forms),
+        ])
     }
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 struct LemmaKey {
     lemma: String,
     reading: String,
@@ -403,7 +461,7 @@ impl LemmaMap {
     }
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 struct FormKey {
     uninflected: String,
     inflected: String,
@@ -421,8 +479,8 @@ impl Serialize for FormMap {
     where
         S: serde::Serializer,
     {
-        let mut nested: Map<&str, Map<&str, Map<&str, &(FormSource, Vec<String>)>>> =
-            Map::default();
+        type Nested<'a> = Map<&'a str, Map<&'a str, Map<&'a str, &'a (FormSource, Vec<String>)>>>;
+        let mut nested: Nested = Map::default();
 
         for (key, infos) in &self.0 {
             nested
@@ -518,14 +576,44 @@ struct LemmaInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     etymology_text: Option<String>,
 
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    etymology_chain: Vec<EtymologyStep>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     head_info_text: Option<String>,
 
+    // Syllabified headword: "ge‧gen‧über"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hyphenation: Option<String>,
+
     #[serde(rename = "wlink")]
     link_wiktionary: String,
 
     #[serde(rename = "klink")]
     link_kaikki: String,
+
+    // Entry level relations, the sense level ones being in the gloss tree
+    #[serde(default, skip_serializing_if = "Relations::is_empty")]
+    relations: Relations,
+
+    // Derived terms and compounds
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    derived_terms: Vec<String>,
+
+    // Empty unless `--descendants`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    descendants: Vec<Descendant>,
+}
+
+impl LemmaInfo {
+    fn has_preamble(&self) -> bool {
+        self.etymology_text.is_some()
+            || !self.etymology_chain.is_empty()
+            || self.head_info_text.is_some()
+            || self.hyphenation.is_some()
+            || !self.derived_terms.is_empty()
+            || !self.descendants.is_empty()
+    }
 }
 
 type GlossTree = Map<String, GlossInfo>;
@@ -543,20 +631,140 @@ struct GlossInfo {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     examples: Vec<Example>,
 
+    #[serde(skip_serializing_if = "Relations::is_empty")]
+    relations: Relations,
+
     #[serde(skip_serializing_if = "Map::is_empty")]
     children: GlossTree,
 }
 
+/// Synonyms, antonyms etc. of a sense or of a whole entry.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+struct Relations {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    synonyms: Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    antonyms: Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hypernyms: Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hyponyms: Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    related: Vec<String>,
+}
+
+/// Collect the linked words, without duplicates nor the word itself.
+fn linked_words<'a>(word: &str, linkages: impl IntoIterator<Item = &'a Linkage>) -> Vec<String> {
+    let words: Set<&str> = linkages
+        .into_iter()
+        .map(|linkage| linkage.word.trim())
+        .filter(|&w| !w.is_empty() && w != word)
+        .collect();
+    words.into_iter().map(String::from).collect()
+}
+
+impl Relations {
+    fn new(word: &str, linkages: [&[Linkage]; 5]) -> Self {
+        let [synonyms, antonyms, hypernyms, hyponyms, related] =
+            linkages.map(|linkages| linked_words(word, linkages));
+        Self {
+            synonyms,
+            antonyms,
+            hypernyms,
+            hyponyms,
+            related,
+        }
+    }
+
+    fn of_entry(entry: &WordEntry) -> Self {
+        Self::new(
+            &entry.word,
+            [
+                &entry.synonyms,
+                &entry.antonyms,
+                &entry.hypernyms,
+                &entry.hyponyms,
+                &entry.related,
+            ],
+        )
+    }
+
+    fn of_sense(word: &str, sense: &Sense) -> Self {
+        Self::new(
+            word,
+            [
+                &sense.synonyms,
+                &sense.antonyms,
+                &sense.hypernyms,
+                &sense.hyponyms,
+                &sense.related,
+            ],
+        )
+    }
+
+    /// Iterate over the non-empty kinds, with their label.
+    fn iter(&self) -> impl Iterator<Item = (&'static str, &[String])> {
+        [
+            ("synonyms", &self.synonyms),
+            ("antonyms", &self.antonyms),
+            ("hypernyms", &self.hypernyms),
+            ("hyponyms", &self.hyponyms),
+            ("related", &self.related),
+        ]
+        .into_iter()
+        .filter(|(_, words)| !words.is_empty())
+        .map(|(label, words)| (label, words.as_slice()))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
 /// Intermediate representation of the main dictionary.
 #[derive(Debug, Default)]
 pub struct Tidy {
     lemma_map: LemmaMap, // 56
     form_map: FormMap,   // 56
+    spilled: Option<Spilled>,
+    /// Running estimate of the heap of both maps, kept by the inserts, cf. `memory_usage`.
+    heap_estimate: usize,
+}
+
+/// What a `Tidy` moved to disk to stay under `--memory-limit`.
+#[derive(Debug)]
+struct Spilled {
+    limit: usize,
+    lemmas: Runs<LemmaKey, Vec<LemmaInfo>>,
+    forms: Runs<FormKey, (FormSource, Vec<Tag>)>,
 }
 
 impl Tidy {
+    // Approximate once spilled: a key spilled several times is counted several times.
     fn len(&self) -> usize {
-        self.lemma_map.len() + self.form_map.len()
+        let spilled = self
+            .spilled
+            .as_ref()
+            .map_or(0, |spilled| spilled.lemmas.len() + spilled.forms.len());
+        self.lemma_map.len() + self.form_map.len() + spilled
+    }
+
+    /// Move both maps to disk as new runs. They are merged back by `to_yomitan_spilled`.
+    fn spill(&mut self, dir: &Path, limit: usize) -> Result<()> {
+        let spilled = self.spilled.get_or_insert_with(|| Spilled {
+            limit,
+            lemmas: Runs::new(dir, "lemmas"),
+            forms: Runs::new(dir, "forms"),
+        });
+        spilled.lemmas.push(std::mem::take(&mut self.lemma_map.0))?;
+        spilled.forms.push(std::mem::take(&mut self.form_map.0))?;
+        self.heap_estimate = 0;
+        Ok(())
     }
 
     // This is usually called at the end, so it could just move the arguments...
@@ -569,8 +777,10 @@ impl Tidy {
             pos: pos.into(),
         };
 
+        self.heap_estimate += size_of::<LemmaInfo>() + entry.heap_size();
         match self.lemma_map.0.entry(key) {
             Entry::Vacant(e) => {
+                self.heap_estimate += size_of::<(LemmaKey, Vec<LemmaInfo>)>() + e.key().heap_size();
                 e.insert(vec![entry]);
             }
             Entry::Occupied(mut e) => {
@@ -596,8 +806,12 @@ impl Tidy {
             pos: pos.into(),
         };
 
+        self.heap_estimate += tags.heap_size();
         match self.form_map.0.entry(key) {
             Entry::Vacant(e) => {
+                self.heap_estimate += size_of::<(FormKey, (FormSource, Vec<Tag>))>()
+                    + e.key().heap_size()
+                    + source.heap_size();
                 e.insert((source, tags));
             }
             Entry::Occupied(mut e) => {
@@ -609,6 +823,13 @@ impl Tidy {
     // NOTE: we write stuff even if irs.attribute is empty
     #[tracing::instrument(skip_all)]
     fn write(&self, pm: &PathManager) -> Result<()> {
+        if self.spilled.is_some() {
+            tracing::warn!(
+                "Not writing tidy files: they were spilled to disk (cf. --memory-limit)"
+            );
+            return Ok(());
+        }
+
         let opath = pm.path_lemmas();
         let file = File::create(&opath)?;
         let writer = BufWriter::new(file);
@@ -641,21 +862,25 @@ impl Tidy {
 
 fn postprocess_forms(form_map: &mut FormMap) {
     for (_, _, _, _, tags) in form_map.flat_iter_mut() {
-        // Keep only unique tags and remove tags subsets
-        remove_redundant_tags(tags);
+        postprocess_form_tags(tags);
+    }
+}
 
-        // Merge person tags
-        merge_person_tags(tags);
+fn postprocess_form_tags(tags: &mut Vec<Tag>) {
+    // Keep only unique tags and remove tags subsets
+    remove_redundant_tags(tags);
 
-        // Sort inner words
-        for tag in tags.iter_mut() {
-            let mut words: Vec<&str> = tag.split(' ').collect();
-            sort_tags(&mut words);
-            *tag = words.join(" ");
-        }
+    // Merge person tags
+    merge_person_tags(tags);
 
-        sort_tags_by_similar(tags);
+    // Sort inner words
+    for tag in tags.iter_mut() {
+        let mut words: Vec<&str> = tag.split(' ').collect();
+        sort_tags(&mut words);
+        *tag = words.join(" ");
     }
+
+    sort_tags_by_similar(tags);
 }
 
 fn process_main(edition: Edition, source: Lang, entry: &WordEntry, irs: &mut Tidy) {
@@ -794,6 +1019,36 @@ fn preprocess_main(
     }
     entry.senses = senses_without_inflections;
 
+    // WARN: mutates entry::senses, entry::forms and entry::alt_of
+    //
+    // Done after dealing with inflections: those senses are forms, not definitions.
+    let pruned = prune_senses(entry, opts);
+    if entry.senses.is_empty() && !pruned.is_empty() {
+        redirect_pruned(entry, &pruned, irs);
+    }
+
+    // WARN: mutates entry::senses::tags
+    //
+    // Done after pruning: categories already served there.
+    if opts.category_tags {
+        for sense in &mut entry.senses {
+            for category in entry.categories.iter().chain(&sense.categories) {
+                if let Some(tag) = find_category_tag(category)
+                    && !sense.tags.contains(&tag)
+                {
+                    sense.tags.push(tag);
+                }
+            }
+        }
+    }
+
+    // WARN: mutates entry::descendants
+    //
+    // Opt-in: they are long, and mostly of interest for historical languages.
+    if !opts.descendants {
+        entry.descendants.clear();
+    }
+
     // WARN: mutates entry::senses::glosses
     //
     // rg: full stop
@@ -812,13 +1067,84 @@ fn preprocess_main(
     }
 }
 
+/// Drop the senses excluded by `--drop-sense-tags`, `--drop-sense-topics`, `--keep-sense-topics`
+/// and their categories counterparts, and return them. Tags, topics and categories of the entry
+/// count for every sense.
+fn prune_senses(entry: &mut WordEntry, opts: &Options) -> Vec<Sense> {
+    if opts.drop_sense_tags.is_empty()
+        && opts.drop_sense_topics.is_empty()
+        && opts.keep_sense_topics.is_empty()
+        && opts.drop_sense_categories.is_empty()
+        && opts.keep_sense_categories.is_empty()
+    {
+        return Vec::new();
+    }
+
+    let senses = std::mem::take(&mut entry.senses);
+    let (kept, pruned) = senses
+        .into_iter()
+        .partition(|sense| keeps_sense(entry, sense, opts));
+    entry.senses = kept;
+
+    pruned
+}
+
+fn keeps_sense(entry: &WordEntry, sense: &Sense, opts: &Options) -> bool {
+    let tagged = |tags: &[String]| {
+        let mut sense_tags = sense.tags.iter().chain(&entry.tags);
+        sense_tags.any(|tag| tags.contains(tag))
+    };
+    let about = |topics: &[String]| {
+        let mut sense_topics = sense.topics.iter().chain(&entry.topics);
+        sense_topics.any(|topic| topics.contains(topic))
+    };
+    let categorized = |categories: &[String]| {
+        let mut sense_categories = sense.categories.iter().chain(&entry.categories);
+        sense_categories.any(|category| {
+            // "en:Medicine" is matched by "Medicine"
+            let unprefixed = category.split_once(':').map(|(_, rest)| rest);
+            categories
+                .iter()
+                .any(|c| c == category || Some(c.as_str()) == unprefixed)
+        })
+    };
+
+    !tagged(&opts.drop_sense_tags)
+        && !about(&opts.drop_sense_topics)
+        && (opts.keep_sense_topics.is_empty() || about(&opts.keep_sense_topics))
+        && !categorized(&opts.drop_sense_categories)
+        && (opts.keep_sense_categories.is_empty() || categorized(&opts.keep_sense_categories))
+}
+
+/// Redirect an entry whose senses were all pruned to the words it is a form of.
+///
+/// Its own forms are dropped: there is no lemma left for them to point to.
+fn redirect_pruned(entry: &mut WordEntry, pruned: &[Sense], irs: &mut Tidy) {
+    let tags = vec![format!("redirected from {}", entry.word)];
+    let form_of = entry
+        .form_of
+        .iter()
+        .chain(pruned.iter().flat_map(|sense| &sense.form_of))
+        .map(|form| (form, FormSource::Inflection));
+    let alt_of = entry
+        .alt_of
+        .iter()
+        .chain(pruned.iter().flat_map(|sense| &sense.alt_of))
+        .map(|form| (form, FormSource::AltOf));
+
+    for (form, source) in form_of.chain(alt_of) {
+        if !form.word.is_empty() && form.word != entry.word {
+            irs.insert_form(&form.word, &entry.word, &entry.pos, source, tags.clone());
+        }
+    }
+
+    entry.forms.clear();
+    entry.alt_of.clear();
+}
+
 /// Add Extracted forms. That is, forms from `entry.forms`.
 fn process_forms(edition: Edition, source: Lang, entry: &WordEntry, irs: &mut Tidy) {
     for form in entry.non_trivial_forms() {
-        if should_break_at_finish_forms(edition, source, form) {
-            break;
-        }
-
         if should_skip_form(edition, source, form) {
             continue;
         }
@@ -869,12 +1195,8 @@ fn should_skip_form(edition: Edition, source: Lang, form: &Form) -> bool {
                 return true;
             }
         }
-        (Edition::En, Lang::Ja) => {
-            // Skip transliterations: "hashireru" etc.
-            if is_roman(&form.form) {
-                return true;
-            }
-        }
+        // Skip transliterations: "hashireru" etc.
+        (Edition::En, Lang::Ja) if is_roman(&form.form) => return true,
         _ => (),
     }
     false
@@ -884,27 +1206,6 @@ const fn is_roman(form: &str) -> bool {
     form.is_ascii()
 }
 
-// Finnish from the English edition crashes with out-of-memory.
-// There are simply too many forms, so we prune the less used (possessive).
-//
-// https://uusikielemme.fi/finnish-grammar/possessive-suffixes-possessiivisuffiksit#one
-fn should_break_at_finish_forms(edition: Edition, source: Lang, form: &Form) -> bool {
-    if matches!((edition, source), (Edition::En, Lang::Fi)) {
-        // HACK: 1. For tables that parse the title
-        // https://kaikki.org/dictionary/Finnish/meaning/p/p%C3%A4/p%C3%A4%C3%A4.html
-        if form.form == "See the possessive forms below." {
-            return true;
-        }
-        // HACK: 2. For tables that don't parse the title
-        // https://kaikki.org/dictionary/Finnish/meaning/i/is/iso.html
-        // https://github.com/tatuylonen/wiktextract/issues/1565
-        if form.form == "Rare. Only used with substantive adjectives." {
-            return true;
-        }
-    }
-    false
-}
-
 /// Add `AltOf` forms. That is, alternative forms.
 fn process_alt_forms(entry: &WordEntry, irs: &mut Tidy) {
     let base_tags = vec!["alt-of".to_string()];
@@ -937,27 +1238,23 @@ fn process_alt_forms(entry: &WordEntry, irs: &mut Tidy) {
 
 /// Process "no-gloss" word entries for alternative ways of adding lemmas/forms.
 fn process_no_gloss(edition: Edition, entry: &WordEntry, irs: &mut Tidy) {
-    match edition {
-        // Unfortunately we are in the same A from B, B from C situation discussed in
-        // preprocess_entry. There is no easy solution for adding the lemma back because at
-        // this point the gloss has been deleted. Maybe reconsider the original approach of
-        // deleting glosses, and mark them somehow as "inflection-only".
-        //
-        // At any rate, this will still add useful redirections.
-        Edition::El => {
-            if entry.is_participle()
-                && let Some(form_of) = entry.form_of.first()
-            {
-                irs.insert_form(
-                    &form_of.word,
-                    &entry.word,
-                    &entry.pos,
-                    FormSource::Inflection,
-                    vec![format!("redirected from {}", entry.word)],
-                );
-            }
-        }
-        _ => (),
+    // Unfortunately we are in the same A from B, B from C situation discussed in
+    // preprocess_entry. There is no easy solution for adding the lemma back because at
+    // this point the gloss has been deleted. Maybe reconsider the original approach of
+    // deleting glosses, and mark them somehow as "inflection-only".
+    //
+    // At any rate, this will still add useful redirections.
+    if edition == Edition::El
+        && entry.is_participle()
+        && let Some(form_of) = entry.form_of.first()
+    {
+        irs.insert_form(
+            &form_of.word,
+            &entry.word,
+            &entry.pos,
+            FormSource::Inflection,
+            vec![format!("redirected from {}", entry.word)],
+        );
     }
 }
 
@@ -1055,14 +1352,30 @@ fn process_entry(edition: Edition, source: Lang, entry: &WordEntry) -> LemmaInfo
         etymology_text: entry
             .etymology_texts()
             .map(|etymology_text| etymology_text.join("\n")),
+        etymology_chain: entry.etymology_chain(),
         head_info_text: get_head_info(&entry.head_templates).map(String::from),
+        hyphenation: get_hyphenation(entry),
         link_wiktionary: link_wiktionary(edition, source, &entry.word),
         link_kaikki: link_kaikki(edition, source, &entry.word),
+        relations: Relations::of_entry(entry),
+        derived_terms: linked_words(&entry.word, entry.derived.iter().chain(&entry.compounds)),
+        descendants: entry.descendants.clone(),
     }
 }
 
 static PARENS_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\(.+?\)").unwrap());
 
+/// Alternatives are separated by a slash. Monosyllables are skipped: there is nothing to show.
+fn get_hyphenation(entry: &WordEntry) -> Option<String> {
+    let hyphenations: Vec<_> = entry
+        .hyphenations()
+        .into_iter()
+        .filter(|syllables| syllables.len() > 1)
+        .map(|syllables| syllables.join("‧"))
+        .collect();
+    (!hyphenations.is_empty()).then(|| hyphenations.join(" / "))
+}
+
 // rg: getheadinfo
 fn get_head_info(head_templates: &[HeadTemplate]) -> Option<&str> {
     head_templates.iter().find_map(|head_template| {
@@ -1096,6 +1409,7 @@ fn get_gloss_tree(entry: &WordEntry) -> GlossTree {
             &sense.tags,
             &sense.topics,
             &filtered_examples,
+            &Relations::of_sense(&entry.word, sense),
         );
     }
 
@@ -1109,6 +1423,7 @@ fn insert_glosses(
     tags: &[Tag],
     topics: &[Tag],
     examples: &[Example],
+    relations: &Relations,
 ) {
     let Some(head) = glosses.first() else {
         return;
@@ -1132,13 +1447,14 @@ fn insert_glosses(
             .collect();
     }
 
-    // assign examples to the last level
+    // assign examples and relations to the last level
     if tail.is_empty() {
         node.examples = examples.to_vec();
+        node.relations = relations.clone();
         return;
     }
 
-    insert_glosses(&mut node.children, tail, tags, topics, examples);
+    insert_glosses(&mut node.children, tail, tags, topics, examples, relations);
 }
 
 static DE_INFLECTION_RE: LazyLock<Regex> = LazyLock::new(|| {
@@ -1390,13 +1706,12 @@ fn normalize_orthography(source: Lang, word: &str) -> String {
 }
 
 #[tracing::instrument(skip_all, level = "trace")]
-fn to_yomitan_lemmas(target: Lang, lemma_map: LemmaMap) -> Vec<YomitanEntry> {
+fn to_yomitan_lemmas(target: Lang, lemma_map: LemmaMap) -> impl Iterator<Item = YomitanEntry> {
     lemma_map
         .into_flat_iter()
         .map(move |(lemma, reading, pos, info)| {
             to_yomitan_lemma(target, &lemma, &reading, &pos, info)
         })
-        .collect()
 }
 
 // TODO: consume info
@@ -1415,11 +1730,8 @@ fn to_yomitan_lemma(
 
     let mut detailed_definition_content = Node::new_array();
 
-    if info.etymology_text.is_some() || info.head_info_text.is_some() {
-        detailed_definition_content.push(structured_preamble(
-            info.etymology_text,
-            info.head_info_text,
-        ));
+    if info.has_preamble() {
+        detailed_definition_content.push(structured_preamble(&info));
     }
 
     detailed_definition_content.push(structured_glosses(
@@ -1428,6 +1740,10 @@ fn to_yomitan_lemma(
         &common_short_tags_found,
     ));
 
+    if !info.relations.is_empty() {
+        detailed_definition_content.push(structured_relations(&info.relations));
+    }
+
     detailed_definition_content.push(structured_backlink(info.link_wiktionary, info.link_kaikki));
 
     YomitanEntry::TermBank(TermBank(
@@ -1479,13 +1795,46 @@ fn build_details_entry(ty: &str, content: String) -> Node {
     )
 }
 
-fn structured_preamble(etymology_text: Option<String>, head_info_text: Option<String>) -> Node {
+fn structured_preamble(info: &LemmaInfo) -> Node {
     let mut preamble_content = Node::new_array();
-    if let Some(head_info_text) = head_info_text {
-        preamble_content.push(build_details_entry("Grammar", head_info_text));
+    if let Some(hyphenation) = &info.hyphenation {
+        preamble_content.push(wrap(
+            NTag::Div,
+            "hyphenation",
+            Node::Text(hyphenation.clone()),
+        ));
     }
-    if let Some(etymology_text) = etymology_text {
-        preamble_content.push(build_details_entry("Etymology", etymology_text));
+    if let Some(head_info_text) = &info.head_info_text {
+        preamble_content.push(build_details_entry("Grammar", head_info_text.clone()));
+    }
+    if !info.etymology_chain.is_empty() {
+        preamble_content.push(structured_etymology(
+            &info.etymology_chain,
+            info.etymology_text.as_deref(),
+        ));
+    } else if let Some(etymology_text) = &info.etymology_text {
+        preamble_content.push(build_details_entry("Etymology", etymology_text.clone()));
+    }
+    if !info.derived_terms.is_empty() {
+        preamble_content.push(structured_derived_terms(&info.derived_terms));
+    }
+    if !info.descendants.is_empty() {
+        preamble_content.push(wrap(
+            NTag::Details,
+            "details-entry-Descendants",
+            Node::Array(vec![
+                wrap(
+                    NTag::Summary,
+                    "summary-entry",
+                    Node::Text("Descendants".into()),
+                ),
+                wrap(
+                    NTag::Div,
+                    "Descendants-content",
+                    structured_descendants(&info.descendants),
+                ),
+            ]),
+        ));
     }
 
     wrap(
@@ -1495,6 +1844,120 @@ fn structured_preamble(etymology_text: Option<String>, head_info_text: Option<St
     )
 }
 
+/// The ancestry as a nested list, each step under the previous one, then the full text that also
+/// has the cognates etc.
+fn structured_etymology(etymology_chain: &[EtymologyStep], etymology_text: Option<&str>) -> Node {
+    let mut chain = None;
+    for step in etymology_chain.iter().rev() {
+        let mut step_content = Node::Array(vec![
+            Node::Text(format!("{} from ", step.relation.as_str())),
+            wrap(NTag::Span, "etymology-lang", Node::Text(step.lang.clone())),
+        ]);
+        if !step.word.is_empty() {
+            step_content.push(Node::Text(" ".into()));
+            step_content.push(wrap(
+                NTag::Span,
+                "etymology-word",
+                Node::Text(step.word.clone()),
+            ));
+        }
+        if let Some(previous) = chain {
+            step_content.push(previous);
+        }
+        chain = Some(wrap(
+            NTag::Ul,
+            "etymology-chain",
+            wrap(NTag::Li, "", step_content),
+        ));
+    }
+
+    let mut etymology_content = Node::new_array();
+    if let Some(chain) = chain {
+        etymology_content.push(chain);
+    }
+    if let Some(etymology_text) = etymology_text {
+        etymology_content.push(wrap(
+            NTag::Div,
+            "etymology-text",
+            Node::Text(etymology_text.to_string()),
+        ));
+    }
+
+    wrap(
+        NTag::Details,
+        "details-entry-Etymology",
+        Node::Array(vec![
+            wrap(
+                NTag::Summary,
+                "summary-entry",
+                Node::Text("Etymology".into()),
+            ),
+            wrap(NTag::Div, "Etymology-content", etymology_content),
+        ]),
+    )
+}
+
+/// Recursive helper: one list per level of descendants.
+fn structured_descendants(descendants: &[Descendant]) -> Node {
+    let items = descendants
+        .iter()
+        .filter(|descendant| !descendant.word.is_empty() || !descendant.descendants.is_empty())
+        .map(|descendant| {
+            let mut item_content = Node::Array(vec![wrap(
+                NTag::Span,
+                "descendant-lang",
+                Node::Text(format!("{}: ", descendant.lang)),
+            )]);
+            if !descendant.word.is_empty() {
+                item_content.push(wrap(
+                    NTag::Span,
+                    "descendant-word",
+                    Node::Text(descendant.word.clone()),
+                ));
+            }
+            if !descendant.roman.is_empty() {
+                item_content.push(Node::Text(format!(" ({})", descendant.roman)));
+            }
+            if !descendant.descendants.is_empty() {
+                item_content.push(structured_descendants(&descendant.descendants));
+            }
+            wrap(NTag::Li, "", item_content)
+        })
+        .collect();
+
+    wrap(NTag::Ul, "descendants", Node::Array(items))
+}
+
+/// Past this, derived terms are truncated: some words have hundreds of them.
+const MAX_DERIVED_TERMS: usize = 30;
+
+fn structured_derived_terms(derived_terms: &[String]) -> Node {
+    let mut derived_terms_content = Node::new_array();
+    for (idx, word) in derived_terms.iter().take(MAX_DERIVED_TERMS).enumerate() {
+        if idx > 0 {
+            derived_terms_content.push(Node::Text(", ".into()));
+        }
+        derived_terms_content.push(Node::Query(QueryLink(word.clone())));
+    }
+    if derived_terms.len() > MAX_DERIVED_TERMS {
+        let rest = derived_terms.len() - MAX_DERIVED_TERMS;
+        derived_terms_content.push(Node::Text(format!(" (+{rest} more)")));
+    }
+
+    wrap(
+        NTag::Details,
+        "details-entry-Derived",
+        Node::Array(vec![
+            wrap(
+                NTag::Summary,
+                "summary-entry",
+                Node::Text(format!("Derived terms ({})", derived_terms.len())),
+            ),
+            wrap(NTag::Div, "Derived-content", derived_terms_content),
+        ]),
+    )
+}
+
 fn structured_backlink(wlink: String, klink: String) -> Node {
     wrap(
         NTag::Div,
@@ -1567,6 +2030,10 @@ fn structured_glosses_go(
             level_content.push(structured_examples(target, &gloss_info.examples));
         }
 
+        if !gloss_info.relations.is_empty() {
+            level_content.push(structured_relations(&gloss_info.relations));
+        }
+
         nested.push(wrap(html_tag, "", level_content));
 
         if gloss_info.children.is_empty() {
@@ -1679,32 +2146,440 @@ fn structured_examples(target: Lang, examples: &[Example]) -> Node {
     )
 }
 
+fn structured_relations(relations: &Relations) -> Node {
+    debug_assert!(!relations.is_empty());
+
+    let labels: Vec<_> = relations.iter().map(|(label, _)| label).collect();
+    let mut structured_relations_content = wrap(
+        NTag::Summary,
+        "summary-entry",
+        Node::Text(labels.join(", ")),
+    )
+    .into_array_node();
+
+    for (label, words) in relations.iter() {
+        let mut structured_relation_content = wrap(
+            NTag::Span,
+            "relation-label",
+            Node::Text(format!("{label}:")),
+        )
+        .into_array_node();
+        for (idx, word) in words.iter().enumerate() {
+            structured_relation_content.push(Node::Text(if idx == 0 { " " } else { ", " }.into()));
+            structured_relation_content.push(Node::Query(QueryLink(word.clone())));
+        }
+        structured_relations_content.push(wrap(
+            NTag::Div,
+            &format!("relation-{label}"),
+            structured_relation_content,
+        ));
+    }
+
+    wrap(
+        NTag::Details,
+        "details-entry-relations",
+        structured_relations_content,
+    )
+}
+
 #[tracing::instrument(skip_all, level = "trace")]
-fn to_yomitan_forms(source: Lang, form_map: FormMap) -> Vec<YomitanEntry> {
+fn to_yomitan_forms(source: Lang, form_map: FormMap) -> impl Iterator<Item = YomitanEntry> {
     form_map
         .into_flat_iter()
         .map(move |(uninflected, inflected, _, _, tags)| {
-            // There needs to be DetailedDefinition per tag because yomitan reads
-            // multiple tags in a single Inflection as a causal inflection chain.
-            let deinflection_definitions: Vec<_> = tags
-                .iter()
-                .map(|tag| {
-                    DetailedDefinition::Inflection((uninflected.to_string(), vec![tag.to_string()]))
-                })
-                .collect();
+            to_yomitan_form(source, &uninflected, inflected, &tags)
+        })
+}
 
-            let normalized_inflected = normalize_orthography(source, &inflected);
-            let reading = if normalized_inflected == inflected {
-                String::new()
-            } else {
-                inflected
-            };
+fn to_yomitan_form(
+    source: Lang,
+    uninflected: &str,
+    inflected: String,
+    tags: &[Tag],
+) -> YomitanEntry {
+    // There needs to be DetailedDefinition per tag because yomitan reads
+    // multiple tags in a single Inflection as a causal inflection chain.
+    let deinflection_definitions: Vec<_> = tags
+        .iter()
+        .map(|tag| DetailedDefinition::Inflection((uninflected.to_string(), vec![tag.to_string()])))
+        .collect();
+
+    let normalized_inflected = normalize_orthography(source, &inflected);
+    let reading = if normalized_inflected == inflected {
+        String::new()
+    } else {
+        inflected
+    };
 
-            YomitanEntry::TermBankSimplified(TermBankSimplified(
-                normalized_inflected,
-                reading,
-                deinflection_definitions,
+    YomitanEntry::TermBankSimplified(TermBankSimplified(
+        normalized_inflected,
+        reading,
+        deinflection_definitions,
+    ))
+}
+
+/// Merge the runs of a spilled `Tidy`, what was left in memory being the last one.
+///
+/// Forms are postprocessed here, once their tags from every run are together. Entries are rendered
+/// lazily, as in memory, and fail if the runs can not be read back.
+fn to_yomitan_spilled(
+    langs: LangSpecs,
+    irs: Tidy,
+    mut spilled: Spilled,
+) -> Result<Vec<LabelledYomitanEntry>> {
+    spilled.lemmas.push(irs.lemma_map.0)?;
+    spilled.forms.push(irs.form_map.0)?;
+
+    let target = langs.target;
+    let lemmas = spilled
+        .lemmas
+        .merge(spilled.limit, |infos, more| infos.extend(more))?
+        .flat_map(move |record| match record {
+            Ok((key, infos)) => infos
+                .into_iter()
+                .map(|info| {
+                    Ok(to_yomitan_lemma(
+                        target,
+                        &key.lemma,
+                        &key.reading,
+                        &key.pos,
+                        info,
+                    ))
+                })
+                .collect(),
+            Err(err) => vec![Err(err)],
+        });
+
+    let source = langs.source;
+    let forms = spilled
+        .forms
+        .merge(spilled.limit, |(_, tags), (_, more)| tags.extend(more))?
+        .map(move |record| {
+            let (key, (_, mut tags)) = record?;
+            postprocess_form_tags(&mut tags);
+            Ok(to_yomitan_form(
+                source,
+                &key.uninflected,
+                key.inflected,
+                &tags,
             ))
-        })
-        .collect()
+        });
+
+    Ok(vec![
+        LabelledYomitanEntry::fallible("lemma", lemmas),
+        LabelledYomitanEntry::fallible("form", forms),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir;
+
+    fn make(entries: &[&str], opts: &Options) -> Tidy {
+        let mut irs = Tidy::default();
+        for entry in entries {
+            let mut entry: WordEntry = serde_json::from_str(entry).unwrap();
+            preprocess_main(Edition::En, Lang::En, opts, &mut entry, &mut irs);
+            process_main(Edition::En, Lang::En, &entry, &mut irs);
+        }
+        irs
+    }
+
+    const THOU: &str = r#"{
+        "word": "thou",
+        "pos": "verb",
+        "senses": [
+            {"glosses": ["To address (someone) as thou."], "tags": ["archaic"]},
+            {"glosses": ["To use the pronoun thou."]}
+        ]
+    }"#;
+
+    const OLDE: &str = r#"{
+        "word": "olde",
+        "pos": "adj",
+        "forms": [{"form": "older", "tags": ["comparative"]}],
+        "senses": [
+            {"glosses": ["Archaic spelling of old."], "tags": ["archaic"], "alt_of": [{"word": "old"}]}
+        ]
+    }"#;
+
+    fn yomitan_json(irs: Tidy) -> Vec<String> {
+        let langs = LangSpecs {
+            edition: Edition::En.into(),
+            source: Lang::De,
+            target: Lang::En,
+        };
+        DMain
+            .to_yomitan(langs, irs)
+            .unwrap()
+            .into_iter()
+            .flat_map(|labelled| labelled.entries)
+            .map(|entry| serde_json::to_string(&entry.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn spilled_build_matches_in_memory() {
+        let fixture = std::fs::read_to_string("tests/kaikki/de-en-extract.jsonl").unwrap();
+        // Twice, so that keys are found in several runs
+        let lines: Vec<_> = fixture.lines().chain(fixture.lines()).collect();
+        let dir = temp_dir("main-spill");
+        let opts = Options::default();
+
+        let mut in_memory = Tidy::default();
+        let mut spilled = Tidy::default();
+        for (idx, line) in lines.iter().enumerate() {
+            for irs in [&mut in_memory, &mut spilled] {
+                let mut entry: WordEntry = serde_json::from_str(line).unwrap();
+                preprocess_main(Edition::En, Lang::De, &opts, &mut entry, irs);
+                process_main(Edition::En, Lang::De, &entry, irs);
+            }
+            if idx % 3 == 2 {
+                spilled.spill(&dir, 1).unwrap();
+            }
+        }
+        assert!(spilled.spilled.is_some());
+
+        DMain.postprocess(&mut in_memory);
+        DMain.postprocess(&mut spilled);
+        let expected = yomitan_json(in_memory);
+        assert!(!expected.is_empty());
+        assert_eq!(yomitan_json(spilled), expected);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn memory_usage_follows_the_inserts() {
+        let fixture = std::fs::read_to_string("tests/kaikki/de-en-extract.jsonl").unwrap();
+        let lines: Vec<_> = fixture.lines().collect();
+        let mut irs = make(&lines, &Options::default());
+
+        // Capacities are not tracked: only the order of magnitude holds.
+        let walked = irs.heap_size();
+        let estimate = irs.memory_usage();
+        assert!(
+            estimate > walked / 2 && estimate <= walked,
+            "{estimate} vs {walked}"
+        );
+
+        let dir = temp_dir("main-usage");
+        irs.spill(&dir, 1).unwrap();
+        assert_eq!(irs.memory_usage(), 0);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn prune_senses_by_tag() {
+        let opts = Options {
+            drop_sense_tags: vec!["archaic".to_string()],
+            ..Default::default()
+        };
+        let irs = make(&[THOU, OLDE], &opts);
+
+        let lemmas: Vec<_> = irs.lemma_map.0.keys().map(|k| k.lemma.as_str()).collect();
+        assert_eq!(lemmas, ["thou"]);
+        let glosses: Vec<_> = irs.lemma_map.0.values().next().unwrap()[0]
+            .gloss_tree
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(glosses, ["To use the pronoun thou."]);
+
+        // The fully pruned entry points to its lemma, and its own forms are gone
+        let forms: Vec<_> = irs
+            .form_map
+            .0
+            .keys()
+            .map(|k| (k.uninflected.as_str(), k.inflected.as_str()))
+            .collect();
+        assert_eq!(forms, [("old", "olde")]);
+    }
+
+    #[test]
+    fn prune_senses_by_topic() {
+        let entry = r#"{
+            "word": "Herz",
+            "pos": "noun",
+            "senses": [
+                {"glosses": ["heart"], "topics": ["anatomy"]},
+                {"glosses": ["hearts"], "topics": ["card-games"]}
+            ]
+        }"#;
+        let glosses = |opts: &Options| -> Vec<String> {
+            let irs = make(&[entry], opts);
+            irs.lemma_map
+                .0
+                .values()
+                .flat_map(|infos| infos[0].gloss_tree.keys().cloned())
+                .collect()
+        };
+
+        let keep = Options {
+            keep_sense_topics: vec!["anatomy".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(glosses(&keep), ["heart"]);
+
+        let drop = Options {
+            drop_sense_topics: vec!["anatomy".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(glosses(&drop), ["hearts"]);
+
+        assert_eq!(glosses(&Options::default()).len(), 2);
+    }
+
+    #[test]
+    fn relations_link_to_their_words() {
+        let entry = r#"{
+            "word": "big",
+            "pos": "adj",
+            "antonyms": [{"word": "small"}],
+            "senses": [
+                {
+                    "glosses": ["Of great size."],
+                    "synonyms": [{"word": "large"}, {"word": "large", "tags": ["formal"]}, {"word": "big"}],
+                    "hyponyms": [{"word": "R&B"}]
+                },
+                {"glosses": ["Popular."]}
+            ]
+        }"#;
+        let irs = make(&[entry], &Options::default());
+
+        let info = &irs.lemma_map.0.values().next().unwrap()[0];
+        assert_eq!(info.relations.antonyms, ["small"]);
+        let sense = &info.gloss_tree["Of great size."].relations;
+        assert_eq!(sense.synonyms, ["large"]);
+        assert_eq!(sense.hyponyms, ["R&B"]);
+        assert!(info.gloss_tree["Popular."].relations.is_empty());
+
+        let json = yomitan_json(irs).concat();
+        assert!(
+            json.contains(r#"{"tag":"a","href":"?query=large&wildcards=off","content":"large"}"#)
+        );
+        assert!(json.contains(r#""href":"?query=R%26B&wildcards=off""#));
+        assert!(json.contains(r#""content":"synonyms, hyponyms""#));
+        assert!(json.contains(r#""content":"antonyms""#));
+    }
+
+    #[test]
+    fn derived_terms_are_truncated() {
+        let derived: Vec<_> = (0..MAX_DERIVED_TERMS + 5)
+            .map(|i| format!(r#"{{"word": "Haus{i}"}}"#))
+            .collect();
+        let entry = format!(
+            r#"{{
+                "word": "Haus",
+                "pos": "noun",
+                "derived": [{}, {{"word": "Haus"}}],
+                "compounds": [{{"word": "Haus0"}}, {{"word": "Hausboot"}}],
+                "senses": [{{"glosses": ["house"]}}]
+            }}"#,
+            derived.join(", ")
+        );
+        let irs = make(&[&entry], &Options::default());
+
+        let info = &irs.lemma_map.0.values().next().unwrap()[0];
+        assert_eq!(info.derived_terms.len(), MAX_DERIVED_TERMS + 6);
+        assert_eq!(info.derived_terms.last().unwrap(), "Hausboot");
+
+        let json = yomitan_json(irs).concat();
+        assert!(json.contains(r#""content":"Derived terms (36)""#));
+        assert!(json.contains(r#""?query=Haus29&wildcards=off""#));
+        assert!(!json.contains(r#""?query=Haus30&wildcards=off""#));
+        assert!(json.contains(" (+6 more)"));
+    }
+
+    const FAMA: &str = r#"{
+        "word": "fama",
+        "pos": "noun",
+        "etymology_text": "From Proto-Italic *fāmā, from Proto-Indo-European *bʰéh₂meh₂.",
+        "etymology_templates": [
+            {"name": "inh", "args": {"1": "la", "2": "itc-pro", "3": "*fāmā"}, "expansion": "Proto-Italic *fāmā"},
+            {"name": "der", "args": {"1": "la", "2": "ine-pro", "3": "", "4": "*bʰéh₂meh₂"}, "expansion": "Proto-Indo-European *bʰéh₂meh₂"},
+            {"name": "cog", "args": {"1": "grc", "2": "φήμη"}, "expansion": "Ancient Greek φήμη"}
+        ],
+        "descendants": [
+            {"lang": "Old French", "lang_code": "fro", "word": "fame", "descendants": [
+                {"lang": "Middle French", "lang_code": "frm", "word": "fame"}
+            ]}
+        ],
+        "senses": [{"glosses": ["rumor"]}]
+    }"#;
+
+    #[test]
+    fn etymology_chain_and_descendants() {
+        let irs = make(&[FAMA], &Options::default());
+        let info = &irs.lemma_map.0.values().next().unwrap()[0];
+        let chain: Vec<_> = info
+            .etymology_chain
+            .iter()
+            .map(|step| {
+                (
+                    step.relation.as_str(),
+                    step.lang.as_str(),
+                    step.word.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            chain,
+            [
+                ("inherited", "Proto-Italic", "*fāmā"),
+                ("derived", "Proto-Indo-European", "*bʰéh₂meh₂")
+            ]
+        );
+        // Opt-in
+        assert!(info.descendants.is_empty());
+        let json = yomitan_json(irs).concat();
+        assert!(json.contains(r#""etymology-chain""#));
+        assert!(json.contains(r#""etymology-text""#));
+        assert!(!json.contains("Descendants"));
+
+        let opts = Options {
+            descendants: true,
+            ..Default::default()
+        };
+        let json = yomitan_json(make(&[FAMA], &opts)).concat();
+        assert!(json.contains(r#""content":"Descendants""#));
+        assert!(json.contains(r#""content":"Middle French: ""#));
+    }
+
+    #[test]
+    fn prune_senses_by_category_and_show_category_tags() {
+        let entry = r#"{
+            "word": "sehen",
+            "pos": "verb",
+            "categories": ["German class 5 strong verbs"],
+            "senses": [
+                {"glosses": ["to see"], "categories": ["de:Vision"]},
+                {"glosses": ["to look"], "categories": ["German terms with quotations"]}
+            ]
+        }"#;
+        let opts = Options {
+            keep_sense_categories: vec!["Vision".to_string()],
+            category_tags: true,
+            ..Default::default()
+        };
+        let irs = make(&[entry], &opts);
+        let gloss_tree = &irs.lemma_map.0.values().next().unwrap()[0].gloss_tree;
+        assert_eq!(gloss_tree.keys().collect::<Vec<_>>(), ["to see"]);
+        assert!(
+            gloss_tree["to see"]
+                .tags
+                .contains(&"strong verb".to_string())
+        );
+
+        let opts = Options {
+            drop_sense_categories: vec!["German terms with quotations".to_string()],
+            ..Default::default()
+        };
+        let irs = make(&[entry], &opts);
+        let gloss_tree = &irs.lemma_map.0.values().next().unwrap()[0].gloss_tree;
+        assert_eq!(gloss_tree.keys().collect::<Vec<_>>(), ["to see"]);
+        assert!(gloss_tree["to see"].tags.is_empty());
+    }
 }
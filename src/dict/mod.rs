@@ -1,11 +1,19 @@
+pub mod build;
+mod converter;
 mod core;
+mod custom;
+pub mod filter;
+pub mod hyphenation;
 mod index;
 mod locale;
 mod main;
 mod other;
 pub mod release;
+mod spill;
 mod writer;
 
+pub use converter::*;
 pub use core::*;
+pub use custom::*;
 pub use main::*;
 pub use other::*;
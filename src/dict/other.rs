@@ -1,3 +1,5 @@
+use anyhow::Result;
+
 use crate::{
     Map, Set,
     cli::{GlossaryArgs, GlossaryExtendedArgs, IpaArgs, IpaMergedArgs, LangSpecs},
@@ -37,8 +39,8 @@ impl Dictionary for DGlossary {
         process_glossary(langs.edition, langs.target, entry, irs);
     }
 
-    fn to_yomitan(&self, _: LangSpecs, irs: Self::I) -> Vec<LabelledYomitanEntry> {
-        vec![LabelledYomitanEntry::new("term", irs)]
+    fn to_yomitan(&self, _: LangSpecs, irs: Self::I) -> Result<Vec<LabelledYomitanEntry>> {
+        Ok(vec![LabelledYomitanEntry::new("term", irs)])
     }
 }
 
@@ -70,11 +72,11 @@ impl Dictionary for DGlossaryExtended {
         }));
     }
 
-    fn to_yomitan(&self, _: LangSpecs, irs: Self::I) -> Vec<LabelledYomitanEntry> {
-        vec![LabelledYomitanEntry::new(
+    fn to_yomitan(&self, _: LangSpecs, irs: Self::I) -> Result<Vec<LabelledYomitanEntry>> {
+        Ok(vec![LabelledYomitanEntry::new(
             "term",
             to_yomitan_glossary_extended(irs),
-        )]
+        )])
     }
 }
 
@@ -94,8 +96,8 @@ impl Dictionary for DIpa {
         process_ipa(langs.edition, langs.source, entry, irs);
     }
 
-    fn to_yomitan(&self, _: LangSpecs, irs: Self::I) -> Vec<LabelledYomitanEntry> {
-        vec![LabelledYomitanEntry::new("term", to_yomitan_ipa(irs))]
+    fn to_yomitan(&self, _: LangSpecs, irs: Self::I) -> Result<Vec<LabelledYomitanEntry>> {
+        Ok(vec![LabelledYomitanEntry::new("term", to_yomitan_ipa(irs))])
     }
 }
 
@@ -118,8 +120,11 @@ impl Dictionary for DIpaMerged {
         irs.sort_by(|a, b| a.0.cmp(&b.0));
     }
 
-    fn to_yomitan(&self, _: LangSpecs, tidy: Self::I) -> Vec<LabelledYomitanEntry> {
-        vec![LabelledYomitanEntry::new("term", to_yomitan_ipa(tidy))]
+    fn to_yomitan(&self, _: LangSpecs, tidy: Self::I) -> Result<Vec<LabelledYomitanEntry>> {
+        Ok(vec![LabelledYomitanEntry::new(
+            "term",
+            to_yomitan_ipa(tidy),
+        )])
     }
 }
 
@@ -276,24 +281,31 @@ pub fn get_ipas(entry: &WordEntry) -> Vec<Ipa> {
     ipas_grouped
 }
 
-type IIpa = (String, PhoneticTranscription);
-
-fn process_ipa(edition: Edition, source: Lang, entry: &WordEntry, irs: &mut Vec<IIpa>) {
+/// Like `get_ipas`, with the short version of known tags.
+pub fn get_short_ipas(entry: &WordEntry) -> Vec<Ipa> {
     let mut ipas = get_ipas(entry);
 
-    if ipas.is_empty() {
-        return;
-    }
-
     // This replacing with the short tag will still show the long version on hover.
     for ipa in &mut ipas {
         for tag in &mut ipa.tags {
-            if let Some(tag_info) = find_tag_in_bank(&tag) {
+            if let Some(tag_info) = find_tag_in_bank(tag) {
                 *tag = (*tag_info.short_tag).to_string();
             }
         }
     }
 
+    ipas
+}
+
+type IIpa = (String, PhoneticTranscription);
+
+fn process_ipa(edition: Edition, source: Lang, entry: &WordEntry, irs: &mut Vec<IIpa>) {
+    let ipas = get_short_ipas(entry);
+
+    if ipas.is_empty() {
+        return;
+    }
+
     let phonetic_transcription = PhoneticTranscription {
         reading: get_reading(edition, source, entry).unwrap_or_else(|| entry.word.clone()),
         transcriptions: ipas,
@@ -9,11 +9,11 @@ use std::{
     fs::File,
     io::{BufRead, BufReader},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
     time::Instant,
 };
 
-use anyhow::Result;
-use rayon::ThreadPoolBuilder;
+use anyhow::{Context, Result, bail};
 use rayon::prelude::*;
 use rkyv::Archived;
 use rusqlite::{Connection, params};
@@ -24,12 +24,14 @@ use crate::{
         IpaArgs, IpaMergedArgs, IpaMergedLangs, MainArgs, MainLangs, Options, ReleaseArgs,
     },
     dict::{
-        DGlossary, DGlossaryExtended, DIpa, DIpaMerged, DMain, Dictionary, Intermediate, Langs,
-        find_or_download_jsonl, iter_datasets, writer::write_yomitan,
+        BadLine, DGlossary, DGlossaryExtended, DIpa, DIpaMerged, DMain, Dictionary, Intermediate,
+        Langs, Quarantine, build::skipped_message, find_or_download_jsonl, iter_datasets,
+        make_dicts, writer::write_yomitan,
     },
     lang::{Edition, EditionSpec, Lang},
     models::kaikki::WordEntry,
     path::PathManager,
+    progress,
 };
 
 pub fn release(rargs: ReleaseArgs) -> Result<()> {
@@ -55,12 +57,12 @@ pub fn release(rargs: ReleaseArgs) -> Result<()> {
     //
     // NOTE: For some reason this takes time even when db are init, why?
     let _ = std::fs::create_dir(&rargs.root_dir);
-    download_and_create_db(&rargs, &editions);
+    let skipped = AtomicUsize::new(download_and_create_db(&rargs, &editions));
 
     let start = Instant::now();
 
     editions.par_iter().for_each(|edition| {
-        release_main(&rargs, *edition);
+        skipped.fetch_add(release_main(&rargs, *edition), Ordering::Relaxed);
         release_ipa(&rargs, *edition);
         release_glossary(&rargs, *edition);
     });
@@ -74,18 +76,22 @@ pub fn release(rargs: ReleaseArgs) -> Result<()> {
     });
 
     let elapsed = start.elapsed();
-    println!("Finished dictionaries in {elapsed:.2?}");
+    println!(
+        "Finished dictionaries in {elapsed:.2?}{}",
+        skipped_message(skipped.into_inner())
+    );
 
     Ok(())
 }
 
-fn download_and_create_db(rargs: &ReleaseArgs, editions: &[Edition]) {
+/// Returns the number of malformed lines that were skipped while importing.
+fn download_and_create_db(rargs: &ReleaseArgs, editions: &[Edition]) -> usize {
     let start = Instant::now();
 
     let dir_kaik = rargs.root_dir.join("kaikki"); // cf. same function @ path.rs
     let _ = std::fs::create_dir(dir_kaik);
 
-    editions.par_iter().for_each(|edition| {
+    let skipped = editions.par_iter().map(|edition| {
         let now = Instant::now();
         let args = MainArgs {
             langs: MainLangs {
@@ -96,17 +102,38 @@ fn download_and_create_db(rargs: &ReleaseArgs, editions: &[Edition]) {
             options: Options {
                 quiet: false,
                 root_dir: rargs.root_dir.clone(),
+                max_errors: rargs.max_errors,
                 ..Default::default()
             },
         };
         let pm: &PathManager = &args.try_into().unwrap();
         let path_jsonl = find_or_download_jsonl(*edition, None, pm).unwrap();
-        println!("Finished download for {edition} ({:.2?})", now.elapsed());
-        let _ = WiktextractDb::create(rargs.root_dir.clone(), *edition, path_jsonl).unwrap();
-        println!("Finished database for {edition} ({:.2?})", now.elapsed());
+        progress::println(&format!(
+            "Finished download for {edition} ({:.2?})",
+            now.elapsed()
+        ));
+        let quarantine = Quarantine::new(
+            pm.dir_diagnostics()
+                .join(format!("quarantine-db-{edition}.jsonl")),
+            rargs.max_errors,
+        );
+        let (_, skipped) =
+            WiktextractDb::create(rargs.root_dir.clone(), *edition, path_jsonl, quarantine)
+                .unwrap();
+        progress::println(&format!(
+            "Finished database for {edition} ({:.2?})",
+            now.elapsed()
+        ));
+        skipped
     });
+    let skipped = skipped.sum();
 
-    println!("Finished download & db creation in {:.2?}", start.elapsed());
+    println!(
+        "Finished download & db creation in {:.2?}{}",
+        start.elapsed(),
+        skipped_message(skipped)
+    );
+    skipped
 }
 
 // Pretty print utility
@@ -116,48 +143,61 @@ fn pp(dict_name: &str, first_lang: Lang, second_lang: Lang, time: Instant) {
     eprintln!("{label:<20} done in {:.2?}", time.elapsed());
 }
 
-fn release_main(rargs: &ReleaseArgs, edition: Edition) {
-    // Limit only this workload (as opposed to the full logic. IPA and glossaries are completely
-    // fine and will never OOM).
-    let pool = ThreadPoolBuilder::new()
-        // 2 seems fine with a MemoryMax of 20GB (works on my machine TM)
-        // 8 is fine for testing with only English/German/French editions
-        .num_threads(2)
-        .build()
-        .expect("Failed to build local thread pool");
-
-    pool.install(|| {
-        Lang::all().par_iter().for_each(|source| {
-            let start = Instant::now();
-
-            let langs = match (edition, source) {
-                (Edition::Simple, Lang::Simple) => MainLangs {
-                    source: *source,
-                    target: edition,
-                },
-                (Edition::Simple, _) | (_, Lang::Simple) => return,
-                _ => MainLangs {
-                    source: *source,
+/// Memory allowed to the intermediate representations of one edition's main dictionaries.
+///
+/// Languages that do not fit are built in subsequent passes over the edition, and a language that
+/// does not fit on its own spills to disk (cf. `--memory-limit`).
+const RELEASE_MAIN_MEMORY_LIMIT: usize = 4 << 30;
+
+/// Returns the number of malformed lines that were skipped.
+fn release_main(rargs: &ReleaseArgs, edition: Edition) -> usize {
+    let start = Instant::now();
+
+    // Unlike the other dictionaries, which read from the database, this makes every source in a
+    // single pass over the jsonlines of the edition.
+    let sources: Vec<Lang> = match edition {
+        Edition::Simple => vec![Lang::Simple],
+        _ => Lang::all()
+            .into_iter()
+            .filter(|source| *source != Lang::Simple)
+            .collect(),
+    };
+
+    let pms: Result<Vec<PathManager>> = sources
+        .into_iter()
+        .map(|source| {
+            MainArgs {
+                langs: MainLangs {
+                    source,
                     target: edition,
                 },
-            };
-
-            let args = MainArgs {
-                langs,
                 dict_name: DictName::default(),
                 options: Options {
                     quiet: true,
                     root_dir: rargs.root_dir.clone(),
+                    max_errors: rargs.max_errors,
+                    memory_limit: Some(RELEASE_MAIN_MEMORY_LIMIT),
                     ..Default::default()
                 },
-            };
-
-            match make_dict(DMain, args) {
-                Ok(()) => pp("main", *source, edition.into(), start),
-                Err(err) => tracing::error!("[main-{source}-{edition}] ERROR: {err:?}"),
             }
-        });
-    });
+            .try_into()
+        })
+        .collect();
+
+    match pms.and_then(|pms| make_dicts(DMain, pms, Some(RELEASE_MAIN_MEMORY_LIMIT))) {
+        Ok(skipped) => {
+            eprintln!(
+                "{:<20} done in {:.2?}",
+                format!("[main-*-{edition}]"),
+                start.elapsed()
+            );
+            skipped
+        }
+        Err(err) => {
+            tracing::error!("[main-*-{edition}] ERROR: {err:?}");
+            0
+        }
+    }
 }
 
 fn release_ipa(rargs: &ReleaseArgs, edition: Edition) {
@@ -187,7 +227,7 @@ fn release_ipa(rargs: &ReleaseArgs, edition: Edition) {
         };
 
         match make_dict(DIpa, args) {
-            Ok(()) => pp("ipa", *source, edition.into(), start),
+            Ok(_) => pp("ipa", *source, edition.into(), start),
             Err(err) => tracing::error!("[ipa-{source}-{edition}] ERROR: {err:?}"),
         }
     });
@@ -213,7 +253,7 @@ fn release_ipa_merged(rargs: &ReleaseArgs, target: Lang) {
 
     match make_dict(DIpaMerged, args) {
         // Lang::Sq is a filler, it should be EditionSpec::All
-        Ok(()) => pp("ipa-merged", target, Lang::Sq, start),
+        Ok(_) => pp("ipa-merged", target, Lang::Sq, start),
         Err(err) => tracing::error!("[ipa-merged-{target}] ERROR: {err:?}"),
     }
 }
@@ -243,7 +283,7 @@ fn release_glossary(rargs: &ReleaseArgs, edition: Edition) {
 
         match make_dict(DGlossary, args) {
             // Order may be wrong
-            Ok(()) => pp("gloss", *target, edition.into(), start),
+            Ok(_) => pp("gloss", *target, edition.into(), start),
             Err(err) => tracing::error!("[gloss-{target}-{edition}] ERROR: {err:?}"),
         }
     });
@@ -275,12 +315,18 @@ fn release_glossary_extended(source: Lang) {
         };
 
         match make_dict(DGlossaryExtended, args) {
-            Ok(()) => pp("gloss-all", source, *target, start),
+            Ok(_) => pp("gloss-all", source, *target, start),
             Err(err) => tracing::error!("[gloss-all-{source}-{target}] ERROR: {err:?}"),
         }
     });
 }
 
+/// Version of the archived `WordEntry` layout, stored as the database's `user_version`.
+///
+/// Bump it whenever `WordEntry` changes: databases of another version are dropped and imported
+/// again, since their blobs can not be read anymore.
+const DB_VERSION: i64 = 1;
+
 pub struct WiktextractDb {
     pub conn: Connection,
 }
@@ -308,10 +354,28 @@ impl WiktextractDb {
     {
         let db_path = Self::db_path_for(root_dir, edition);
         let conn = Connection::open(&db_path)?;
+        let version = Self::version(&conn)?;
+        if version != DB_VERSION {
+            bail!(
+                "Database version {version} @ {} is not {DB_VERSION}: it must be imported again",
+                db_path.display()
+            );
+        }
         Ok(Self { conn })
     }
 
-    pub fn create<P>(root_dir: P, edition: Edition, path_jsonl: PathBuf) -> Result<Self>
+    fn version(conn: &Connection) -> Result<i64> {
+        Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+
+    /// Open the database of `edition`, importing `path_jsonl` if it is empty or of another
+    /// `DB_VERSION`. Also returns the number of malformed lines that were skipped.
+    pub(crate) fn create<P>(
+        root_dir: P,
+        edition: Edition,
+        path_jsonl: PathBuf,
+        quarantine: Quarantine,
+    ) -> Result<(Self, usize)>
     where
         P: AsRef<Path>,
     {
@@ -320,6 +384,12 @@ impl WiktextractDb {
         let db_path = Self::db_path_for(&root_dir, edition);
         let conn = Connection::open(&db_path)?;
 
+        let version = Self::version(&conn)?;
+        if version != DB_VERSION {
+            tracing::info!("DB version {version} for {edition} is outdated, dropping it...");
+            conn.execute_batch("DROP TABLE IF EXISTS wiktextract;")?;
+        }
+
         conn.execute_batch(
             r"
             CREATE TABLE IF NOT EXISTS wiktextract (
@@ -340,48 +410,68 @@ impl WiktextractDb {
             .conn
             .query_row("SELECT COUNT(*) FROM wiktextract", [], |row| row.get(0))?;
 
+        let mut skipped = 0;
         if count == 0 {
             tracing::info!("DB empty for {edition}, importing JSONL...");
-            db.import_jsonl(path_jsonl)?;
+            skipped = db.import_jsonl(path_jsonl, quarantine)?;
+            // Only once imported, so that an interrupted import is done again
+            db.conn.pragma_update(None, "user_version", DB_VERSION)?;
         } else {
             tracing::trace!("DB already initialized for {edition} ({count} rows)");
         }
 
-        Ok(db)
+        Ok((db, skipped))
     }
 
     #[tracing::instrument(skip_all, level = "debug")]
-    pub fn import_jsonl<P: AsRef<Path>>(&mut self, jsonl_path: P) -> Result<()> {
+    pub(crate) fn import_jsonl<P: AsRef<Path>>(
+        &mut self,
+        jsonl_path: P,
+        mut quarantine: Quarantine,
+    ) -> Result<usize> {
         let start = Instant::now();
         let file = File::open(&jsonl_path)?;
         let reader = BufReader::new(file);
+        let dataset_path = jsonl_path.as_ref().display().to_string();
 
         let tx = self.conn.transaction()?;
         {
             let mut stmt = tx.prepare("INSERT INTO wiktextract (lang, entry) VALUES (?, ?)")?;
 
-            for line in reader.lines() {
+            for (idx, line) in reader.lines().enumerate() {
                 let line = line?;
-                let word_entry: WordEntry = serde_json::from_str(&line)?;
+                let word_entry: WordEntry = match serde_json::from_str(&line) {
+                    Ok(word_entry) => word_entry,
+                    Err(err) => {
+                        quarantine.record(
+                            &dataset_path,
+                            idx + 1,
+                            BadLine::new(line.into(), err),
+                        )?;
+                        continue;
+                    }
+                };
                 let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&word_entry)?;
 
                 stmt.execute(params![word_entry.lang_code, bytes.as_ref()])?;
             }
         }
         tx.commit()?;
+        let skipped = quarantine.finish()?;
         tracing::debug!(
             "Making db took {:.3} ms",
             start.elapsed().as_secs_f64() * 1000.0
         );
 
-        Ok(())
+        Ok(skipped)
     }
 
     pub fn blob_to_word_entry(blob: &[u8]) -> Result<WordEntry> {
         let archived: &Archived<WordEntry> =
-            rkyv::access::<Archived<WordEntry>, rkyv::rancor::Error>(blob).unwrap();
-        let word_entry: WordEntry =
-            rkyv::deserialize::<WordEntry, rkyv::rancor::Error>(archived).unwrap();
+            rkyv::access::<Archived<WordEntry>, rkyv::rancor::Error>(blob)
+                .context("Failed to access an archived entry")?;
+        let word_entry: WordEntry = rkyv::deserialize::<WordEntry, rkyv::rancor::Error>(archived)
+            .context("Failed to deserialize an archived entry")?;
         Ok(word_entry)
     }
 }
@@ -442,8 +532,8 @@ fn make_dict<D: Dictionary + EditionFrom>(dict: D, raw_args: D::A) -> Result<()>
     }
 
     if !opts.skip_yomitan {
-        let labelled_entries = dict.to_yomitan(pm.langs, irs);
-        write_yomitan(source_pm, target_pm, opts, &pm, labelled_entries)?;
+        let labelled_entries = dict.to_yomitan(pm.langs, irs)?;
+        write_yomitan(source_pm, target_pm, opts, pm, labelled_entries, None)?;
     }
 
     Ok(())
@@ -489,3 +579,54 @@ impl EditionFrom for DGlossaryExtended {
         EditionIs::All
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::utils::temp_dir;
+
+    #[test]
+    fn outdated_db_is_imported_again() {
+        let root = temp_dir("release-db");
+        fs::create_dir_all(WiktextractDb::db_folder(&root)).unwrap();
+        let jsonl = root.join("en-extract.jsonl");
+        fs::write(
+            &jsonl,
+            r#"{"lang_code": "de", "word": "faul", "pos": "adj"}"#,
+        )
+        .unwrap();
+
+        // An unversioned database, as written before `DB_VERSION`
+        let conn = Connection::open(WiktextractDb::db_path_for(&root, Edition::En)).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE wiktextract (id INTEGER PRIMARY KEY, lang TEXT NOT NULL, entry BLOB NOT NULL);
+            INSERT INTO wiktextract (lang, entry) VALUES ('de', x'00');",
+        )
+        .unwrap();
+        drop(conn);
+        assert!(WiktextractDb::open(&root, Edition::En).is_err());
+
+        let quarantine = Quarantine::discarding(0);
+        WiktextractDb::create(&root, Edition::En, jsonl, quarantine).unwrap();
+
+        let db = WiktextractDb::open(&root, Edition::En).unwrap();
+        let blobs: Vec<Vec<u8>> = db
+            .conn
+            .prepare("SELECT entry FROM wiktextract")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(blobs.len(), 1);
+        let entry = WiktextractDb::blob_to_word_entry(&blobs[0]).unwrap();
+        assert_eq!(entry.word, "faul");
+
+        assert!(WiktextractDb::blob_to_word_entry(&[0]).is_err());
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,274 @@
+//! Spill intermediate maps to disk, cf. `--memory-limit`.
+//!
+//! A map is written as a run of records sorted by key. Merging the runs brings together the
+//! values of a key found in several of them, then restores the order in which keys were first
+//! inserted: the output is the same as if the map had stayed in memory.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::Map;
+use crate::dict::main::heap::HeapSize;
+
+/// Where a key was first inserted: (run, position in that run).
+///
+/// Runs are written in order, so sorting by `Order` gives the first-insertion order of the keys.
+type Order = (usize, usize);
+
+#[derive(Serialize, Deserialize)]
+struct Record<K, V> {
+    key: K,
+    order: Order,
+    value: V,
+}
+
+/// Runs of a map of `K` to `V`, stored in `dir` as `{name}-{run}.jsonl`.
+#[derive(Debug)]
+pub struct Runs<K, V> {
+    dir: PathBuf,
+    name: &'static str,
+    paths: Vec<PathBuf>,
+    records: usize,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> Runs<K, V>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned + HeapSize,
+    V: Serialize + DeserializeOwned + HeapSize,
+{
+    pub fn new(dir: &Path, name: &'static str) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            name,
+            paths: Vec::new(),
+            records: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Number of records written, counting a key once per run.
+    pub const fn len(&self) -> usize {
+        self.records
+    }
+
+    /// Write `map` as a new run.
+    pub fn push(&mut self, map: Map<K, V>) -> Result<()> {
+        if map.is_empty() {
+            return Ok(());
+        }
+
+        let run = self.paths.len();
+        let mut records: Vec<_> = map
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (key, value))| Record {
+                key,
+                order: (run, idx),
+                value,
+            })
+            .collect();
+        records.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}-{run}.jsonl", self.name));
+        write_records(&path, &records)?;
+        tracing::debug!("Spilled {} records to {}", records.len(), path.display());
+
+        self.records += records.len();
+        self.paths.push(path);
+        Ok(())
+    }
+
+    /// Merge the runs back, in first-insertion order.
+    ///
+    /// `combine` appends the value from a later run to the one from an earlier run. Merged records
+    /// are buffered up to `limit` bytes, then written to disk in first-insertion order.
+    pub fn merge(
+        self,
+        limit: usize,
+        mut combine: impl FnMut(&mut V, V),
+    ) -> Result<impl Iterator<Item = Result<(K, V)>>> {
+        let mut by_key = MergeRuns::open(&self.paths, |record: &Record<K, V>| record.key.clone())?;
+        let mut chunks = Vec::new();
+        let mut buffer = Vec::new();
+        let mut buffered = 0;
+
+        while let Some(record) = by_key.next() {
+            let mut record = record?;
+            // Keys are unique within a run, and runs come out in order on equal keys.
+            while let Some(next) = by_key.next_if(|next| next.key == record.key) {
+                combine(&mut record.value, next?.value);
+            }
+
+            buffered += record.key.heap_size() + record.value.heap_size();
+            buffer.push(record);
+            if buffered > limit {
+                chunks.push(self.write_chunk(chunks.len(), &mut buffer)?);
+                buffered = 0;
+            }
+        }
+        if !buffer.is_empty() {
+            chunks.push(self.write_chunk(chunks.len(), &mut buffer)?);
+        }
+
+        let by_order = MergeRuns::open(&chunks, |record: &Record<K, V>| record.order)?;
+        Ok(by_order.map(|record| record.map(|record| (record.key, record.value))))
+    }
+
+    fn write_chunk(&self, idx: usize, buffer: &mut Vec<Record<K, V>>) -> Result<PathBuf> {
+        buffer.sort_unstable_by_key(|record| record.order);
+        let path = self.dir.join(format!("{}-merged-{idx}.jsonl", self.name));
+        write_records(&path, buffer)?;
+        buffer.clear();
+        Ok(path)
+    }
+}
+
+impl<K, V> Drop for Runs<K, V> {
+    // Runs are removed once merged: this is for builds that stopped before.
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn write_records<T: Serialize>(path: &Path, records: &[T]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for record in records {
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+type RecordStream<T> =
+    serde_json::StreamDeserializer<'static, serde_json::de::IoRead<BufReader<File>>, T>;
+
+/// K-way merge of files of records, each sorted by `by`.
+///
+/// On ties, records from the earlier file come first. Files are removed once read.
+struct MergeRuns<T, O, F> {
+    streams: Vec<(PathBuf, RecordStream<T>)>,
+    heads: Vec<Option<T>>,
+    heap: BinaryHeap<Reverse<(O, usize)>>,
+    by: F,
+}
+
+impl<T, O, F> MergeRuns<T, O, F>
+where
+    T: DeserializeOwned,
+    O: Ord,
+    F: Fn(&T) -> O,
+{
+    fn open(paths: &[PathBuf], by: F) -> Result<Self> {
+        let mut merge = Self {
+            streams: Vec::with_capacity(paths.len()),
+            heads: Vec::with_capacity(paths.len()),
+            heap: BinaryHeap::with_capacity(paths.len()),
+            by,
+        };
+        for (idx, path) in paths.iter().enumerate() {
+            let reader = BufReader::new(File::open(path)?);
+            let stream = serde_json::Deserializer::from_reader(reader).into_iter();
+            merge.streams.push((path.clone(), stream));
+            merge.heads.push(None);
+            merge.advance(idx)?;
+        }
+        Ok(merge)
+    }
+
+    /// Read the next record of the `idx`th file.
+    fn advance(&mut self, idx: usize) -> Result<()> {
+        let (path, stream) = &mut self.streams[idx];
+        match stream.next() {
+            Some(record) => {
+                let record =
+                    record.with_context(|| format!("corrupted spill file {}", path.display()))?;
+                self.heap.push(Reverse(((self.by)(&record), idx)));
+                self.heads[idx] = Some(record);
+            }
+            None => fs::remove_file(&*path)?,
+        }
+        Ok(())
+    }
+
+    /// Return the next record if it satisfies `pred`.
+    fn next_if(&mut self, pred: impl FnOnce(&T) -> bool) -> Option<Result<T>> {
+        let Reverse((_, idx)) = self.heap.peek()?;
+        let head = self.heads[*idx].as_ref()?;
+        if pred(head) { self.next() } else { None }
+    }
+}
+
+impl<T, O, F> Iterator for MergeRuns<T, O, F>
+where
+    T: DeserializeOwned,
+    O: Ord,
+    F: Fn(&T) -> O,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, idx)) = self.heap.pop()?;
+        let record = self.heads[idx].take()?;
+        Some(self.advance(idx).map(|()| record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir;
+
+    #[test]
+    fn merge_restores_insertion_order() {
+        let batches = [
+            vec![("b", "1"), ("a", "2")],
+            vec![("c", "3"), ("b", "4")],
+            vec![("a", "5"), ("d", "6"), ("c", "7")],
+        ];
+
+        let mut expected: Map<String, Vec<String>> = Map::default();
+        for (key, value) in batches.iter().flatten() {
+            expected
+                .entry(key.to_string())
+                .or_default()
+                .push(value.to_string());
+        }
+
+        // A limit of 1 byte writes one merged chunk per key
+        for limit in [1, usize::MAX] {
+            let dir = temp_dir("spill-order");
+            let mut runs: Runs<String, Vec<String>> = Runs::new(&dir, "test");
+            for batch in &batches {
+                let map: Map<String, Vec<String>> = batch
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), vec![value.to_string()]))
+                    .collect();
+                runs.push(map).unwrap();
+            }
+            assert_eq!(runs.len(), 7);
+
+            let merged: Vec<_> = runs
+                .merge(limit, |values, more| values.extend(more))
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+            assert_eq!(merged, expected.clone().into_iter().collect::<Vec<_>>());
+
+            // Every file was removed once read
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
@@ -4,19 +4,23 @@
 //! not included here and should be next to their dictionary for visibility.
 
 use std::fs::{self, File};
-use std::io::{Seek, Write};
+use std::io::{Cursor, Seek, Write};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::Result;
-use zip::ZipWriter;
+use anyhow::{Context, Result, bail};
+use chrono::{Datelike, Timelike};
+use rayon::prelude::*;
 use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
 
 use crate::cli::Options;
-use crate::dict::core::LabelledYomitanEntry;
+use crate::dict::core::{E, LabelledYomitanEntry};
 use crate::dict::index::get_index;
 use crate::lang::Lang;
 use crate::models::yomitan::YomitanEntry;
 use crate::path::PathManager;
+use crate::progress;
 use crate::tags::get_tag_bank_as_tag_info;
 use crate::utils::{CHECK_C, pretty_print_at_path, pretty_println_at_path};
 
@@ -25,6 +29,108 @@ const BANK_SIZE: usize = 25_000;
 const STYLES_CSS: &[u8] = include_bytes!("../../assets/styles.css");
 const STYLES_CSS_EXPERIMENTAL: &[u8] = include_bytes!("../../assets/styles_experimental.css");
 
+/// When a dictionary was made: the revision of its index, and the time of its zip entries.
+pub(crate) struct Stamp {
+    revision: String,
+    /// `None` leaves the zip crate's default.
+    zip_time: Option<zip::DateTime>,
+}
+
+impl Stamp {
+    pub(crate) fn now() -> Self {
+        Self {
+            revision: revision(chrono::Utc::now()),
+            zip_time: None,
+        }
+    }
+
+    /// A fixed date, in seconds since the Unix epoch.
+    pub(crate) fn at(epoch: i64) -> Result<Self> {
+        let date = chrono::DateTime::from_timestamp(epoch, 0)
+            .with_context(|| format!("invalid timestamp {epoch}"))?;
+        // Zip dates start in 1980
+        let zip_time = u16::try_from(date.year())
+            .ok()
+            .and_then(|year| {
+                zip::DateTime::from_date_and_time(
+                    year,
+                    date.month() as u8,
+                    date.day() as u8,
+                    date.hour() as u8,
+                    date.minute() as u8,
+                    date.second() as u8,
+                )
+                .ok()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            revision: revision(date),
+            zip_time: Some(zip_time),
+        })
+    }
+
+    /// With `--reproducible`, the date of `SOURCE_DATE_EPOCH`, or else of the newest dataset.
+    ///
+    /// With `--stream`, that is the Last-Modified of what was streamed (`streamed`): files on disk
+    /// may be from another dump.
+    pub(crate) fn for_pm(pm: &PathManager, streamed: Option<SystemTime>) -> Result<Self> {
+        if !pm.opts.reproducible {
+            return Ok(Self::now());
+        }
+
+        if let Ok(epoch) = std::env::var(SOURCE_DATE_EPOCH) {
+            let epoch = epoch
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid {SOURCE_DATE_EPOCH}: {epoch}"))?;
+            return Self::at(epoch);
+        }
+
+        let modified = if pm.opts.stream {
+            streamed
+        } else {
+            dataset_modified(pm)
+        };
+        let Some(modified) = modified else {
+            bail!("--reproducible needs {SOURCE_DATE_EPOCH} when the dataset has no date");
+        };
+        let epoch = modified.duration_since(UNIX_EPOCH)?.as_secs();
+        Self::at(i64::try_from(epoch)?)
+    }
+}
+
+const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+fn revision(date: chrono::DateTime<chrono::Utc>) -> String {
+    date.format("%Y.%m.%d").to_string() // needs to be dot separated
+}
+
+/// The newest modification time of the datasets `pm` reads, if they are on disk.
+///
+/// Downloads are dated from kaikki's Last-Modified, and filtered files from their edition.
+fn dataset_modified(pm: &PathManager) -> Option<SystemTime> {
+    if let Some(input) = &pm.opts.input {
+        // Stdin has no date
+        if input.as_os_str() == "-" {
+            return None;
+        }
+        return fs::metadata(input).ok()?.modified().ok();
+    }
+
+    let (edition_pm, source, _) = pm.langs();
+    edition_pm
+        .variants()
+        .into_iter()
+        .filter_map(|edition| {
+            pm.dataset_paths(edition, Some(source))
+                .inner
+                .into_iter()
+                .find_map(|dataset| fs::metadata(dataset.path).ok())
+        })
+        .filter_map(|metadata| metadata.modified().ok())
+        .max()
+}
+
 /// Write yomitan labelled entries in banks to a sink (either disk or zip).
 ///
 /// When zipping, also write metadata (index, css etc.).
@@ -34,6 +140,7 @@ pub fn write_yomitan(
     opts: &Options,
     pm: &PathManager,
     labelled_entries: Vec<LabelledYomitanEntry>,
+    streamed: Option<SystemTime>,
 ) -> Result<()> {
     let mut bank_index = 0;
 
@@ -41,14 +148,7 @@ pub fn write_yomitan(
         let out_dir = pm.dir_temp_dict();
         fs::create_dir_all(&out_dir)?;
         for lentry in labelled_entries {
-            write_banks_to_disk(
-                opts.pretty,
-                opts.quiet,
-                &lentry.entries,
-                &mut bank_index,
-                lentry.label,
-                &out_dir,
-            )?;
+            write_banks_to_disk(opts.pretty, opts.quiet, lentry, &mut bank_index, &out_dir)?;
         }
 
         if !opts.quiet {
@@ -57,6 +157,8 @@ pub fn write_yomitan(
         return Ok(());
     }
 
+    let stamp = Stamp::for_pm(pm, streamed)?;
+
     if opts.output_stdout {
         let stdout = std::io::stdout();
         let mut zip = ZipWriter::new_stream(stdout.lock());
@@ -65,6 +167,7 @@ pub fn write_yomitan(
             target,
             opts,
             pm,
+            &stamp,
             labelled_entries,
             &mut bank_index,
             Path::new("<stdout>"),
@@ -82,6 +185,7 @@ pub fn write_yomitan(
         target,
         opts,
         pm,
+        &stamp,
         labelled_entries,
         &mut bank_index,
         &writer_path,
@@ -94,20 +198,30 @@ pub fn write_yomitan(
     Ok(())
 }
 
-fn write_yomitan_zip<W: Write + Seek>(
+#[expect(clippy::too_many_arguments)]
+pub(crate) fn write_yomitan_zip<W: Write + Seek>(
     source: Lang,
     target: Lang,
     opts: &Options,
     pm: &PathManager,
+    stamp: &Stamp,
     labelled_entries: Vec<LabelledYomitanEntry>,
     bank_index: &mut usize,
     output_path: &Path,
     zip: &mut ZipWriter<W>,
 ) -> Result<()> {
-    let zip_opts =
-        SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut zip_opts = zip_options(opts.compression_level);
+    if let Some(zip_time) = stamp.zip_time {
+        zip_opts = zip_opts.last_modified_time(zip_time);
+    }
 
-    let index_string = get_index(pm.dict_ty, &pm.dict_name_expanded(), source, target);
+    let index_string = get_index(
+        pm.dict_ty,
+        &pm.dict_name_expanded(),
+        source,
+        target,
+        &stamp.revision,
+    );
     zip.start_file("index.json", zip_opts)?;
     zip.write_all(index_string.as_bytes())?;
 
@@ -127,9 +241,8 @@ fn write_yomitan_zip<W: Write + Seek>(
             zip_opts,
             opts.pretty,
             opts.quiet,
-            &lentry.entries,
+            lentry,
             bank_index,
-            lentry.label,
             output_path,
         )?;
     }
@@ -137,25 +250,56 @@ fn write_yomitan_zip<W: Write + Seek>(
     Ok(())
 }
 
+/// Deflate at `level`, or store only if it is 0.
+fn zip_options(level: Option<u8>) -> SimpleFileOptions {
+    match level {
+        Some(0) => SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+        level => SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(level.map(i64::from)),
+    }
+}
+
+/// Pull banks of `BANK_SIZE` entries, with their number and the total if `len` is known.
+///
+/// A bank fails with the first entry that could not be rendered.
+fn banks(
+    entries: E,
+    len: Option<usize>,
+) -> impl Iterator<Item = Result<(usize, Option<usize>, Vec<YomitanEntry>)>> {
+    let total = len.map(|len| len.div_ceil(BANK_SIZE));
+    let mut entries = entries.peekable();
+
+    (1..).map_while(move |bank_num| {
+        entries.peek()?;
+        let bank: Result<Vec<_>> = entries.by_ref().take(BANK_SIZE).collect();
+        Some(bank.map(|bank| (bank_num, total, bank)))
+    })
+}
+
+/// `Wrote yomitan lemma bank 2/5 (25000 entries)`, or `bank 2` if the total is not known.
+fn bank_message(label: &str, bank_num: usize, total: Option<usize>, len: usize) -> String {
+    let total = total.map(|total| format!("/{total}")).unwrap_or_default();
+    format!("Wrote yomitan {label} bank {bank_num}{total} ({len} entries)")
+}
+
 /// Writes `yomitan_entries` in batches to disk.
 #[tracing::instrument(skip_all, level = "DEBUG")]
 fn write_banks_to_disk(
     pretty: bool,
     quiet: bool,
-    yomitan_entries: &[YomitanEntry],
+    lentry: LabelledYomitanEntry,
     bank_index: &mut usize,
-    label: &str,
     out_dir: &Path,
 ) -> Result<()> {
-    let bank_name_prefix = match yomitan_entries.first() {
-        Some(first) => first.file_prefix(),
-        None => return Ok(()),
-    };
+    let mut wrote_any = false;
+    let label = lentry.label;
 
-    let total_bank_num = yomitan_entries.len().div_ceil(BANK_SIZE);
-
-    for (bank_num, bank) in yomitan_entries.chunks(BANK_SIZE).enumerate() {
+    for bank in banks(lentry.entries, lentry.len) {
+        let (bank_num, total, bank) = bank?;
+        let bank_name_prefix = bank[0].file_prefix();
         *bank_index += 1;
+        wrote_any = true;
 
         let bank_name = format!("{bank_name_prefix}_{bank_index}.json");
         let file_path = out_dir.join(&bank_name);
@@ -167,79 +311,237 @@ fn write_banks_to_disk(
         }
 
         if !quiet {
-            if bank_num > 0 {
-                print!("\r\x1b[K");
-            }
             pretty_print_at_path(
-                &format!(
-                    "Wrote yomitan {label} bank {}/{total_bank_num} ({} entries)",
-                    bank_num + 1,
-                    bank.len()
-                ),
+                &bank_message(label, bank_num, total, bank.len()),
                 &file_path,
             );
-            std::io::stdout().flush()?;
         }
     }
 
-    if !quiet {
-        println!();
+    if !quiet && wrote_any {
+        progress::end_status();
     }
 
     Ok(())
 }
 
 /// Writes `yomitan_entries` in batches to a zip writer.
+///
+/// Banks are serialized and compressed in parallel, a few at a time, each into a zip of its own.
+/// These are then appended, already compressed, in order.
 #[tracing::instrument(skip_all, level = "DEBUG")]
 fn write_banks_to_zip<W: Write + Seek>(
     zip: &mut ZipWriter<W>,
     zip_options: SimpleFileOptions,
     pretty: bool,
     quiet: bool,
-    yomitan_entries: &[YomitanEntry],
+    lentry: LabelledYomitanEntry,
     bank_index: &mut usize,
-    label: &str,
     output_path: &Path,
 ) -> Result<()> {
-    let bank_name_prefix = match yomitan_entries.first() {
-        Some(first) => first.file_prefix(),
-        None => return Ok(()),
-    };
-
-    let total_bank_num = yomitan_entries.len().div_ceil(BANK_SIZE);
-
-    for (bank_num, bank) in yomitan_entries.chunks(BANK_SIZE).enumerate() {
-        *bank_index += 1;
+    let mut wrote_any = false;
+    let label = lentry.label;
+    let mut banks = banks(lentry.entries, lentry.len);
+    // Bounds how many banks are held in memory at once
+    let batch_size = rayon::current_num_threads();
+
+    loop {
+        let batch: Vec<_> = banks
+            .by_ref()
+            .take(batch_size)
+            .map(|bank| {
+                let (bank_num, total, bank) = bank?;
+                *bank_index += 1;
+                let bank_name = format!("{}_{bank_index}.json", bank[0].file_prefix());
+                Ok((bank_name, bank_num, total, bank))
+            })
+            .collect::<Result<_>>()?;
+        if batch.is_empty() {
+            break;
+        }
 
-        let bank_name = format!("{bank_name_prefix}_{bank_index}.json");
-        let file_path = output_path.join(&bank_name);
+        let compressed: Vec<_> = batch
+            .par_iter()
+            .map(|(bank_name, _, _, bank)| compress_bank(bank_name, bank, zip_options, pretty))
+            .collect();
 
-        zip.start_file(&bank_name, zip_options)?;
-        if pretty {
-            serde_json::to_writer_pretty(&mut *zip, &bank)?;
-        } else {
-            serde_json::to_writer(&mut *zip, &bank)?;
-        }
+        for ((bank_name, bank_num, total, bank), compressed) in batch.into_iter().zip(compressed) {
+            zip.merge_archive(ZipArchive::new(Cursor::new(compressed?))?)?;
+            wrote_any = true;
 
-        if !quiet {
-            if bank_num > 0 {
-                print!("\r\x1b[K");
+            if quiet {
+                continue;
             }
+            let file_path = output_path.join(&bank_name);
             pretty_print_at_path(
-                &format!(
-                    "Wrote yomitan {label} bank {}/{total_bank_num} ({} entries)",
-                    bank_num + 1,
-                    bank.len()
-                ),
+                &bank_message(label, bank_num, total, bank.len()),
                 &file_path,
             );
-            std::io::stdout().flush()?;
         }
     }
 
-    if !quiet {
-        println!();
+    if !quiet && wrote_any {
+        progress::end_status();
     }
 
     Ok(())
 }
+
+/// Serialize and compress `bank` as the only file of an in-memory zip.
+fn compress_bank(
+    bank_name: &str,
+    bank: &[YomitanEntry],
+    zip_options: SimpleFileOptions,
+    pretty: bool,
+) -> Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(bank_name, zip_options)?;
+    if pretty {
+        serde_json::to_writer_pretty(&mut zip, bank)?;
+    } else {
+        serde_json::to_writer(&mut zip, bank)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::yomitan::TermBankSimplified;
+    use crate::utils::temp_dir;
+
+    fn entries(n: usize) -> impl Iterator<Item = Result<YomitanEntry>> {
+        (0..n).map(|i| {
+            Ok(YomitanEntry::TermBankSimplified(TermBankSimplified(
+                i.to_string(),
+                String::new(),
+                Vec::new(),
+            )))
+        })
+    }
+
+    #[test]
+    fn banks_are_pulled_in_chunks() {
+        let sizes = |lentry: LabelledYomitanEntry| -> Vec<_> {
+            banks(lentry.entries, lentry.len)
+                .map(|bank| bank.map(|(num, total, bank)| (num, total, bank.len())))
+                .collect::<Result<_>>()
+                .unwrap()
+        };
+
+        let exact = LabelledYomitanEntry::fallible("lemma", entries(BANK_SIZE + 1));
+        assert_eq!(sizes(exact), [(1, Some(2), BANK_SIZE), (2, Some(2), 1)]);
+
+        // A flat_map, like the lemmas, can not tell its length: the total is unknown unless given
+        let flat = || (0..2).flat_map(|_| entries(BANK_SIZE));
+        let unknown = LabelledYomitanEntry::fallible("lemma", flat());
+        assert_eq!(sizes(unknown), [(1, None, BANK_SIZE), (2, None, BANK_SIZE)]);
+        let given = LabelledYomitanEntry::fallible("lemma", flat()).with_len(BANK_SIZE * 2);
+        assert_eq!(
+            sizes(given),
+            [(1, Some(2), BANK_SIZE), (2, Some(2), BANK_SIZE)]
+        );
+
+        assert!(sizes(LabelledYomitanEntry::fallible("lemma", entries(0))).is_empty());
+    }
+
+    #[test]
+    fn banks_are_zipped_in_order() {
+        for level in [Some(0), Some(1)] {
+            let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+            let mut bank_index = 0;
+            write_banks_to_zip(
+                &mut zip,
+                zip_options(level),
+                false,
+                true,
+                LabelledYomitanEntry::fallible("lemma", entries(BANK_SIZE * 2 + 1)),
+                &mut bank_index,
+                Path::new("<test>"),
+            )
+            .unwrap();
+            assert_eq!(bank_index, 3);
+
+            let mut zip = ZipArchive::new(zip.finish().unwrap()).unwrap();
+            let names: Vec<_> = zip.file_names().collect();
+            assert_eq!(
+                names,
+                ["term_bank_1.json", "term_bank_2.json", "term_bank_3.json"]
+            );
+
+            let expected_method = if level == Some(0) {
+                CompressionMethod::Stored
+            } else {
+                CompressionMethod::Deflated
+            };
+            for idx in 0..zip.len() {
+                let file = zip.by_index(idx).unwrap();
+                assert_eq!(file.compression(), expected_method);
+                let bank: Vec<serde_json::Value> = serde_json::from_reader(file).unwrap();
+                // The first entry of the n-th bank is the number (n - 1) * BANK_SIZE
+                assert_eq!(bank[0][0], (idx * BANK_SIZE).to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn streamed_stamps_ignore_files_on_disk() {
+        let root_dir = temp_dir("stamp");
+        let args = crate::cli::MainArgs {
+            langs: crate::cli::MainLangs {
+                source: Lang::De,
+                target: crate::lang::Edition::En,
+            },
+            dict_name: crate::cli::DictName::default(),
+            options: Options {
+                stream: true,
+                reproducible: true,
+                root_dir: root_dir.clone(),
+                ..Default::default()
+            },
+        };
+        let pm: PathManager = args.try_into().unwrap();
+        // A stale dump from an earlier download
+        fs::create_dir_all(pm.dir_kaik()).unwrap();
+        fs::write(pm.dir_kaik().join("en-extract.jsonl"), "").unwrap();
+
+        let streamed = UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let stamp = Stamp::for_pm(&pm, Some(streamed)).unwrap();
+        assert_eq!(stamp.revision, "2023.11.14");
+        assert!(Stamp::for_pm(&pm, None).is_err());
+
+        let _ = fs::remove_dir_all(root_dir);
+    }
+
+    #[test]
+    fn stamps_from_epoch() {
+        let stamp = Stamp::at(1_700_000_000).unwrap();
+        assert_eq!(stamp.revision, "2023.11.14");
+        let zip_time = stamp.zip_time.unwrap();
+        assert_eq!(
+            (zip_time.year(), zip_time.month(), zip_time.day()),
+            (2023, 11, 14)
+        );
+        assert_eq!(
+            (zip_time.hour(), zip_time.minute(), zip_time.second()),
+            (22, 13, 20)
+        );
+
+        // Before 1980, zip entries fall back to the earliest date they can hold
+        let stamp = Stamp::at(0).unwrap();
+        assert_eq!(stamp.revision, "1970.01.01");
+        assert_eq!(stamp.zip_time.unwrap(), zip::DateTime::default());
+    }
+
+    #[test]
+    fn bank_messages() {
+        assert_eq!(
+            bank_message("lemma", 2, Some(5), 10),
+            "Wrote yomitan lemma bank 2/5 (10 entries)"
+        );
+        assert_eq!(
+            bank_message("form", 2, None, 10),
+            "Wrote yomitan form bank 2 (10 entries)"
+        );
+    }
+}
@@ -40,27 +40,50 @@ mod html {
     use std::io::BufRead;
     use std::io::{BufReader, BufWriter};
     use std::path::Path;
+    use std::time::SystemTime;
 
     use crate::{
         lang::Edition,
+        progress::{self, Progress},
         utils::{CHECK_C, pretty_println_at_path},
     };
 
+    type Decoder = GzDecoder<Box<dyn std::io::Read + Send>>;
+
     // In the past, we supported downloading the post-processed, English-edition-only,
     // filtered datasets.
     // Those became deprecated cf. <https://github.com/tatuylonen/wiktextract/issues/1178>
     // but also caused some issues due to not being structured as their "raw" counterparts.
     //
-    fn jsonl_reader(edition: Edition) -> Result<GzDecoder<Box<dyn std::io::Read>>> {
+    // Progress counts compressed bytes, since Content-Length is all we know of the total.
+    //
+    // Also returns when the dataset was last modified, according to kaikki.
+    fn jsonl_reader(edition: Edition, quiet: bool) -> Result<(Decoder, Option<SystemTime>)> {
         let url = url_jsonl_gz(edition)?;
         let response = ureq::get(url).call()?;
 
-        if let Some(last_modified) = response.headers().get("last-modified") {
-            tracing::info!("Download was last modified: {:?}", last_modified);
+        let last_modified = response
+            .headers()
+            .get("last-modified")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| chrono::DateTime::parse_from_rfc2822(value).ok());
+        if let Some(last_modified) = last_modified {
+            tracing::info!("Download was last modified: {last_modified}");
         }
-
-        let reader: Box<dyn std::io::Read> = Box::new(response.into_body().into_reader());
-        Ok(GzDecoder::new(reader))
+        let total = response
+            .headers()
+            .get("content-length")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+
+        let reader = Progress::reader(
+            format!("⬇ {edition}"),
+            total,
+            !quiet,
+            response.into_body().into_reader(),
+        );
+        let reader: Box<dyn std::io::Read + Send> = Box::new(reader);
+        Ok((GzDecoder::new(reader), last_modified.map(SystemTime::from)))
     }
 
     /// Download the "raw" jsonl (jsonlines) from kaikki and write it to `path_jsonl`.
@@ -68,21 +91,26 @@ mod html {
     /// "Raw" means that it does not include extra information, not intended for general use,
     /// that they (kaikki) use for their website generation.
     ///
-    /// Does not write the .gz file to disk.
+    /// Does not write the .gz file to disk. The file is dated from the dump's Last-Modified, which
+    /// `--reproducible` builds use as their revision.
     ///
     /// WARN: expects `path_jsonl` to be a valid path (with existing parents etc.)
     pub fn download_jsonl_to_path(edition: Edition, path_jsonl: &Path, quiet: bool) -> Result<()> {
         let url = url_jsonl_gz(edition)?;
         if !quiet {
-            println!("⬇ Downloading {url}");
+            progress::println(&format!("⬇ Downloading {url}"));
         }
 
         // We can't use gzip's ureq feature because there is no content-encoding in headers
         // https://github.com/tatuylonen/wiktextract/issues/1482
-        let mut decoder = jsonl_reader(edition)?;
+        let (mut decoder, last_modified) = jsonl_reader(edition, quiet)?;
 
         let mut writer = BufWriter::new(File::create(path_jsonl)?);
         std::io::copy(&mut decoder, &mut writer)?;
+        let file = writer.into_inner()?;
+        if let Some(last_modified) = last_modified {
+            file.set_modified(last_modified)?;
+        }
 
         if !quiet {
             pretty_println_at_path(&format!("{CHECK_C} Downloaded"), path_jsonl);
@@ -91,19 +119,24 @@ mod html {
         Ok(())
     }
 
+    /// Stream the "raw" jsonl from kaikki, without writing it to disk.
+    ///
+    /// Also returns when the dataset was last modified, which `--reproducible` builds use as their
+    /// revision.
     pub fn stream_jsonl_reader(
         edition: Edition,
         quiet: bool,
         capacity: usize,
-    ) -> Result<Box<dyn BufRead>> {
+    ) -> Result<(Box<dyn BufRead + Send>, Option<SystemTime>)> {
         let url = url_jsonl_gz(edition)?;
         if !quiet {
-            println!("⬇ Streaming {url}");
+            progress::println(&format!("⬇ Streaming {url}"));
         }
 
-        Ok(Box::new(BufReader::with_capacity(
-            capacity,
-            jsonl_reader(edition)?,
-        )))
+        let (decoder, last_modified) = jsonl_reader(edition, quiet)?;
+        Ok((
+            Box::new(BufReader::with_capacity(capacity, decoder)),
+            last_modified,
+        ))
     }
 }
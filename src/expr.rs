@@ -0,0 +1,483 @@
+//! Expression language to select entries (`--where`).
+//!
+//! ```text
+//! pos in [noun, verb] and not tags == archaic
+//! sense_topics == medicine or categories ~ "^Medicine"
+//! word ^= un and senses >= 2
+//! has_ipa and has_translations_to(de)
+//! ```
+//!
+//! Grammar, from lowest to highest precedence:
+//!
+//! ```text
+//! expr := and ("or" and)*
+//! and  := not ("and" not)*
+//! not  := "not" not | "(" expr ")" | pred
+//! pred := FIELD ("==" | "!=" | "~" | "^=") VALUE
+//!       | FIELD "in" "[" VALUE ("," VALUE)* "]"
+//!       | "senses" ("==" | "!=" | "<" | "<=" | ">" | ">=") NUMBER
+//!       | "has_ipa"
+//!       | "has_translations_to" "(" VALUE ")"
+//! ```
+//!
+//! Text fields are `word`, `pos` and `lang_code`. List fields match if any of their items does:
+//! `tags`, `topics`, `sense_tags`, `sense_topics` and `categories` (of the entry and its senses).
+//! `~` is a regex search, `^=` a prefix match. Values are bare words or quoted strings, and dashes
+//! in names are read as underscores (`has-ipa`).
+
+use std::fmt;
+use std::str::FromStr;
+
+use regex::Regex;
+
+use crate::models::kaikki::WordEntry;
+
+/// A parsed `--where` expression.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    source: String,
+    node: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+    Not(Box<Self>),
+    Match(Field, Matcher),
+    Senses(Cmp, usize),
+    HasIpa,
+    HasTranslationsTo(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Word,
+    Pos,
+    LangCode,
+    Tags,
+    Topics,
+    SenseTags,
+    SenseTopics,
+    Categories,
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Eq(String),
+    In(Vec<String>),
+    Regex(Regex),
+    Prefix(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Expr {
+    /// Whether `entry` satisfies the expression.
+    pub fn eval(&self, entry: &WordEntry) -> bool {
+        self.node.eval(entry)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let node = parser.expr()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {token}"));
+        }
+        Ok(Self {
+            source: s.to_string(),
+            node,
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Node {
+    fn eval(&self, entry: &WordEntry) -> bool {
+        match self {
+            Self::And(lhs, rhs) => lhs.eval(entry) && rhs.eval(entry),
+            Self::Or(lhs, rhs) => lhs.eval(entry) || rhs.eval(entry),
+            Self::Not(node) => !node.eval(entry),
+            Self::Match(field, matcher) => field
+                .values(entry)
+                .into_iter()
+                .any(|value| matcher.is_match(value)),
+            Self::Senses(cmp, n) => cmp.compare(entry.senses.len(), *n),
+            Self::HasIpa => entry.sounds.iter().any(|sound| !sound.ipa.is_empty()),
+            Self::HasTranslationsTo(lang_code) => entry
+                .non_trivial_translations()
+                .any(|translation| translation.lang_code == *lang_code),
+        }
+    }
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "word" => Self::Word,
+            "pos" => Self::Pos,
+            "lang_code" => Self::LangCode,
+            "tags" => Self::Tags,
+            "topics" => Self::Topics,
+            "sense_tags" => Self::SenseTags,
+            "sense_topics" => Self::SenseTopics,
+            "categories" => Self::Categories,
+            _ => return None,
+        })
+    }
+
+    fn values(self, entry: &WordEntry) -> Vec<&str> {
+        let senses = || entry.senses.iter();
+        match self {
+            Self::Word => vec![&entry.word],
+            Self::Pos => vec![&entry.pos],
+            Self::LangCode => vec![&entry.lang_code],
+            Self::Tags => entry.tags.iter().map(String::as_str).collect(),
+            Self::Topics => entry.topics.iter().map(String::as_str).collect(),
+            Self::SenseTags => senses().flat_map(|s| &s.tags).map(String::as_str).collect(),
+            Self::SenseTopics => senses()
+                .flat_map(|s| &s.topics)
+                .map(String::as_str)
+                .collect(),
+            Self::Categories => entry
+                .categories
+                .iter()
+                .chain(senses().flat_map(|s| &s.categories))
+                .map(String::as_str)
+                .collect(),
+        }
+    }
+}
+
+impl Matcher {
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            Self::Eq(expected) => value == expected,
+            Self::In(expected) => expected.iter().any(|e| e == value),
+            Self::Regex(re) => re.is_match(value),
+            Self::Prefix(prefix) => value.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+impl Cmp {
+    fn from_op(op: &str) -> Option<Self> {
+        Some(match op {
+            "==" => Self::Eq,
+            "!=" => Self::Ne,
+            "<" => Self::Lt,
+            "<=" => Self::Le,
+            ">" => Self::Gt,
+            ">=" => Self::Ge,
+            _ => return None,
+        })
+    }
+
+    const fn compare(self, lhs: usize, rhs: usize) -> bool {
+        match self {
+            Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A bare word: name, keyword or value.
+    Word(String),
+    /// A quoted string.
+    Str(String),
+    Op(&'static str),
+    Punct(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Word(word) => write!(f, "'{word}'"),
+            Self::Str(s) => write!(f, "\"{s}\""),
+            Self::Op(op) => write!(f, "'{op}'"),
+            Self::Punct(c) => write!(f, "'{c}'"),
+        }
+    }
+}
+
+// Longest first, so that `<=` is not read as `<`.
+const OPS: [&str; 8] = ["==", "!=", "^=", "<=", ">=", "<", ">", "~"];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if "()[],".contains(c) {
+            tokens.push(Token::Punct(c));
+            rest = &rest[1..];
+        } else if c == '"' || c == '\'' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| format!("unterminated string: {rest}"))?;
+            tokens.push(Token::Str(rest[1..=end].to_string()));
+            rest = &rest[end + 2..];
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if is_word_char(c) {
+            let end = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            return Err(format!("unexpected character '{c}'"));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-.:".contains(c)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(word)) if word == keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_punct(&mut self, expected: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Punct(c) if c == expected => Ok(()),
+            token => Err(format!("expected '{expected}', found {token}")),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Node, String> {
+        let mut node = self.and()?;
+        while self.eat_keyword("or") {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut node = self.not()?;
+        while self.eat_keyword("and") {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, String> {
+        if self.eat_keyword("not") {
+            return Ok(Node::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::Punct('(')) {
+            self.pos += 1;
+            let node = self.expr()?;
+            self.expect_punct(')')?;
+            return Ok(node);
+        }
+        self.pred()
+    }
+
+    fn pred(&mut self) -> Result<Node, String> {
+        let name = match self.next()? {
+            Token::Word(word) => word.replace('-', "_"),
+            token => return Err(format!("expected a field, found {token}")),
+        };
+
+        match name.as_str() {
+            "has_ipa" => return Ok(Node::HasIpa),
+            "has_translations_to" => {
+                self.expect_punct('(')?;
+                let lang_code = self.value()?;
+                self.expect_punct(')')?;
+                return Ok(Node::HasTranslationsTo(lang_code));
+            }
+            "senses" => {
+                let cmp = match self.next()? {
+                    Token::Op(op) => Cmp::from_op(op),
+                    _ => None,
+                }
+                .ok_or("expected a comparison after 'senses'")?;
+                let value = self.value()?;
+                let n = value
+                    .parse()
+                    .map_err(|_| format!("expected a number, found '{value}'"))?;
+                return Ok(Node::Senses(cmp, n));
+            }
+            _ => (),
+        }
+
+        let field = Field::from_name(&name).ok_or_else(|| {
+            format!(
+                "unknown field '{name}'. Choose between: word | pos | lang_code | tags | topics | \
+                 sense_tags | sense_topics | categories | senses | has_ipa | has_translations_to"
+            )
+        })?;
+
+        if self.eat_keyword("in") {
+            self.expect_punct('[')?;
+            let mut values = vec![self.value()?];
+            while self.peek() == Some(&Token::Punct(',')) {
+                self.pos += 1;
+                values.push(self.value()?);
+            }
+            self.expect_punct(']')?;
+            return Ok(Node::Match(field, Matcher::In(values)));
+        }
+
+        let op = match self.next()? {
+            Token::Op(op) => op,
+            token => {
+                return Err(format!(
+                    "expected an operator after '{name}', found {token}"
+                ));
+            }
+        };
+        let value = self.value()?;
+        let matcher = match op {
+            "==" | "!=" => Matcher::Eq(value),
+            "^=" => Matcher::Prefix(value),
+            "~" => Matcher::Regex(Regex::new(&value).map_err(|err| err.to_string())?),
+            _ => return Err(format!("'{op}' can only compare 'senses'")),
+        };
+        let node = Node::Match(field, matcher);
+
+        Ok(if op == "!=" {
+            Node::Not(Box::new(node))
+        } else {
+            node
+        })
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Word(word) | Token::Str(word) => Ok(word),
+            token => Err(format!("expected a value, found {token}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> WordEntry {
+        serde_json::from_str(
+            r#"{
+                "word": "Herz",
+                "pos": "noun",
+                "lang_code": "de",
+                "tags": ["neuter"],
+                "categories": ["German nouns"],
+                "sounds": [{"ipa": "/hɛʁt͡s/"}],
+                "translations": [{"lang_code": "fr", "word": "cœur"}],
+                "senses": [
+                    {"glosses": ["heart"], "topics": ["anatomy", "medicine"]},
+                    {"glosses": ["hearts"], "tags": ["card-games"], "categories": ["de:Cards"]}
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn eval(s: &str) -> bool {
+        s.parse::<Expr>().unwrap().eval(&entry())
+    }
+
+    #[test]
+    fn predicates() {
+        assert!(eval("word == Herz"));
+        assert!(eval("pos != verb"));
+        assert!(eval("pos in [verb, noun]"));
+        assert!(eval("word ^= He"));
+        assert!(eval("word ~ '^H.r'"));
+        assert!(eval("tags == neuter"));
+        assert!(eval("sense_topics == medicine"));
+        assert!(eval("sense-tags == card-games"));
+        assert!(eval("categories ~ Cards"));
+        assert!(eval("senses >= 2 and senses < 3"));
+        assert!(eval("has-ipa"));
+        assert!(eval("has_translations_to(fr)"));
+        assert!(!eval("has_translations_to(en)"));
+        assert!(!eval("topics == medicine"));
+    }
+
+    #[test]
+    fn combinators() {
+        assert!(eval("pos == verb or pos == noun"));
+        assert!(!eval("pos == verb or pos == adj and word == Herz"));
+        assert!(eval(
+            "(pos == verb or pos == noun) and not tags == feminine"
+        ));
+        assert!(eval("not not word == Herz"));
+    }
+
+    #[test]
+    fn errors() {
+        for s in [
+            "",
+            "pos",
+            "pos ==",
+            "color == red",
+            "pos < noun",
+            "senses >= many",
+            "(pos == noun",
+            "pos in [noun",
+            "word ~ '('",
+            "word == 'Herz",
+            "pos == noun noun",
+        ] {
+            assert!(s.parse::<Expr>().is_err(), "{s}");
+        }
+    }
+}
@@ -0,0 +1,423 @@
+//! Testsuite maintenance.
+//!
+//! `wty fixture add de en faul` pulls every jsonline of a word out of a kaikki dump and appends
+//! it to `tests/kaikki/de-en-extract.jsonl`, registering it in `tests/registry.json`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+use crate::Map;
+use crate::cli::{
+    DictName, FilterKey, FixtureAddArgs, FixtureArgs, FixtureCommand, GlossaryArgs, GlossaryLangs,
+    IpaArgs, MainArgs, MainLangs, Options,
+};
+use crate::dict::{DGlossary, DIpa, DMain, find_or_download_jsonl, make_dict};
+use crate::lang::{Edition, Lang};
+use crate::models::kaikki::WordEntry;
+use crate::path::{DictionaryType, PathManager};
+use crate::utils::{CHECK_C, SKIP_C, pretty_println_at_path};
+
+pub fn fixture(args: FixtureArgs) -> Result<()> {
+    match args.command {
+        FixtureCommand::Add(args) => fixture_add(args),
+    }
+}
+
+fn fixture_add(args: FixtureAddArgs) -> Result<()> {
+    let source = args.langs.source;
+    let edition = args.langs.target;
+    let quiet = args.options.quiet;
+
+    let mut filters = vec![(FilterKey::Word, args.word.clone())];
+    if let Some(pos) = &args.pos {
+        filters.push((FilterKey::Pos, pos.clone()));
+    }
+    let lang_code = args.lang_code.clone().unwrap_or_else(|| source.to_string());
+    filters.push((FilterKey::LangCode, lang_code));
+
+    let pm = PathManager {
+        dict_ty: DictionaryType::Main,
+        dict_name: DictName::default(),
+        langs: args.langs.clone().try_into()?,
+        opts: args.options.clone().into(),
+    };
+    let _ = fs::create_dir_all(pm.dir_kaik());
+    let dump_path = find_or_download_jsonl(edition, Some(source), &pm)?;
+
+    let matches = find_lines(&dump_path, &args.word, &filters)?;
+    if matches.is_empty() {
+        bail!(
+            "No entries found for '{}' @ {}",
+            args.word,
+            dump_path.display()
+        );
+    }
+
+    let fixture_path = fixture_path(&args.tests_dir, source, edition);
+    let added = append_new_lines(&fixture_path, &matches)?;
+
+    if added.is_empty() {
+        if !quiet {
+            let msg = format!("{SKIP_C} Skipping fixture: every entry is already in the testsuite");
+            pretty_println_at_path(&msg, &fixture_path);
+        }
+        return Ok(());
+    }
+
+    update_registry(&args.tests_dir, source, edition, &added)?;
+
+    if !quiet {
+        let msg = format!("{CHECK_C} Added {} entries to fixture", added.len());
+        pretty_println_at_path(&msg, &fixture_path);
+    }
+
+    if args.snapshot {
+        regenerate_snapshot(source, edition, &args.tests_dir)?;
+    }
+
+    Ok(())
+}
+
+fn fixture_path(tests_dir: &Path, source: Lang, edition: Edition) -> PathBuf {
+    tests_dir
+        .join("kaikki")
+        .join(format!("{source}-{edition}-extract.jsonl"))
+}
+
+/// Return the jsonlines of the dump that match every filter, as they appear in the dump.
+fn find_lines(path: &Path, word: &str, filters: &[(FilterKey, String)]) -> Result<Vec<String>> {
+    let reader = BufReader::new(File::open(path)?);
+    // Cheap probe to avoid deserializing every line: the word must appear quoted somewhere.
+    let needle = serde_json::to_string(word)?;
+    let mut lines = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        if !line.contains(&needle) {
+            continue;
+        }
+        let entry: WordEntry = serde_json::from_str(&line)
+            .with_context(|| format!("Failed to parse line @ {}", path.display()))?;
+        if filters.iter().all(|(k, v)| k.field_value(&entry) == v) {
+            lines.push(line.trim_end().to_string());
+        }
+    }
+
+    Ok(lines)
+}
+
+/// Append to the fixture the lines that are not already there and return them.
+///
+/// Lines are compared as json values so that formatting differences do not matter.
+fn append_new_lines<'a>(fixture_path: &Path, lines: &'a [String]) -> Result<Vec<&'a str>> {
+    let mut seen: Vec<Value> = match fs::read_to_string(fixture_path) {
+        Ok(content) => content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut added = Vec::new();
+    let mut to_write = String::new();
+    for line in lines {
+        let value: Value = serde_json::from_str(line)?;
+        if seen.contains(&value) {
+            continue;
+        }
+        to_write.push_str(line);
+        to_write.push('\n');
+        seen.push(value);
+        added.push(line.as_str());
+    }
+
+    if !to_write.is_empty() {
+        if let Some(parent) = fixture_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(fixture_path)?;
+        file.write_all(to_write.as_bytes())?;
+    }
+
+    Ok(added)
+}
+
+/// The kaikki page where this word can be found, as used by `scripts/update_tests.py`.
+fn registry_download_url(edition: Edition, word: &str) -> String {
+    let chars: Vec<_> = word.chars().collect();
+    let first: String = chars.iter().take(1).collect();
+    let first_two: String = chars.iter().take(2).collect();
+    let dictionary = match edition {
+        Edition::En => "dictionary",
+        other => &format!("{other}wiktionary"),
+    };
+
+    format!(
+        "https://kaikki.org/{dictionary}/All%20languages%20combined/meaning/{first}/{first_two}/{word}.jsonl"
+    )
+}
+
+/// `tests/registry.json`: entries by source and edition, in the order of the file.
+type Registry = Map<String, Map<String, Vec<RegistryEntry>>>;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RegistryEntry {
+    url: String,
+    download_url: String,
+    json: Json,
+}
+
+/// A json value that, unlike `serde_json::Value`, keeps the order of object keys.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(String),
+    Array(Vec<Json>),
+    Object(Map<String, Json>),
+}
+
+fn update_registry(tests_dir: &Path, source: Lang, edition: Edition, added: &[&str]) -> Result<()> {
+    let registry_path = tests_dir.join("registry.json");
+    let mut registry: Registry = match fs::read_to_string(&registry_path) {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("Unexpected registry format @ {}", registry_path.display()))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Registry::default(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let entries = registry
+        .entry(source.to_string())
+        .or_default()
+        .entry(edition.to_string())
+        .or_default();
+
+    for line in added {
+        let Json::Object(mut object) = serde_json::from_str(line)? else {
+            bail!("Not a json object: {line}");
+        };
+        let word = match object.get("word") {
+            Some(Json::String(word)) => word.as_str(),
+            _ => "",
+        };
+        let download_url = registry_download_url(edition, word);
+        move_word_and_pos_first(&mut object);
+        entries.push(RegistryEntry {
+            url: download_url.replace(".jsonl", ".html"),
+            download_url,
+            json: Json::Object(object),
+        });
+    }
+
+    // Same formatting as the python script (indent=2, no trailing newline)
+    fs::write(&registry_path, serde_json::to_string_pretty(&registry)?)?;
+
+    Ok(())
+}
+
+fn move_word_and_pos_first(object: &mut Map<String, Json>) {
+    let mut front = 0;
+    for key in ["word", "pos"] {
+        if let Some(idx) = object.get_index_of(key) {
+            object.move_index(idx, front);
+            front += 1;
+        }
+    }
+}
+
+/// Same dictionaries as the snapshot test, restricted to this language pair.
+fn regenerate_snapshot(source: Lang, edition: Edition, tests_dir: &Path) -> Result<()> {
+    make_dict(DMain, snapshot_main_args(source, edition, tests_dir))?;
+    snapshot_ipa(source, edition, tests_dir)?;
+
+    if Lang::from(edition) == source {
+        let langs = langs_in_testsuite(&snapshot_cases(tests_dir)?);
+        snapshot_glossaries(edition, &langs, tests_dir)?;
+    }
+
+    Ok(())
+}
+
+/// Snapshot dictionaries are written, pretty printed, as temp files under `tests_dir/dict`.
+pub fn snapshot_options(tests_dir: &Path) -> Options {
+    Options {
+        save_temps: true,
+        pretty: true,
+        root_dir: tests_dir.to_path_buf(),
+        ..Default::default()
+    }
+}
+
+/// The `(source, target)` of every `tests_dir/kaikki/{source}-{target}-extract.jsonl`.
+pub fn snapshot_cases(tests_dir: &Path) -> Result<Vec<(Lang, Lang)>> {
+    let mut cases = Vec::new();
+
+    for entry in fs::read_dir(tests_dir.join("kaikki"))? {
+        let path = entry?.path();
+        if let Some(fname) = path.file_name().and_then(|f| f.to_str())
+            && let Some(base) = fname.strip_suffix("-extract.jsonl")
+            && let Some((source, target)) = base.split_once('-')
+        {
+            let source: Lang = source.parse().map_err(anyhow::Error::msg)?;
+            let target: Lang = target.parse().map_err(anyhow::Error::msg)?;
+            cases.push((source, target));
+        }
+    }
+
+    Ok(cases)
+}
+
+/// Every language that appears in `cases`, in order of appearance.
+pub fn langs_in_testsuite(cases: &[(Lang, Lang)]) -> Vec<Lang> {
+    let mut langs = Vec::new();
+    for lang in cases.iter().flat_map(|&(source, target)| [source, target]) {
+        if !langs.contains(&lang) {
+            langs.push(lang);
+        }
+    }
+    langs
+}
+
+pub fn snapshot_main_args(source: Lang, edition: Edition, tests_dir: &Path) -> MainArgs {
+    MainArgs {
+        langs: MainLangs {
+            source,
+            target: edition,
+        },
+        dict_name: DictName::default(),
+        options: snapshot_options(tests_dir),
+    }
+}
+
+pub fn snapshot_ipa(source: Lang, edition: Edition, tests_dir: &Path) -> Result<()> {
+    let args = IpaArgs {
+        langs: MainLangs {
+            source,
+            target: edition,
+        },
+        dict_name: DictName::default(),
+        options: snapshot_options(tests_dir),
+    };
+    make_dict(DIpa, args).map(drop)
+}
+
+/// Glossaries from `edition` to every other language in `langs`.
+pub fn snapshot_glossaries(edition: Edition, langs: &[Lang], tests_dir: &Path) -> Result<()> {
+    for &target in langs {
+        if Lang::from(edition) == target {
+            continue;
+        }
+        let args = GlossaryArgs {
+            langs: GlossaryLangs {
+                source: edition,
+                target,
+            },
+            dict_name: DictName::default(),
+            options: snapshot_options(tests_dir),
+        };
+        make_dict(DGlossary, args)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cli::FixtureOptions;
+    use crate::utils::temp_dir;
+
+    fn add_args(
+        root_dir: &Path,
+        tests_dir: &Path,
+        word: &str,
+        pos: Option<&str>,
+    ) -> FixtureAddArgs {
+        FixtureAddArgs {
+            langs: MainLangs {
+                source: Lang::De,
+                target: Edition::En,
+            },
+            word: word.to_string(),
+            pos: pos.map(str::to_string),
+            lang_code: None,
+            snapshot: false,
+            tests_dir: tests_dir.to_path_buf(),
+            options: FixtureOptions {
+                redownload: false,
+                quiet: true,
+                root_dir: root_dir.to_path_buf(),
+            },
+        }
+    }
+
+    #[test]
+    fn add_word_from_dump() {
+        let root = temp_dir("fixture-add");
+        let root_dir = root.join("data");
+        let tests_dir = root.join("tests");
+        fs::create_dir_all(root_dir.join("kaikki")).unwrap();
+        fs::create_dir_all(tests_dir.join("kaikki")).unwrap();
+
+        let dump = [
+            r#"{"lang_code": "de", "pos": "adj", "word": "faul", "senses": [{"glosses": ["lazy"]}]}"#,
+            r#"{"lang_code": "de", "pos": "verb", "word": "faulen", "senses": [{"glosses": ["to rot"]}]}"#,
+            r#"{"lang_code": "de", "pos": "noun", "word": "faul", "senses": [{"glosses": ["foul"]}]}"#,
+            r#"{"lang_code": "nl", "pos": "adj", "word": "faul", "senses": [{"glosses": ["lazy"]}]}"#,
+        ];
+        fs::write(
+            root_dir.join("kaikki/de-en-extract.jsonl"),
+            dump.join("\n") + "\n",
+        )
+        .unwrap();
+        fs::write(tests_dir.join("registry.json"), r#"{"de": {"en": []}}"#).unwrap();
+
+        fixture_add(add_args(&root_dir, &tests_dir, "faul", Some("adj"))).unwrap();
+        // Adding it twice does not duplicate lines
+        fixture_add(add_args(&root_dir, &tests_dir, "faul", Some("adj"))).unwrap();
+        fixture_add(add_args(&root_dir, &tests_dir, "faul", None)).unwrap();
+
+        let fixture = fs::read_to_string(tests_dir.join("kaikki/de-en-extract.jsonl")).unwrap();
+        assert_eq!(fixture, format!("{}\n{}\n", dump[0], dump[2]));
+
+        let registry: Registry =
+            serde_json::from_str(&fs::read_to_string(tests_dir.join("registry.json")).unwrap())
+                .unwrap();
+        let values = &registry["de"]["en"];
+        assert_eq!(values.len(), 2);
+        assert_eq!(
+            values[0].url,
+            "https://kaikki.org/dictionary/All%20languages%20combined/meaning/f/fa/faul.html"
+        );
+        let Json::Object(object) = &values[1].json else {
+            panic!("{:?}", values[1].json);
+        };
+        let keys: Vec<_> = object.keys().collect();
+        assert_eq!(keys, ["word", "pos", "lang_code", "senses"]);
+
+        assert!(fixture_add(add_args(&root_dir, &tests_dir, "nope", None)).is_err());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn registry_roundtrip_is_lossless() {
+        let path = Path::new("tests/registry.json");
+        let content = fs::read_to_string(path).unwrap();
+        let registry: Registry = serde_json::from_str(&content).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&registry).unwrap(), content);
+    }
+}
@@ -1,13 +1,19 @@
 pub mod cli;
+pub mod config;
 pub mod dict;
 pub mod download;
+pub mod expr;
+pub mod fixture;
 pub mod lang;
+pub mod lookup;
 pub mod models;
 pub mod path;
+pub mod progress;
+pub mod serve;
 pub mod tags;
 pub mod utils;
 
-pub use dict::make_dict;
+pub use dict::{Converter, make_dict};
 
 use fxhash::FxBuildHasher;
 use indexmap::{IndexMap, IndexSet};
@@ -0,0 +1,600 @@
+//! Query a built dictionary from the terminal.
+//!
+//! Reads back the term banks of a dictionary (either the zip or the `--save-temps` directory) and
+//! pretty prints the entries that match a word. Non-lemma entries (forms) are followed to their
+//! lemma, so that looking up an inflected word shows the lemma it comes from.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::Map;
+use crate::cli::LookupArgs;
+use crate::path::PathManager;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const CYAN: &str = "\x1b[36m";
+const MAGENTA: &str = "\x1b[35m";
+const RESET: &str = "\x1b[0m";
+
+/// A term bank row, as written by `TermBank` and `TermBankSimplified`.
+///
+/// <https://github.com/yomidevs/yomitan/blob/master/ext/data/schemas/dictionary-term-bank-v3-schema.json>
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TermRow(
+    pub String,     // term
+    pub String,     // reading
+    pub String,     // definition_tags
+    pub String,     // rules
+    pub i64,        // score
+    pub Vec<Value>, // definitions
+    pub i64,        // sequence
+    pub String,     // term_tags
+);
+
+impl TermRow {
+    pub fn term(&self) -> &str {
+        &self.0
+    }
+
+    pub fn reading(&self) -> &str {
+        &self.1
+    }
+
+    pub fn definition_tags(&self) -> &str {
+        &self.2
+    }
+
+    pub fn definitions(&self) -> &[Value] {
+        &self.5
+    }
+
+    /// Whether this row is a form that only redirects to its lemma(s).
+    pub fn is_form(&self) -> bool {
+        self.definition_tags() == "non-lemma"
+    }
+
+    /// Iterates over: uninflected, inflection tags
+    ///
+    /// Only meaningful for forms, cf. `DetailedDefinition::Inflection`.
+    pub fn inflections(&self) -> impl Iterator<Item = (&str, Vec<&str>)> {
+        self.definitions().iter().filter_map(|definition| {
+            let [uninflected, tags] = definition.as_array()?.as_slice() else {
+                return None;
+            };
+            let tags = tags.as_array()?.iter().filter_map(Value::as_str).collect();
+            Some((uninflected.as_str()?, tags))
+        })
+    }
+}
+
+/// How a lemma was reached from a form.
+#[derive(Debug)]
+pub struct Redirect<'a> {
+    pub inflected: &'a str,
+    pub tags: Vec<&'a str>,
+}
+
+#[derive(Debug)]
+pub struct LookupResult<'a> {
+    pub row: &'a TermRow,
+    pub redirect: Option<Redirect<'a>>,
+}
+
+/// An in-memory dictionary, indexed by term and reading.
+#[derive(Debug, Default)]
+pub struct LookupDictionary {
+    /// The title in `index.json`, or the file name if there is no index.
+    pub title: String,
+    rows: Vec<TermRow>,
+    index: Map<String, Vec<usize>>,
+    /// Keys of `index`, sorted for prefix search.
+    sorted_terms: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct IndexTitle {
+    title: String,
+}
+
+impl LookupDictionary {
+    /// Open a dictionary zip, or a directory containing term banks.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut dict = Self {
+            title: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+
+        if path.is_dir() {
+            let mut bank_paths: Vec<_> = fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|fname| fname.to_str())
+                        .is_some_and(is_term_bank)
+                })
+                .collect();
+            bank_paths.sort();
+
+            for bank_path in bank_paths {
+                let bank = fs::read(&bank_path)?;
+                dict.extend_from_bank(&bank)
+                    .with_context(|| format!("Error reading bank @ {}", bank_path.display()))?;
+            }
+        } else {
+            let file = File::open(path)
+                .with_context(|| format!("Dictionary not found @ {}", path.display()))?;
+            let mut zip = ZipArchive::new(file)?;
+
+            for index in 0..zip.len() {
+                let mut zip_file = zip.by_index(index)?;
+                if zip_file.name() == "index.json" {
+                    let mut index_bytes = Vec::new();
+                    zip_file.read_to_end(&mut index_bytes)?;
+                    if let Ok(index) = serde_json::from_slice::<IndexTitle>(&index_bytes) {
+                        dict.title = index.title;
+                    }
+                    continue;
+                }
+                if !is_term_bank(zip_file.name()) {
+                    continue;
+                }
+                let mut bank = Vec::new();
+                zip_file.read_to_end(&mut bank)?;
+                dict.extend_from_bank(&bank)
+                    .with_context(|| format!("Error reading bank {}", zip_file.name()))?;
+            }
+        }
+
+        dict.sorted_terms = dict.index.keys().cloned().collect();
+        dict.sorted_terms.sort_unstable();
+
+        Ok(dict)
+    }
+
+    fn extend_from_bank(&mut self, bank: &[u8]) -> Result<()> {
+        let rows: Vec<TermRow> = serde_json::from_slice(bank)?;
+        for row in rows {
+            self.push(row);
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, row: TermRow) {
+        let idx = self.rows.len();
+        self.index
+            .entry(row.term().to_string())
+            .or_default()
+            .push(idx);
+        if !row.reading().is_empty() && row.reading() != row.term() {
+            self.index
+                .entry(row.reading().to_string())
+                .or_default()
+                .push(idx);
+        }
+        self.rows.push(row);
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn rows_of(&self, term: &str) -> impl Iterator<Item = &TermRow> {
+        self.index
+            .get(term)
+            .into_iter()
+            .flatten()
+            .map(|idx| &self.rows[*idx])
+    }
+
+    /// Return up to `limit` terms (lemmas or forms) starting with `prefix`, in sorted order.
+    pub fn prefix(&self, prefix: &str, limit: usize) -> impl Iterator<Item = &str> {
+        let start = self
+            .sorted_terms
+            .partition_point(|term| term.as_str() < prefix);
+        self.sorted_terms[start..]
+            .iter()
+            .take_while(move |term| term.starts_with(prefix))
+            .take(limit)
+            .map(String::as_str)
+    }
+
+    /// Return the lemmas matching `term`, following form redirections.
+    pub fn lookup(&self, term: &str) -> Vec<LookupResult<'_>> {
+        let mut results: Vec<LookupResult> = Vec::new();
+
+        for row in self.rows_of(term) {
+            if !row.is_form() {
+                if !results.iter().any(|res| std::ptr::eq(res.row, row)) {
+                    results.push(LookupResult {
+                        row,
+                        redirect: None,
+                    });
+                }
+                continue;
+            }
+
+            // The reading of a form holds the unnormalized inflected word, if any.
+            let inflected = if row.reading().is_empty() {
+                row.term()
+            } else {
+                row.reading()
+            };
+
+            for (uninflected, tags) in row.inflections() {
+                for lemma_row in self.rows_of(uninflected).filter(|r| !r.is_form()) {
+                    if let Some(existing) = results
+                        .iter_mut()
+                        .find(|res| std::ptr::eq(res.row, lemma_row))
+                    {
+                        if let Some(redirect) = &mut existing.redirect {
+                            redirect.tags.extend(tags.iter().copied());
+                        }
+                        continue;
+                    }
+                    results.push(LookupResult {
+                        row: lemma_row,
+                        redirect: Some(Redirect {
+                            inflected,
+                            tags: tags.clone(),
+                        }),
+                    });
+                }
+            }
+        }
+
+        results
+    }
+}
+
+fn is_term_bank(fname: &str) -> bool {
+    fname.starts_with("term_bank_") && fname.ends_with(".json")
+}
+
+pub fn lookup(args: LookupArgs) -> Result<()> {
+    let word = args.word.clone();
+    let colour = !args.no_color;
+    let pm = PathManager::try_from(args)?;
+
+    let path = if pm.opts.save_temps {
+        pm.dir_temp_dict()
+    } else {
+        pm.path_dict()
+    };
+
+    let dict = LookupDictionary::open(&path)?;
+    tracing::debug!("Loaded {} entries @ {}", dict.len(), path.display());
+
+    let results = dict.lookup(&word);
+    if results.is_empty() {
+        bail!("No entries found for '{word}' @ {}", path.display());
+    }
+
+    print!("{}", render_results(&results, colour));
+
+    Ok(())
+}
+
+/// Render lookup results as (optionally coloured) text.
+pub fn render_results(results: &[LookupResult], colour: bool) -> String {
+    let mut out = String::new();
+
+    for (idx, result) in results.iter().enumerate() {
+        if idx > 0 {
+            out.push('\n');
+        }
+
+        let row = result.row;
+        out.push_str(&paint(colour, BOLD, row.term()));
+        if !row.reading().is_empty() {
+            out.push_str(&format!(" ({})", row.reading()));
+        }
+        for tag in row.definition_tags().split_whitespace() {
+            out.push(' ');
+            out.push_str(&paint(colour, CYAN, &format!("[{tag}]")));
+        }
+        out.push('\n');
+
+        if let Some(redirect) = &result.redirect {
+            let msg = format!("← {}: {}", redirect.inflected, redirect.tags.join(", "));
+            out.push_str(&paint(colour, MAGENTA, &msg));
+            out.push('\n');
+        }
+
+        let mut renderer = Renderer::new(colour);
+        for definition in row.definitions() {
+            renderer.definition(definition);
+        }
+        out.push_str(&renderer.finish());
+    }
+
+    out
+}
+
+fn paint(colour: bool, style: &str, text: &str) -> String {
+    if colour {
+        format!("{style}{text}{RESET}")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Plain text content of a structured content node.
+fn node_text(node: &Value) -> String {
+    match node {
+        Value::String(s) => s.clone(),
+        Value::Array(nodes) => nodes.iter().map(node_text).collect(),
+        Value::Object(obj) => obj.get("content").map(node_text).unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Line based renderer for structured content.
+///
+/// Block elements (div, li etc.) start a new line, inline elements (span, tags) do not.
+struct Renderer {
+    colour: bool,
+    lines: Vec<String>,
+    line: String,
+    /// Whether `line` only contains a list marker.
+    line_is_marker: bool,
+    depth: usize,
+    /// One entry per nested list: `Some(counter)` for ordered lists, `None` for unordered ones.
+    lists: Vec<Option<usize>>,
+}
+
+impl Renderer {
+    const fn new(colour: bool) -> Self {
+        Self {
+            colour,
+            lines: Vec::new(),
+            line: String::new(),
+            line_is_marker: false,
+            depth: 1,
+            lists: Vec::new(),
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        self.lines
+            .into_iter()
+            .map(|line| format!("{line}\n"))
+            .collect()
+    }
+
+    fn text(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.line.push_str(text);
+            self.line_is_marker = false;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.line_is_marker || self.line.trim().is_empty() {
+            return;
+        }
+        let indent = "  ".repeat(self.depth);
+        self.lines.push(format!(
+            "{indent}{}",
+            std::mem::take(&mut self.line).trim_end()
+        ));
+    }
+
+    fn definition(&mut self, definition: &Value) {
+        match definition {
+            Value::String(text) => {
+                self.flush();
+                self.text(text);
+                self.flush();
+            }
+            Value::Object(obj)
+                if obj.get("type").and_then(Value::as_str) == Some("structured-content") =>
+            {
+                if let Some(content) = obj.get("content") {
+                    self.node(content);
+                }
+                self.flush();
+            }
+            _ => (),
+        }
+    }
+
+    fn node(&mut self, node: &Value) {
+        match node {
+            Value::String(text) => self.text(text),
+            Value::Array(nodes) => nodes.iter().for_each(|node| self.node(node)),
+            Value::Object(_) => self.element(node),
+            _ => (),
+        }
+    }
+
+    fn block(&mut self, content: &Value) {
+        self.flush();
+        self.node(content);
+        self.flush();
+    }
+
+    fn element(&mut self, node: &Value) {
+        let tag = node.get("tag").and_then(Value::as_str).unwrap_or_default();
+        let class = node
+            .get("data")
+            .and_then(|data| data.get("content"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let content = node.get("content").unwrap_or(&Value::Null);
+
+        match (tag, class) {
+            // Backlinks to wiktionary/kaikki are noise in a terminal
+            ("a", _) => (),
+            (_, "tag") => {
+                let tag = paint(self.colour, CYAN, &format!("[{}]", node_text(content)));
+                self.text(&tag);
+                self.text(" ");
+            }
+            ("summary", _) => {
+                self.flush();
+                let summary = paint(self.colour, BOLD, &format!("▸ {}", node_text(content)));
+                self.text(&summary);
+                self.flush();
+            }
+            ("details", _) => {
+                self.flush();
+                let children = match content {
+                    Value::Array(children) => children.as_slice(),
+                    other => std::slice::from_ref(other),
+                };
+                for child in children {
+                    let is_summary = child.get("tag").and_then(Value::as_str) == Some("summary");
+                    if is_summary {
+                        self.node(child);
+                    } else {
+                        self.depth += 1;
+                        self.block(child);
+                        self.depth -= 1;
+                    }
+                }
+            }
+            ("ol" | "ul", _) => {
+                self.flush();
+                self.lists.push((tag == "ol").then_some(0));
+                self.depth += 1;
+                self.node(content);
+                self.depth -= 1;
+                self.lists.pop();
+                self.flush();
+            }
+            ("li", _) => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(counter)) => {
+                        *counter += 1;
+                        format!("{counter}. ")
+                    }
+                    Some(None) => "- ".to_string(),
+                    None => String::new(),
+                };
+                self.line.push_str(&marker);
+                self.line_is_marker = !marker.is_empty();
+                self.node(content);
+                self.flush();
+                self.line_is_marker = false;
+                self.line.clear();
+            }
+            (_, "example-sentence-a") => {
+                self.flush();
+                let example = paint(self.colour, ITALIC, &node_text(content));
+                self.text(&example);
+                self.flush();
+            }
+            (_, "example-sentence-b" | "example-sentence-c") => {
+                self.flush();
+                let extra = paint(self.colour, DIM, &node_text(content));
+                self.text(&extra);
+                self.flush();
+            }
+            ("span", _) | (_, "tags") => self.node(content),
+            _ => self.block(content),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir;
+
+    fn de_en_snapshot() -> LookupDictionary {
+        LookupDictionary::open(Path::new("tests/dict/de/en/temp-main/dict")).unwrap()
+    }
+
+    #[test]
+    fn lookup_lemma() {
+        let dict = de_en_snapshot();
+        let results = dict.lookup("pflegen");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].row.term(), "pflegen");
+        assert!(results[0].redirect.is_none());
+    }
+
+    #[test]
+    fn lookup_follows_form_redirects() {
+        let dict = de_en_snapshot();
+        let results = dict.lookup("gepflogen");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].row.term(), "pflegen");
+
+        let redirect = results[0].redirect.as_ref().unwrap();
+        assert_eq!(redirect.inflected, "gepflogen");
+        assert_eq!(redirect.tags, ["past participle"]);
+    }
+
+    #[test]
+    fn open_zip() {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let path = temp_dir("lookup").with_extension("zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("index.json", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"{}").unwrap();
+        zip.start_file("term_bank_1.json", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(br#"[["run","","v","v",0,["to move quickly"],0,""],["ran","","non-lemma","",0,[["run",["past"]]],0,""]]"#)
+            .unwrap();
+        zip.finish().unwrap();
+
+        let dict = LookupDictionary::open(&path).unwrap();
+        assert_eq!(dict.len(), 2);
+        assert_eq!(dict.title, path.file_stem().unwrap().to_string_lossy());
+        let rendered = render_results(&dict.lookup("ran"), false);
+        assert_eq!(rendered, "run [v]\n← ran: past\n  to move quickly\n");
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn prefix_search() {
+        let dict = de_en_snapshot();
+        let terms: Vec<_> = dict.prefix("pfl", 3).collect();
+        assert_eq!(terms.len(), 3);
+        assert!(terms.iter().all(|term| term.starts_with("pfl")));
+        assert!(terms.is_sorted());
+        assert_eq!(dict.prefix("__never__", 3).count(), 0);
+    }
+
+    #[test]
+    fn lookup_unknown_word() {
+        let dict = de_en_snapshot();
+        assert!(dict.lookup("__never__").is_empty());
+    }
+
+    #[test]
+    fn render_without_colour() {
+        let dict = de_en_snapshot();
+        let rendered = render_results(&dict.lookup("gepflogen"), false);
+        let mut lines = rendered.lines();
+
+        assert_eq!(lines.next(), Some("pflegen [v]"));
+        assert_eq!(lines.next(), Some("← gepflogen: past participle"));
+        assert_eq!(lines.next(), Some("  ▸ Grammar"));
+        assert!(rendered.contains("1. [vt] [med] providing care or service for someone/something"));
+        assert!(rendered.contains("- to nurse; to care for someone in poor health"));
+        assert!(!rendered.contains('\x1b'));
+    }
+}
@@ -4,12 +4,16 @@ use tracing_subscriber::fmt::format::FmtSpan;
 
 use wty::{
     cli::{Cli, Command, LangSpecs},
+    config::config,
     dict::{
-        DGlossary, DGlossaryExtended, DIpa, DIpaMerged, DMain, find_or_download_jsonl, make_dict,
-        release::release,
+        DGlossary, DGlossaryExtended, DIpa, DIpaMerged, DMain, build::build, filter::filter,
+        find_or_download_jsonl, hyphenation::hyphenation, make_dict, release::release,
     },
+    fixture::fixture,
     lang::{Edition, Lang},
+    lookup::lookup,
     path::PathManager,
+    serve::serve,
 };
 
 fn init_logger(verbose: bool) {
@@ -36,19 +40,24 @@ fn run(cmd: Command) -> Result<()> {
     tracing::trace!("{:#?}", cmd);
 
     match cmd {
-        Command::Main(args) => make_dict(DMain, args),
-        Command::Glossary(args) => make_dict(DGlossary, args),
-        Command::GlossaryExtended(args) => make_dict(DGlossaryExtended, args),
-        Command::Ipa(args) => make_dict(DIpa, args),
-        Command::IpaMerged(args) => make_dict(DIpaMerged, args),
+        Command::Main(args) => make_dict(DMain, args).map(drop),
+        Command::Glossary(args) => make_dict(DGlossary, args).map(drop),
+        Command::GlossaryExtended(args) => make_dict(DGlossaryExtended, args).map(drop),
+        Command::Ipa(args) => make_dict(DIpa, args).map(drop),
+        Command::IpaMerged(args) => make_dict(DIpaMerged, args).map(drop),
+        Command::Custom(args) => make_dict(args.spec.clone(), args).map(drop),
+        Command::Hyphenation(args) => hyphenation(args),
         Command::Download(args) => {
             if args.options.stream {
                 anyhow::bail!("`wty download` does not support `--stream`.");
             }
+            if args.options.input.is_some() {
+                anyhow::bail!("`wty download` does not support `--input`.");
+            }
 
             // NOTE: uses MainArgs, so it expects two language codes.
             let langs: LangSpecs = args.langs.clone().try_into()?;
-            let source: Lang = langs.source.try_into().unwrap();
+            let source: Lang = langs.source;
             let edition: Edition = langs.edition.try_into().unwrap();
             let pm = PathManager::try_from(args)?;
 
@@ -57,6 +66,7 @@ fn run(cmd: Command) -> Result<()> {
             let _ = find_or_download_jsonl(edition, Some(source), &pm)?;
             Ok(())
         }
+        Command::Filter(args) => filter(args),
         Command::Iso(args) => {
             if args.edition {
                 println!("{}", Lang::help_editions());
@@ -66,11 +76,16 @@ fn run(cmd: Command) -> Result<()> {
             Ok(())
         }
         Command::Release(args) => release(args),
+        Command::Build(args) => build(args),
+        Command::Lookup(args) => lookup(args),
+        Command::Serve(args) => serve(args),
+        Command::Fixture(args) => fixture(args),
+        Command::Config(args) => config(args),
     }
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::parse_cli();
+    let cli = Cli::parse_cli()?;
     init_logger(cli.verbose);
     run(cli.command)
 }
@@ -10,15 +10,21 @@
 //! Example (el):
 //! <https://github.com/tatuylonen/wiktextract/blob/master/src/wiktextract/extractor/el/models.py>
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
 
-use crate::tags::{BLACKLISTED_FORM_TAGS, IDENTITY_FORM_TAGS};
+use crate::{
+    lang::Lang,
+    tags::{BLACKLISTED_FORM_TAGS, IDENTITY_FORM_TAGS},
+};
 
 // In case we ever decide to narrow them
 pub type Tag = String;
 pub type Pos = String;
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[serde(default)]
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
 pub struct WordEntry {
@@ -32,30 +38,173 @@ pub struct WordEntry {
     // Not pub because unstable: use the getter method
     etymology_text: String, // En, El editions still use this
     etymology_texts: Vec<String>,
+    pub etymology_templates: Vec<EtymologyTemplate>,
 
     pub sounds: Vec<Sound>,
 
+    // Not pub because unstable: use the getter method
+    hyphenation: LegacyHyphenation, // Older dumps, El edition
+    hyphenations: Vec<Hyphenation>,
+
     pub senses: Vec<Sense>,
 
     pub tags: Vec<Tag>,
     pub topics: Vec<Tag>,
+    pub categories: Vec<String>,
 
     pub forms: Vec<Form>,
     pub form_of: Vec<AltForm>,
     pub alt_of: Vec<AltForm>,
 
     pub translations: Vec<Translation>, // used in glossary
+
+    // Entry level relations: they may still point to a sense through `Linkage::sense`
+    pub synonyms: Vec<Linkage>,
+    pub antonyms: Vec<Linkage>,
+    pub hypernyms: Vec<Linkage>,
+    pub hyponyms: Vec<Linkage>,
+    pub related: Vec<Linkage>,
+
+    pub derived: Vec<Linkage>,
+    pub compounds: Vec<Linkage>, // not every edition
+
+    pub descendants: Vec<Descendant>,
 }
 
 // To be avoided as much as possible: sort of internal field.
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[serde(default)]
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
 pub struct HeadTemplate {
     pub expansion: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+// Templates of the etymology section, as in {{inh|de|gmh|vuhs}}.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
+pub struct EtymologyTemplate {
+    pub name: String,
+    pub args: BTreeMap<String, String>,
+    pub expansion: String,
+}
+
+// Descendants are nested: rkyv needs explicit bounds for recursive types.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
+#[rkyv(serialize_bounds(
+    __S: rkyv::ser::Writer + rkyv::ser::Allocator,
+    __S::Error: rkyv::rancor::Source,
+))]
+#[rkyv(deserialize_bounds(__D::Error: rkyv::rancor::Source))]
+#[rkyv(bytecheck(bounds(
+    __C: rkyv::validation::ArchiveContext,
+    __C::Error: rkyv::rancor::Source,
+)))]
+pub struct Descendant {
+    pub lang: String,
+    pub lang_code: String,
+    pub word: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub roman: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Tag>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub raw_tags: Vec<Tag>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[rkyv(omit_bounds)]
+    pub descendants: Vec<Descendant>,
+}
+
+/// How a word came from the previous one in its etymology.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EtymologyRelation {
+    Inherited,
+    Borrowed,
+    Derived,
+}
+
+impl EtymologyRelation {
+    /// Relation of an etymology template, if it is one of the ancestry: cognates etc. are not.
+    fn from_template(name: &str) -> Option<Self> {
+        match name.trim_end_matches('+') {
+            "inh" | "inherited" => Some(Self::Inherited),
+            "bor" | "borrowed" | "lbor" | "slbor" | "obor" | "ubor" | "lbor-lite" => {
+                Some(Self::Borrowed)
+            }
+            "der" | "derived" | "uder" => Some(Self::Derived),
+            _ => None,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Inherited => "inherited",
+            Self::Borrowed => "borrowed",
+            Self::Derived => "derived",
+        }
+    }
+}
+
+/// One step of the ancestry of a word: "inherited from Middle High German vuhs".
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EtymologyStep {
+    pub relation: EtymologyRelation,
+    pub lang: String,
+    pub lang_code: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub word: String,
+}
+
+impl EtymologyStep {
+    fn from_template(template: &EtymologyTemplate) -> Option<Self> {
+        let relation = EtymologyRelation::from_template(&template.name)?;
+        let lang_code = template.args.get("2")?.trim();
+        if lang_code.is_empty() {
+            return None;
+        }
+        // The displayed form (4) if any, else the linked word (3). "-" means that it is unknown.
+        let word: String = ["4", "3"]
+            .into_iter()
+            .filter_map(|idx| template.args.get(idx))
+            .map(|word| word.trim())
+            .find(|word| !word.is_empty())
+            .filter(|&word| word != "-")
+            .unwrap_or_default()
+            .nfc()
+            .collect();
+
+        // The expansion is "Middle High German vuhs", or "Inherited from ..." for inh+ etc.
+        let expansion: String = template.expansion.trim().nfc().collect();
+        let expanded_lang = if word.is_empty() {
+            Some(expansion.as_str())
+        } else {
+            expansion.find(&word).map(|idx| &expansion[..idx])
+        }
+        .map(|lang| {
+            lang.rsplit_once(" from ")
+                .map_or(lang, |(_, lang)| lang)
+                .trim()
+        })
+        .filter(|lang| !lang.is_empty());
+        let lang = expanded_lang.unwrap_or_else(|| {
+            lang_code
+                .parse::<Lang>()
+                .map_or(lang_code, |lang| lang.long())
+        });
+
+        Some(Self {
+            relation,
+            lang: lang.to_string(),
+            lang_code: lang_code.to_string(),
+            word,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[serde(default)]
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
 pub struct Sound {
@@ -66,7 +215,53 @@ pub struct Sound {
     // pub other: String, // [ja]
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
+pub struct Hyphenation {
+    pub parts: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Tag>,
+}
+
+// Either one hyphenated string ("κόρ‐φος"), or several alternatives (["vi‧vir"]).
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
+enum LegacyHyphenation {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for LegacyHyphenation {
+    fn default() -> Self {
+        Self::Many(Vec::new())
+    }
+}
+
+// Lenient: anything else (null, an object...) is no hyphenation, rather than a malformed line.
+impl<'de> Deserialize<'de> for LegacyHyphenation {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Lenient {
+            One(String),
+            Many(Vec<String>),
+            Other(serde::de::IgnoredAny),
+        }
+
+        Ok(match Lenient::deserialize(deserializer)? {
+            Lenient::One(hyphenated) => Self::One(hyphenated),
+            Lenient::Many(hyphenated) => Self::Many(hyphenated),
+            Lenient::Other(_) => Self::default(),
+        })
+    }
+}
+
+/// Characters that some editions keep inside hyphenation parts, to mark the breaks.
+const HYPHENATION_POINTS: [char; 2] = ['‧', '‐'];
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[serde(default)]
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
 pub struct Sense {
@@ -79,6 +274,13 @@ pub struct Sense {
     pub alt_of: Vec<AltForm>,
     pub tags: Vec<Tag>,
     pub topics: Vec<Tag>,
+    pub categories: Vec<String>,
+
+    pub synonyms: Vec<Linkage>,
+    pub antonyms: Vec<Linkage>,
+    pub hypernyms: Vec<Linkage>,
+    pub hyponyms: Vec<Linkage>,
+    pub related: Vec<Linkage>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
@@ -93,14 +295,26 @@ pub struct Example {
     pub reference: String, // Reference of a quotation example
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[serde(default)]
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
 pub struct AltForm {
     pub word: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+// A word in one of the synonyms, antonyms, hypernyms, hyponyms, related, derived... arrays.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
+pub struct Linkage {
+    pub word: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub sense: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[serde(default)]
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
 pub struct Form {
@@ -110,7 +324,7 @@ pub struct Form {
     pub ruby: Vec<(String, String)>, // [ja] (kanji, hiragana)
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[serde(default)]
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
 pub struct Translation {
@@ -208,6 +422,47 @@ impl WordEntry {
             .filter(move |translation| !translation.word.is_empty())
     }
 
+    /// Return the ancestry of the word, from its `etymology_templates`, most recent first.
+    pub fn etymology_chain(&self) -> Vec<EtymologyStep> {
+        self.etymology_templates
+            .iter()
+            .filter_map(EtymologyStep::from_template)
+            .collect()
+    }
+
+    /// Return the syllables of every distinct hyphenation of the word: [["ge", "gen", "über"]].
+    ///
+    /// Parts are split again at the break points that some editions keep: "vi‧vir".
+    pub fn hyphenations(&self) -> Vec<Vec<&str>> {
+        let alternatives: Vec<Vec<&str>> = if self.hyphenations.is_empty() {
+            match &self.hyphenation {
+                LegacyHyphenation::One(hyphenated) => vec![vec![hyphenated.as_str()]],
+                LegacyHyphenation::Many(hyphenated) => {
+                    hyphenated.iter().map(|h| vec![h.as_str()]).collect()
+                }
+            }
+        } else {
+            self.hyphenations
+                .iter()
+                .map(|h| h.parts.iter().map(String::as_str).collect())
+                .collect()
+        };
+
+        let mut syllabified: Vec<Vec<&str>> = Vec::new();
+        for parts in alternatives {
+            let syllables: Vec<&str> = parts
+                .into_iter()
+                .flat_map(|part| part.split(HYPHENATION_POINTS))
+                .map(str::trim)
+                .filter(|syllable| !syllable.is_empty())
+                .collect();
+            if !syllables.is_empty() && !syllabified.contains(&syllables) {
+                syllabified.push(syllables);
+            }
+        }
+        syllabified
+    }
+
     pub fn etymology_texts(&self) -> Option<Vec<&str>> {
         if !self.etymology_texts.is_empty() {
             Some(self.etymology_texts.iter().map(String::as_ref).collect())
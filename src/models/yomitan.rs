@@ -122,6 +122,7 @@ pub enum Node {
     Array(Vec<Node>),          // 32
     Generic(Box<GenericNode>), // 16
     Backlink(BacklinkContent), // 32
+    Query(QueryLink),          // 24
 }
 
 impl Node {
@@ -232,6 +233,39 @@ impl Serialize for BacklinkContent {
     }
 }
 
+/// A link that searches its word in yomitan itself.
+#[derive(Debug, Clone)]
+pub struct QueryLink(pub String);
+
+impl QueryLink {
+    /// Yomitan treats hrefs starting with '?' as a search query.
+    pub fn href(&self) -> String {
+        let mut query = String::with_capacity(self.0.len());
+        for ch in self.0.chars() {
+            match ch {
+                '%' | '&' | '#' | '+' | '=' | '?' => query.push_str(&format!("%{:02X}", ch as u8)),
+                _ => query.push(ch),
+            }
+        }
+        format!("?query={query}&wildcards=off")
+    }
+}
+
+// Custom Serialize for the same reason as BacklinkContent: only the word is stored
+impl Serialize for QueryLink {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("QueryLink", 3)?;
+        state.serialize_field("tag", "a")?;
+        state.serialize_field("href", &self.href())?;
+        state.serialize_field("content", &self.0)?;
+        state.end()
+    }
+}
+
 // https://github.com/MarvNC/yomichan-dict-builder/blob/master/src/types/yomitan/termbank.ts
 // @ DetailedDefinition
 #[derive(Debug, Serialize, Clone)]
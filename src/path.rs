@@ -13,6 +13,8 @@ pub enum DictionaryType {
     GlossaryExtended,
     Ipa,
     IpaMerged,
+    /// Made from a spec, cf. `DCustom`. Holds the name of the spec.
+    Custom(&'static str),
 }
 
 /// Used only for the temporary files folder (`dir_temp`).
@@ -24,6 +26,7 @@ impl fmt::Display for DictionaryType {
             Self::GlossaryExtended => "glossary-ext",
             Self::Ipa => "ipa",
             Self::IpaMerged => "ipa-merged",
+            Self::Custom(name) => return write!(f, "custom-{name}"),
         })
     }
 }
@@ -33,7 +36,7 @@ impl fmt::Display for DictionaryType {
 // cf. download::DatasetKind
 #[derive(Debug, PartialEq, Eq)]
 pub enum PathKind {
-    /// Path to a filtered jsonl. For tests only.
+    /// Path to a filtered jsonl. Made by `wty filter`, or by hand for tests.
     Filtered,
     /// Path to a unfiltered jsonl
     Unfiltered,
@@ -165,6 +168,17 @@ impl PathManager {
         DatasetPaths::new(edition, lang, self.dir_kaik())
     }
 
+    /// Per-language jsonlines made by `wty filter` with `--filter`, `--reject` or `--where`.
+    ///
+    /// Unlike `PathKind::Filtered`, builds never pick it up: it lacks the entries that were
+    /// rejected.
+    ///
+    /// Example: `data/kaikki/de-en-extract-selected.jsonl`
+    pub fn path_selected(&self, edition: Edition, lang: Lang) -> PathBuf {
+        self.dir_kaik()
+            .join(format!("{lang}-{edition}-extract-selected.jsonl"))
+    }
+
     /// `data/dict/source/target/temp/tidy/source-target-lemmas.json`
     ///
     /// Example: `data/dict/el/el/temp/tidy/el-el-lemmas.json`
@@ -214,6 +228,7 @@ impl PathManager {
             GlossaryExtended => format!("{dict_name}-{edition}-{source}-{target}-gloss"),
             Ipa => format!("{dict_name}-{source}-{target}-ipa"),
             IpaMerged => format!("{dict_name}-{target}-ipa"),
+            Custom(name) => format!("{dict_name}-{source}-{target}-{name}"),
         };
 
         if self.opts.experimental {
@@ -232,25 +247,50 @@ impl PathManager {
             .join(format!("{}.zip", self.dict_name_expanded()))
     }
 
+    /// Hyphenation exceptions made by `wty hyphenation`.
+    ///
+    /// Example: `data/dict/el/en/dictionary_name-el-en-hyphenation.tex`
+    pub fn path_hyphenation(&self, extension: &str) -> PathBuf {
+        self.dir_dict().join(format!(
+            "{}-hyphenation.{extension}",
+            self.dict_name_expanded()
+        ))
+    }
+
+    /// Runs of the intermediate representation, cf. `--memory-limit`.
+    ///
+    /// Example: `data/dict/el/el/temp/spill`
+    pub fn dir_spill(&self) -> PathBuf {
+        self.dir_temp().join("spill")
+    }
+
     /// Example: `data/dict/el/el/temp/diagnostics`
     pub fn dir_diagnostics(&self) -> PathBuf {
         self.dir_temp().join("diagnostics")
     }
+
+    /// Malformed jsonlines skipped thanks to `--max-errors`.
+    ///
+    /// Example: `data/dict/el/el/temp/diagnostics/quarantine.jsonl`
+    pub fn path_quarantine(&self) -> PathBuf {
+        self.dir_diagnostics().join("quarantine.jsonl")
+    }
+
+    /// Malformed jsonlines of an edition read for several source languages at once, that can not
+    /// be told apart by language.
+    ///
+    /// Example: `data/dict/diagnostics/quarantine-el.jsonl`
+    pub fn path_quarantine_edition(&self) -> PathBuf {
+        self.dir_dicts()
+            .join("diagnostics")
+            .join(format!("quarantine-{}.jsonl", self.langs.edition))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::{SystemTime, UNIX_EPOCH};
-
     use super::*;
-
-    fn temp_root(label: &str) -> PathBuf {
-        let unique = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        std::env::temp_dir().join(format!("wty-{label}-{unique}"))
-    }
+    use crate::utils::temp_dir;
 
     fn path_manager(stream: bool, root_dir: PathBuf) -> PathManager {
         PathManager {
@@ -271,7 +311,7 @@ mod tests {
 
     #[test]
     fn setup_dirs_skips_kaikki_dir_when_streaming() {
-        let root_dir = temp_root("stream");
+        let root_dir = temp_dir("stream");
         let pm = path_manager(true, root_dir.clone());
 
         pm.setup_dirs().unwrap();
@@ -284,7 +324,7 @@ mod tests {
 
     #[test]
     fn setup_dirs_keeps_kaikki_dir_in_cache_mode() {
-        let root_dir = temp_root("cache");
+        let root_dir = temp_dir("cache");
         let pm = path_manager(false, root_dir.clone());
 
         pm.setup_dirs().unwrap();
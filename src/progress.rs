@@ -0,0 +1,302 @@
+//! Byte-based progress reporting for long reads (datasets, downloads).
+//!
+//! A [`Progress`] counts the bytes that go through the readers it wraps and, when stderr is a
+//! terminal, draws a line with percent, throughput and ETA. Several bars can be alive at the same
+//! time (parallel `release`): they share one block of lines that is redrawn in place. Messages
+//! printed meanwhile go through [`println`] and [`print_status`], so that they show above the
+//! block instead of being drawn over.
+//!
+//! When stderr is not a terminal (CI, redirections), the same line is logged every
+//! `LOG_INTERVAL` instead.
+
+use std::io::{IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::utils::human_size;
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Bars currently on screen, and how many lines the last redraw took.
+struct Screen {
+    bars: Vec<Arc<Progress>>,
+    drawn: usize,
+    last_draw: Option<Instant>,
+    /// Whether stdout ends with a status line, that the next one replaces (cf. `print_status`).
+    status: bool,
+}
+
+static SCREEN: Mutex<Screen> = Mutex::new(Screen {
+    bars: Vec::new(),
+    drawn: 0,
+    last_draw: None,
+    status: false,
+});
+
+fn screen() -> MutexGuard<'static, Screen> {
+    SCREEN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Screen {
+    /// Finish the status line, if any, so that what follows starts on a line of its own.
+    fn end_status(&mut self) {
+        if self.status {
+            println!();
+            let _ = std::io::stdout().flush();
+            self.status = false;
+        }
+    }
+
+    /// Erase the bars, leaving the cursor where the block started.
+    fn clear(&mut self) {
+        if self.drawn > 0 {
+            eprint!("\x1b[{}A\x1b[J", self.drawn);
+            self.drawn = 0;
+        }
+    }
+}
+
+/// Print `msg` as a line of stdout, above the bars, that are drawn again below it.
+pub fn println(msg: &str) {
+    let mut screen = screen();
+    screen.end_status();
+    screen.clear();
+    println!("{msg}");
+    let _ = std::io::stdout().flush();
+    if !screen.bars.is_empty() {
+        redraw(&mut screen, None);
+    }
+}
+
+/// Print `msg` on the status line of stdout, in place of the previous one. While bars are on
+/// screen, it is printed as a line of its own instead.
+pub fn print_status(msg: &str) {
+    let mut screen = screen();
+    if screen.bars.is_empty() {
+        print!("\r\x1b[K{msg}");
+        let _ = std::io::stdout().flush();
+        screen.status = true;
+    } else {
+        drop(screen);
+        println(msg);
+    }
+}
+
+/// Finish the status line of `print_status`, if any.
+pub fn end_status() {
+    screen().end_status();
+}
+
+#[derive(Debug)]
+pub struct Progress {
+    label: String,
+    /// Expected number of bytes, if known (file size, Content-Length).
+    total: Option<u64>,
+    read: AtomicU64,
+    start: Instant,
+}
+
+impl Progress {
+    fn new(label: impl Into<String>, total: Option<u64>) -> Self {
+        Self {
+            label: label.into(),
+            total,
+            read: AtomicU64::new(0),
+            start: Instant::now(),
+        }
+    }
+
+    /// Wrap `inner` so that every byte read from it is counted.
+    ///
+    /// If `visible`, the bar is drawn until the returned reader is dropped, or logged if stderr
+    /// is not a terminal.
+    pub fn reader<R: Read>(
+        label: impl Into<String>,
+        total: Option<u64>,
+        visible: bool,
+        inner: R,
+    ) -> ProgressReader<R> {
+        let progress = Arc::new(Self::new(label, total));
+        let display = match (visible, std::io::stderr().is_terminal()) {
+            (false, _) => Display::Hidden,
+            (true, true) => {
+                screen().bars.push(Arc::clone(&progress));
+                Display::Bar
+            }
+            (true, false) => Display::Log {
+                last: Instant::now(),
+            },
+        };
+        ProgressReader {
+            inner,
+            progress,
+            display,
+        }
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    fn line(&self) -> String {
+        self.line_at(self.start.elapsed())
+    }
+
+    fn line_at(&self, elapsed: Duration) -> String {
+        let read = self.bytes_read();
+        let secs = elapsed.as_secs_f64();
+        let rate = if secs > 0.0 { read as f64 / secs } else { 0.0 };
+        let throughput = format!("{}/s", human_size(rate));
+
+        match self.total {
+            Some(total) if total > 0 => {
+                let ratio = (read as f64 / total as f64).min(1.0);
+                let eta = if rate > 0.0 {
+                    let left = total.saturating_sub(read) as f64 / rate;
+                    format_duration(Duration::from_secs_f64(left))
+                } else {
+                    "--:--".to_string()
+                };
+                format!(
+                    "{} {:>5.1}% {} / {} {throughput} ETA {eta}",
+                    self.label,
+                    ratio * 100.0,
+                    human_size(read as f64),
+                    human_size(total as f64),
+                )
+            }
+            _ => format!("{} {} {throughput}", self.label, human_size(read as f64)),
+        }
+    }
+}
+
+/// `mm:ss`, or `h:mm:ss` past the hour.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m:02}:{s:02}")
+    }
+}
+
+/// Redraw every bar, `finished` first so that it stays on screen once it leaves the block.
+fn redraw(screen: &mut Screen, finished: Option<&Arc<Progress>>) {
+    use std::fmt::Write;
+
+    screen.end_status();
+
+    let mut out = String::new();
+    if screen.drawn > 0 {
+        let _ = write!(out, "\x1b[{}A", screen.drawn);
+    }
+    if let Some(bar) = finished {
+        let _ = writeln!(out, "\x1b[2K{}", bar.line());
+        screen.bars.retain(|other| !Arc::ptr_eq(other, bar));
+    }
+    for bar in &screen.bars {
+        let _ = writeln!(out, "\x1b[2K{}", bar.line());
+    }
+    eprint!("{out}");
+    screen.drawn = screen.bars.len();
+    screen.last_draw = Some(Instant::now());
+}
+
+enum Display {
+    Hidden,
+    Bar,
+    /// A plain line every `LOG_INTERVAL`, the last one at `last`.
+    Log {
+        last: Instant,
+    },
+}
+
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Arc<Progress>,
+    display: Display,
+}
+
+impl<R> ProgressReader<R> {
+    pub fn progress(&self) -> &Arc<Progress> {
+        &self.progress
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.progress.read.fetch_add(n as u64, Ordering::Relaxed);
+
+        match &mut self.display {
+            Display::Hidden => (),
+            Display::Bar => {
+                if let Ok(mut screen) = SCREEN.try_lock()
+                    && screen
+                        .last_draw
+                        .is_none_or(|last| last.elapsed() >= REDRAW_INTERVAL)
+                {
+                    redraw(&mut screen, None);
+                }
+            }
+            Display::Log { last } => {
+                if last.elapsed() >= LOG_INTERVAL {
+                    eprintln!("{}", self.progress.line());
+                    *last = Instant::now();
+                }
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+impl<R> Drop for ProgressReader<R> {
+    fn drop(&mut self) {
+        match self.display {
+            Display::Hidden => (),
+            Display::Bar => redraw(&mut screen(), Some(&self.progress)),
+            Display::Log { .. } => eprintln!("{}", self.progress.line()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_bytes() {
+        let data = vec![b'x'; 10_000];
+        let mut reader = Progress::reader("test", Some(10_000), false, data.as_slice());
+        let mut sink = Vec::new();
+        reader.read_to_end(&mut sink).unwrap();
+        assert_eq!(reader.progress().bytes_read(), 10_000);
+    }
+
+    #[test]
+    fn line_shows_percent_and_eta() {
+        let progress = Progress::new("en", Some(4096));
+        progress.read.store(1024, Ordering::Relaxed);
+        assert_eq!(
+            progress.line_at(Duration::from_secs(1)),
+            "en  25.0% 1.00 KB / 4.00 KB 1.00 KB/s ETA 00:03"
+        );
+
+        let unknown = Progress::new("en", None);
+        unknown.read.store(2048, Ordering::Relaxed);
+        assert_eq!(
+            unknown.line_at(Duration::from_secs(2)),
+            "en 2.00 KB 1.00 KB/s"
+        );
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(Duration::from_secs(59)), "00:59");
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1:02:05");
+    }
+}
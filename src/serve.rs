@@ -0,0 +1,346 @@
+//! Local HTTP lookup server over built dictionaries.
+//!
+//! A deliberately small HTTP/1.1 server: it only answers GET requests, one per connection, and
+//! is meant to be bound to localhost. Endpoints:
+//!
+//! - `/lookup?term=gepflogen` lookup with form redirections (add `&format=text` for a rendering)
+//! - `/prefix?q=pfl` autocomplete (add `&limit=n` to change the default of 20, up to 1000)
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{Receiver, sync_channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::{Value, json};
+
+use crate::cli::ServeArgs;
+use crate::lookup::{LookupDictionary, render_results};
+
+const DEFAULT_PREFIX_LIMIT: usize = 20;
+const MAX_PREFIX_LIMIT: usize = 1000;
+/// How much of a request is read: its request line and headers. Bodies are never read.
+const MAX_HEADER_BYTES: u64 = 8 * 1024;
+/// Number of connections handled at once. Further connections wait in the listen backlog.
+const WORKERS: usize = 8;
+/// How long a connection may stall on reading the request or writing the response.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn serve(args: ServeArgs) -> Result<()> {
+    let mut dicts = Vec::with_capacity(args.paths.len());
+    for path in &args.paths {
+        let dict = LookupDictionary::open(path)?;
+        println!("Loaded {} ({} entries)", dict.title, dict.len());
+        dicts.push(dict);
+    }
+
+    let listener = TcpListener::bind((args.host.as_str(), args.port))?;
+    println!("Serving @ http://{}", listener.local_addr()?);
+
+    serve_listener(&listener, Arc::new(dicts));
+
+    Ok(())
+}
+
+/// Accept connections forever, handing them to a fixed pool of `WORKERS` threads.
+pub fn serve_listener(listener: &TcpListener, dicts: Arc<Vec<LookupDictionary>>) {
+    let (tx, rx) = sync_channel::<TcpStream>(WORKERS);
+    let rx = Arc::new(Mutex::new(rx));
+    for _ in 0..WORKERS {
+        let rx = Arc::clone(&rx);
+        let dicts = Arc::clone(&dicts);
+        thread::spawn(move || worker(&rx, &dicts));
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                tracing::warn!("Failed to accept connection: {err}");
+                continue;
+            }
+        };
+        // Blocks while every worker is busy and the queue is full.
+        if tx.send(stream).is_err() {
+            break;
+        }
+    }
+}
+
+fn worker(rx: &Mutex<Receiver<TcpStream>>, dicts: &[LookupDictionary]) {
+    loop {
+        // The guard is dropped before handling, so that other workers can pick up connections.
+        let Ok(stream) = rx.lock().expect("poisoned lock").recv() else {
+            return;
+        };
+        if let Err(err) = handle_connection(stream, dicts) {
+            tracing::warn!("Failed to handle connection: {err}");
+        }
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, dicts: &[LookupDictionary]) -> Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut reader = BufReader::new((&stream).take(MAX_HEADER_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain the headers: we don't use them.
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 || header == "\r\n" || header == "\n" {
+            break;
+        }
+    }
+
+    tracing::debug!("{}", request_line.trim_end());
+    let (status, body) = if request_line.ends_with('\n') {
+        respond(&request_line, dicts)
+    } else {
+        (
+            "431 Request Header Fields Too Large",
+            json!({ "error": "request too large" }),
+        )
+    };
+
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Return the status line and the JSON body for a request line.
+fn respond(request_line: &str, dicts: &[LookupDictionary]) -> (&'static str, Value) {
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return ("400 Bad Request", json!({ "error": "malformed request" }));
+    };
+    if method != "GET" {
+        return (
+            "405 Method Not Allowed",
+            json!({ "error": "only GET is supported" }),
+        );
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = parse_query(query);
+    let param = |key: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+
+    match path {
+        "/lookup" => {
+            let Some(term) = param("term") else {
+                return (
+                    "400 Bad Request",
+                    json!({ "error": "missing parameter 'term'" }),
+                );
+            };
+            let as_text = param("format") == Some("text");
+            ("200 OK", lookup_json(dicts, term, as_text))
+        }
+        "/prefix" => {
+            let Some(prefix) = param("q") else {
+                return (
+                    "400 Bad Request",
+                    json!({ "error": "missing parameter 'q'" }),
+                );
+            };
+            let limit = param("limit")
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(DEFAULT_PREFIX_LIMIT)
+                .min(MAX_PREFIX_LIMIT);
+            ("200 OK", prefix_json(dicts, prefix, limit))
+        }
+        _ => ("404 Not Found", json!({ "error": "not found" })),
+    }
+}
+
+fn lookup_json(dicts: &[LookupDictionary], term: &str, as_text: bool) -> Value {
+    let mut results = Vec::new();
+
+    for dict in dicts {
+        for result in dict.lookup(term) {
+            let redirect = result.redirect.as_ref().map(|redirect| {
+                json!({
+                    "inflected": redirect.inflected,
+                    "tags": redirect.tags,
+                })
+            });
+            let mut value = json!({
+                "dictionary": dict.title,
+                "redirect": redirect,
+            });
+            if as_text {
+                value["text"] = json!(render_results(std::slice::from_ref(&result), false));
+            } else {
+                value["entry"] = json!(result.row);
+            }
+            results.push(value);
+        }
+    }
+
+    json!({ "term": term, "results": results })
+}
+
+fn prefix_json(dicts: &[LookupDictionary], prefix: &str, limit: usize) -> Value {
+    let mut terms: Vec<&str> = dicts
+        .iter()
+        .flat_map(|dict| dict.prefix(prefix, limit))
+        .collect();
+    terms.sort_unstable();
+    terms.dedup();
+    terms.truncate(limit);
+
+    json!({ "q": prefix, "terms": terms })
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+/// Decode `application/x-www-form-urlencoded` text. Invalid escapes are kept as is.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'+' => decoded.push(b' '),
+            b'%' => match bytes.get(idx + 1..idx + 3).and_then(hex_byte) {
+                Some(byte) => {
+                    decoded.push(byte);
+                    idx += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_byte(hex: &[u8]) -> Option<u8> {
+    u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+    use std::path::Path;
+
+    fn get(addr: &str, target: &str) -> (String, Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {target} HTTP/1.1\r\nHost: {addr}\r\n\r\n").unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.lines().next().unwrap().to_string();
+
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn start_server() -> String {
+        let dict = LookupDictionary::open(Path::new("tests/dict/de/en/temp-main/dict")).unwrap();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve_listener(&listener, Arc::new(vec![dict])));
+        addr
+    }
+
+    #[test]
+    fn lookup_endpoint() {
+        let addr = start_server();
+
+        let (status, body) = get(&addr, "/lookup?term=gepflogen");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["entry"][0], "pflegen");
+        assert_eq!(results[0]["redirect"]["tags"][0], "past participle");
+
+        let (_, body) = get(&addr, "/lookup?term=gepflogen&format=text");
+        let text = body["results"][0]["text"].as_str().unwrap();
+        assert!(text.starts_with("pflegen [v]\n← gepflogen: past participle\n"));
+    }
+
+    #[test]
+    fn prefix_endpoint() {
+        let addr = start_server();
+
+        let (status, body) = get(&addr, "/prefix?q=pfl&limit=2");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let terms = body["terms"].as_array().unwrap();
+        assert_eq!(terms.len(), 2);
+        assert!(terms.iter().all(|t| t.as_str().unwrap().starts_with("pfl")));
+    }
+
+    #[test]
+    fn bad_requests() {
+        let addr = start_server();
+
+        assert_eq!(get(&addr, "/lookup").0, "HTTP/1.1 400 Bad Request");
+        assert_eq!(get(&addr, "/nope?term=a").0, "HTTP/1.1 404 Not Found");
+    }
+
+    #[test]
+    fn oversized_request_is_rejected() {
+        let addr = start_server();
+
+        // A request line that never ends: only `MAX_HEADER_BYTES` of it are read
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let request = format!("GET /lookup?term={}", "a".repeat(MAX_HEADER_BYTES as usize));
+        stream
+            .write_all(&request.as_bytes()[..MAX_HEADER_BYTES as usize])
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 "), "{response}");
+    }
+
+    #[test]
+    fn idle_connection_does_not_block_others() {
+        let addr = start_server();
+
+        // Connects but never sends a request: it holds one worker until the read times out.
+        let _idle = TcpStream::connect(&addr).unwrap();
+
+        assert_eq!(get(&addr, "/lookup?term=pflegen").0, "HTTP/1.1 200 OK");
+    }
+
+    #[test]
+    fn decode_query() {
+        assert_eq!(percent_decode("gepflogen"), "gepflogen");
+        assert_eq!(percent_decode("a+b"), "a b");
+        assert_eq!(percent_decode("%CF%88%CE%B7"), "ψη");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+}
@@ -1,8 +1,9 @@
 pub mod tags_constants;
 
-use std::cmp::Ordering;
+use std::{cmp::Ordering, sync::LazyLock};
 
 use indexmap::IndexMap;
+use regex::Regex;
 use tags_constants::{POSES, TAG_BANK, TAG_ORDER};
 
 use crate::models::kaikki::Tag;
@@ -203,6 +204,45 @@ pub fn find_tag_in_bank(tag: &str) -> Option<TagInformation> {
     })
 }
 
+/// Families of categories that `--category-tags` shows as tags, with the (long) tag they map to.
+///
+/// Only English category names: other editions have their own.
+static CATEGORY_TAGS: LazyLock<Vec<(Regex, &str)>> = LazyLock::new(|| {
+    [
+        (r"\birregular verbs\b", "irregular"),
+        (r"\bstrong verbs\b", "strong verb"),
+        (r"\bweak verbs\b", "weak verb"),
+        (
+            r"\b(first|second|third|fourth) conjugation verbs\b",
+            "$1 conjugation",
+        ),
+        (
+            r"\b(first|second|third|fourth|fifth) declension nouns\b",
+            "$1 declension",
+        ),
+        (
+            r"\bnouns in the (first|second|third|fourth|fifth) declension\b",
+            "$1 declension",
+        ),
+    ]
+    .into_iter()
+    .map(|(re, tag)| (Regex::new(re).unwrap(), tag))
+    .collect()
+});
+
+/// Map a category to a tag of `TAG_BANK`, if it belongs to one of `CATEGORY_TAGS` families.
+///
+/// "German class 4 strong verbs" >> "strong verb"
+pub fn find_category_tag(category: &str) -> Option<Tag> {
+    CATEGORY_TAGS.iter().find_map(|(re, tag)| {
+        re.captures(category).map(|caps| {
+            let mut expanded = String::new();
+            caps.expand(tag, &mut expanded);
+            expanded
+        })
+    })
+}
+
 /// Find the short form in POSES (`tag_bank_terms.json` with category "partOfSpeech").
 fn find_short_pos(pos: &str) -> Option<&'static str> {
     POSES
@@ -356,4 +396,32 @@ mod tests {
         assert!(tags_are_subset("foo bar", "bar foo baz"));
         assert!(!tags_are_subset("foo qux", "foo bar baz"));
     }
+
+    #[test]
+    fn category_tags_are_in_the_bank() {
+        for (category, expected) in [
+            ("German class 4 strong verbs", Some("strong verb")),
+            ("English irregular verbs", Some("irregular")),
+            (
+                "Latin first conjugation verbs with perfect in -āv-",
+                Some("first conjugation"),
+            ),
+            (
+                "Latin feminine nouns in the first declension",
+                Some("first declension"),
+            ),
+            (
+                "Latin third conjugation verbs with irregular perfect",
+                Some("third conjugation"),
+            ),
+            ("German weak nouns", None),
+            ("en:Medicine", None),
+        ] {
+            let tag = find_category_tag(category);
+            assert_eq!(tag.as_deref(), expected, "{category}");
+            if let Some(tag) = tag {
+                assert!(find_tag_in_bank(&tag).is_some(), "{tag}");
+            }
+        }
+    }
 }
@@ -81,7 +81,7 @@ pub const TAG_ORDER: [&str; 76] = [
 ];
 
 #[rustfmt::skip]
-pub const TAG_BANK: [(&str, &str, i32, &[&str], i32); 242] = [
+pub const TAG_BANK: [(&str, &str, i32, &[&str], i32); 253] = [
     ("non-lemma", "", 10, &["non-lemma"], -10),
     ("r", "partOfSpeech", -1, &["root"], 1),
     ("pos-r", "partOfSpeech", -1, &["pos-root"], 1),
@@ -357,6 +357,17 @@ pub const TAG_BANK: [(&str, &str, i32, &[&str], i32); 242] = [
     ("reltv", "", 0, &["relative"], 0),
     ("abbv", "", 0, &["abbreviation"], 0),
     ("irreg", "", 0, &["irregular"], 0),
+    ("strong", "", 0, &["strong verb"], 0),
+    ("weak", "", 0, &["weak verb"], 0),
+    ("conj-1", "", 0, &["first conjugation"], 0),
+    ("conj-2", "", 0, &["second conjugation"], 0),
+    ("conj-3", "", 0, &["third conjugation"], 0),
+    ("conj-4", "", 0, &["fourth conjugation"], 0),
+    ("decl-1", "", 0, &["first declension"], 0),
+    ("decl-2", "", 0, &["second declension"], 0),
+    ("decl-3", "", 0, &["third declension"], 0),
+    ("decl-4", "", 0, &["fourth declension"], 0),
+    ("decl-5", "", 0, &["fifth declension"], 0),
     ("indecl", "", 0, &["indeclinable"], 0),
     ("not-comp", "", 0, &["not comparable", "not-comparable"], 0),
     ("init", "", 0, &["initialism"], 0),
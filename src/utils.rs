@@ -3,6 +3,7 @@ use std::fs;
 use std::path::Path;
 
 use crate::lang::{Edition, Lang};
+use crate::progress;
 
 pub const SKIP_C: &str = "⏭";
 pub const CHECK_C: &str = "✓";
@@ -51,11 +52,12 @@ fn pretty_msg_at_path(msg: &str, path: &Path) -> String {
 }
 
 pub fn pretty_println_at_path(msg: &str, path: &Path) {
-    println!("{}", pretty_msg_at_path(msg, path));
+    progress::println(&pretty_msg_at_path(msg, path));
 }
 
+/// Print on the status line, in place of the previous one (cf. `progress::print_status`).
 pub fn pretty_print_at_path(msg: &str, path: &Path) {
-    print!("{}", pretty_msg_at_path(msg, path));
+    progress::print_status(&pretty_msg_at_path(msg, path));
 }
 
 pub fn skip_because_file_exists(skipped: &str, path: &Path) {
@@ -100,3 +102,19 @@ pub fn link_kaikki(edition: Edition, source: Lang, word: &str) -> String {
         "https://kaikki.org/{dictionary}/{localized_source}/meaning/{first}/{first_two}/{word}.html"
     )
 }
+
+/// A path under the system temp dir that no other test, in this process or another, uses.
+///
+/// Anything left there by an earlier run with the same pid is removed.
+#[cfg(test)]
+pub(crate) fn temp_dir(label: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("wty-{label}-{}-{n}", std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    let _ = fs::remove_file(&path);
+    path
+}
@@ -14,8 +14,11 @@ use tracing_subscriber::EnvFilter;
 use tracing_subscriber::fmt::format::FmtSpan;
 use zip::ZipArchive;
 
-use wty::cli::{DictName, GlossaryArgs, GlossaryLangs, IpaArgs, MainArgs, MainLangs, Options};
-use wty::dict::{DGlossary, DIpa, DMain};
+use wty::cli::{DictName, MainArgs, MainLangs, Options};
+use wty::dict::DMain;
+use wty::fixture::{
+    langs_in_testsuite, snapshot_cases, snapshot_glossaries, snapshot_ipa, snapshot_main_args,
+};
 use wty::lang::{Edition, Lang};
 use wty::make_dict;
 use wty::path::PathManager;
@@ -49,16 +52,6 @@ fn cleanup(root: &Path) -> bool {
     is_empty
 }
 
-fn fixture_options(fixture_dir: &Path) -> Options {
-    Options {
-        save_temps: true,
-        pretty: true,
-        experimental: false,
-        root_dir: fixture_dir.to_path_buf(),
-        ..Default::default()
-    }
-}
-
 fn output_options(root_dir: &Path, stream: bool) -> Options {
     Options {
         pretty: true,
@@ -68,14 +61,6 @@ fn output_options(root_dir: &Path, stream: bool) -> Options {
     }
 }
 
-fn fixture_main_args(source: Lang, target: Edition, fixture_dir: &Path) -> MainArgs {
-    MainArgs {
-        langs: MainLangs { source, target },
-        dict_name: DictName::default(),
-        options: fixture_options(fixture_dir),
-    }
-}
-
 fn output_main_args(source: Lang, target: Edition, root_dir: &Path, stream: bool) -> MainArgs {
     MainArgs {
         langs: MainLangs { source, target },
@@ -84,22 +69,6 @@ fn output_main_args(source: Lang, target: Edition, root_dir: &Path, stream: bool
     }
 }
 
-fn fixture_ipa_args(source: Lang, target: Edition, fixture_dir: &Path) -> IpaArgs {
-    IpaArgs {
-        langs: MainLangs { source, target },
-        dict_name: DictName::default(),
-        options: fixture_options(fixture_dir),
-    }
-}
-
-fn fixture_glossary_args(source: Edition, target: Lang, fixture_dir: &Path) -> GlossaryArgs {
-    GlossaryArgs {
-        langs: GlossaryLangs { source, target },
-        dict_name: DictName::default(),
-        options: fixture_options(fixture_dir),
-    }
-}
-
 fn setup_tracing_test() {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn"));
 
@@ -196,9 +165,6 @@ fn snapshot() {
     setup_tracing_test();
 
     let fixture_dir = PathBuf::from("tests");
-    // have to hardcode this since we have not initialized args
-    let fixture_input_dir = fixture_dir.join("kaikki");
-
     // Nuke the output dir to prevent pollution
     // It has the disadvantage of massive diffs if we failfast.
     //
@@ -206,30 +172,8 @@ fn snapshot() {
     // Don't crash if there is no output dir. It may happen if we nuke it manually
     // let _ = fs::remove_dir_all(fixture_output_dir);
 
-    let mut cases = Vec::new();
-    let mut langs_in_testsuite = Vec::new();
-
-    // iterdir and search for source-target-extract.jsonl files
-    for entry in fs::read_dir(&fixture_input_dir).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
-
-        if let Some(fname) = path.file_name().and_then(|f| f.to_str())
-            && let Some(base) = fname.strip_suffix("-extract.jsonl")
-            && let Some((source, target)) = base.split_once('-')
-        {
-            let src = source.parse::<Lang>().unwrap();
-            let tar = target.parse::<Lang>().unwrap();
-            cases.push((src, tar));
-
-            if !langs_in_testsuite.contains(&src) {
-                langs_in_testsuite.push(src);
-            }
-            if !langs_in_testsuite.contains(&tar) {
-                langs_in_testsuite.push(tar);
-            }
-        }
-    }
+    let cases = snapshot_cases(&fixture_dir).unwrap();
+    let langs_in_testsuite = langs_in_testsuite(&cases);
 
     tracing::debug!("Found {} cases: {cases:?}", cases.len());
 
@@ -239,7 +183,7 @@ fn snapshot() {
         let Result::Ok(target) = (*target).try_into() else {
             continue; // skip if target is not edition
         };
-        let args = fixture_main_args(*source, target, &fixture_dir);
+        let args = snapshot_main_args(*source, target, &fixture_dir);
 
         if let Err(e) = shapshot_main(args) {
             panic!("({source}): {e}");
@@ -256,13 +200,7 @@ fn snapshot() {
             continue; // skip if source is not edition
         };
 
-        for possible_target in &langs_in_testsuite {
-            if Lang::from(source) == *possible_target {
-                continue;
-            }
-            let args = fixture_glossary_args(source, *possible_target, &fixture_dir);
-            make_dict(DGlossary, args).unwrap();
-        }
+        snapshot_glossaries(source, &langs_in_testsuite, &fixture_dir).unwrap();
     }
 
     // ipa
@@ -270,8 +208,7 @@ fn snapshot() {
         let Result::Ok(target) = (*target).try_into() else {
             continue; // skip if target is not edition
         };
-        let args = fixture_ipa_args(*source, target, &fixture_dir);
-        make_dict(DIpa, args).unwrap();
+        snapshot_ipa(*source, target, &fixture_dir).unwrap();
     }
 
     cleanup(&fixture_dir.join("dict"));
@@ -321,6 +258,46 @@ fn streamed_main_matches_cached_main_without_creating_cache_files() {
     let _ = fs::remove_dir_all(streamed_root);
 }
 
+#[test]
+fn compressed_inputs_match_plain_input() {
+    let fixture = PathBuf::from("tests/kaikki/ja-en-extract.jsonl");
+    let plain = fs::read(&fixture).unwrap();
+    let root = temp_root("compressed-input");
+    fs::create_dir_all(&root).unwrap();
+
+    let inputs = [
+        ("jsonl", plain.clone()),
+        ("jsonl.gz", gzip_fixture(&fixture)),
+        ("jsonl.zst", zstd::encode_all(plain.as_slice(), 0).unwrap()),
+        ("jsonl.xz", {
+            let mut encoder = liblzma::write::XzEncoder::new(Vec::new(), 6);
+            encoder.write_all(&plain).unwrap();
+            encoder.finish().unwrap()
+        }),
+    ];
+
+    let mut contents = Vec::new();
+    for (extension, bytes) in inputs {
+        let input = root.join(format!("input.{extension}"));
+        fs::write(&input, bytes).unwrap();
+
+        let mut args = output_main_args(Lang::Ja, Edition::En, &root.join(extension), false);
+        args.options.input = Some(input);
+        args.options.quiet = true;
+        let pm = PathManager::try_from(args.clone()).unwrap();
+        make_dict(DMain, args).unwrap();
+
+        contents.push(zip_contents(&pm.path_dict()));
+    }
+
+    assert!(!contents[0].is_empty());
+    for other in &contents[1..] {
+        assert_eq!(&contents[0], other);
+    }
+
+    let _ = fs::remove_dir_all(root);
+}
+
 #[test]
 fn streamed_main_peak_rss_stays_well_below_dataset_size() {
     static ENV_LOCK: OnceLock<Mutex<()>> = OnceLock::new();